@@ -0,0 +1,85 @@
+// Vorbis decoder written in Rust
+//
+// Copyright (c) 2026 est31 <MTest31@outlook.com>
+// and contributors. All rights reserved.
+// Licensed under MIT license, or Apache 2 license,
+// at your option. Please see the LICENSE file
+// attached to this source distribution for details.
+
+// This file contains a single test only, as the
+// allocation counter is shared by the entire binary.
+
+extern crate lewton;
+extern crate ogg;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use lewton::audio::{DecodeState, PreviousWindowRight, get_packet_blockflag,
+	read_audio_packet_generic};
+use lewton::inside_ogg::read_headers;
+use ogg::PacketReader;
+
+struct CountingAlloc;
+
+static ALLOCATIONS :AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+	unsafe fn alloc(&self, layout :Layout) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+		System.alloc(layout)
+	}
+	unsafe fn dealloc(&self, ptr :*mut u8, layout :Layout) {
+		System.dealloc(ptr, layout)
+	}
+	unsafe fn realloc(&self, ptr :*mut u8, layout :Layout, new_size :usize) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+		System.realloc(ptr, layout, new_size)
+	}
+}
+
+#[global_allocator]
+static GLOBAL :CountingAlloc = CountingAlloc;
+
+#[test]
+fn test_decode_state_no_alloc() {
+	let data = include_bytes!("../../../test-data/sine-stereo.ogg");
+	let mut rdr = PacketReader::new(Cursor::new(&data[..]));
+	let ((ident, _, setup), _) = read_headers(&mut rdr).unwrap();
+	let mut packets = Vec::new();
+	while let Some(pck) = rdr.read_packet().unwrap() {
+		packets.push(pck.data);
+	}
+	let mut pwr = PreviousWindowRight::new();
+	let expected = packets.iter()
+		.map(|pck| read_audio_packet_generic(&ident, &setup, pck, &mut pwr).unwrap())
+		.collect::<Vec<Vec<Vec<f32>>>>();
+
+	// Warm up until both block sizes have been seen,
+	// as some buffers depend on the setup header.
+	let mut state = DecodeState::with_capacity(&ident);
+	let mut seen = [false; 2];
+	let mut idx = 0;
+	while !(seen[0] && seen[1]) {
+		let decoded = state.decode_packet(&ident, &setup, &packets[idx]).unwrap();
+		assert_eq!(decoded, &expected[idx][..]);
+		seen[get_packet_blockflag(&setup, &packets[idx]).unwrap() as usize] = true;
+		idx += 1;
+	}
+	let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+	for (pck, expected_pck) in packets[idx ..].iter().zip(expected[idx ..].iter()) {
+		let decoded = state.decode_packet(&ident, &setup, pck).unwrap();
+		assert_eq!(decoded, &expected_pck[..]);
+	}
+	assert_eq!(ALLOCATIONS.load(Ordering::SeqCst) - allocations, 0);
+
+	// After a reset, the entire stream is decoded without allocations
+	state.reset();
+	assert!(!state.is_warmed_up());
+	let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+	for (pck, expected_pck) in packets.iter().zip(expected.iter()) {
+		let decoded = state.decode_packet(&ident, &setup, pck).unwrap();
+		assert_eq!(decoded, &expected_pck[..]);
+	}
+	assert_eq!(ALLOCATIONS.load(Ordering::SeqCst) - allocations, 0);
+}
@@ -47,3 +47,19 @@ fn test_okay_fuzzed() {
 	ensure_okay!("bug-42-sample016.ogg");
 	ensure_okay!("bug-42-sample029.ogg");
 }
+
+#[test]
+fn test_fuzz_decode_fuzzed() {
+	println!();
+	let asset_defs = cmp::get_fuzzed_asset_defs();
+	test_assets::download_test_files(&asset_defs,
+		"test-assets", true).unwrap();
+	println!();
+
+	// None of the fuzzed files may make fuzz_decode panic
+	for asset_def in asset_defs.iter() {
+		let path = format!("test-assets/{}", asset_def.filename);
+		let data = std::fs::read(path).unwrap();
+		lewton::fuzz::fuzz_decode(&data);
+	}
+}
@@ -0,0 +1,274 @@
+// Vorbis decoder written in Rust
+//
+// Copyright (c) 2026 est31 <MTest31@outlook.com>
+// and contributors. All rights reserved.
+// Licensed under MIT license, or Apache 2 license,
+// at your option. Please see the LICENSE file
+// attached to this source distribution for details.
+
+extern crate test_assets;
+extern crate cmp;
+extern crate lewton;
+extern crate ogg;
+
+use std::fs::File;
+use std::io::Cursor;
+use lewton::inside_ogg::{OggStreamReader, read_ident_header_from};
+use lewton::header::{SetupStage, StrictnessConfig, read_header_ident,
+	read_header_setup_with_progress, read_headers_from_xiph_extradata};
+use ogg::{PacketReader, PacketWriter, PacketWriteEndInfo};
+
+fn open_asset(name :&str) -> OggStreamReader<File> {
+	let f = File::open(format!("test-assets/{}", name)).unwrap();
+	OggStreamReader::new(f).unwrap()
+}
+
+#[test]
+fn test_mapping_info() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let rdr = open_asset("square-stereo.ogg");
+	assert_eq!(rdr.ident_hdr.audio_channels, 2);
+	let mapping = rdr.setup_hdr.mapping(0).unwrap();
+	assert_eq!(mapping.coupling_steps(), 1);
+	for ch in 0 .. 2 {
+		let submap = mapping.channel_to_submap(ch).unwrap() as usize;
+		assert!(submap < mapping.submap_count());
+		assert!(mapping.submap_floor(submap).is_some());
+		assert!(mapping.submap_residue(submap).is_some());
+	}
+	assert_eq!(mapping.channel_to_submap(2), None);
+	assert!(rdr.setup_hdr.mapping(rdr.setup_hdr.mapping_count()).is_none());
+}
+
+#[test]
+fn test_strict_packets_after_setup() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Rewrite the file so that the first audio
+	// packet shares the page of the setup header.
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut rdr = PacketReader::new(f);
+	let mut buf = Vec::new();
+	{
+		let mut wtr = PacketWriter::new(&mut buf);
+		let mut idx = 0;
+		while let Some(pck) = rdr.read_packet().unwrap() {
+			let inf = if pck.last_in_stream() {
+				PacketWriteEndInfo::EndStream
+			} else if idx == 0 || idx == 3 {
+				PacketWriteEndInfo::EndPage
+			} else {
+				PacketWriteEndInfo::NormalPacket
+			};
+			let absgp = pck.absgp_page();
+			let serial = pck.stream_serial();
+			wtr.write_packet(pck.data.into_boxed_slice(), serial, inf, absgp).unwrap();
+			idx += 1;
+		}
+	}
+
+	let mut lenient = OggStreamReader::new(Cursor::new(&buf)).unwrap();
+	assert!(lenient.read_dec_packet().unwrap().is_some());
+	let strict = OggStreamReader::new_with_config(Cursor::new(&buf),
+		StrictnessConfig::strict_spec());
+	assert!(strict.is_err());
+
+	// Unmodified files are accepted in strict mode
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut strict = OggStreamReader::new_with_config(f,
+		StrictnessConfig::strict_spec()).unwrap();
+	assert!(strict.read_dec_packet().unwrap().is_some());
+}
+
+#[test]
+fn test_uses_coupling() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_1(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let rdr = open_asset("square-stereo.ogg");
+	assert!(rdr.setup_hdr.uses_coupling());
+	assert!(rdr.setup_hdr.coupling_step_count() > 0);
+
+	let rdr = open_asset("48k-mono.ogg");
+	assert!(!rdr.setup_hdr.uses_coupling());
+	assert_eq!(rdr.setup_hdr.coupling_step_count(), 0);
+}
+
+#[test]
+fn test_read_ident_header_from() {
+	println!();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_1(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let f = File::open("test-assets/48k-mono.ogg").unwrap();
+	let mut rdr = PacketReader::new(f);
+	let (ident_hdr, serial) = read_ident_header_from(&mut rdr).unwrap();
+	assert_eq!(ident_hdr.audio_channels, 1);
+	assert_eq!(ident_hdr.audio_sample_rate, 48000);
+
+	let full = open_asset("48k-mono.ogg");
+	assert_eq!(full.stream_serial(), serial);
+	assert_eq!(full.ident_hdr.blocksize_0, ident_hdr.blocksize_0);
+	assert_eq!(full.ident_hdr.blocksize_1, ident_hdr.blocksize_1);
+
+	// The next packet is the comment header
+	let pck = rdr.read_packet_expected().unwrap();
+	assert_eq!(pck.data[0], 3);
+}
+
+#[test]
+fn test_setup_fingerprint() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_1(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let rdr_a = open_asset("square-stereo.ogg");
+	let rdr_b = open_asset("square-stereo.ogg");
+	assert_eq!(rdr_a.setup_hdr.fingerprint(), rdr_b.setup_hdr.fingerprint());
+
+	let rdr_c = open_asset("48k-mono.ogg");
+	assert_ne!(rdr_a.setup_hdr.fingerprint(), rdr_c.setup_hdr.fingerprint());
+}
+
+#[test]
+fn test_setup_spanning_pages() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	fn count_pages(data :&[u8]) -> usize {
+		data.windows(4).filter(|w| *w == b"OggS").count()
+	}
+
+	// Rewrite the file so that the setup header spans multiple pages.
+	// Data after the framing bit of the setup header is ignored,
+	// so we can grow the packet by appending zeros to it.
+	// A single page can hold at most 255 * 255 bytes.
+	let orig = ::std::fs::read("test-assets/square-stereo.ogg").unwrap();
+	let mut rdr = PacketReader::new(Cursor::new(&orig));
+	let mut buf = Vec::new();
+	let mut setup_len = 0;
+	{
+		let mut wtr = PacketWriter::new(&mut buf);
+		let mut idx = 0;
+		while let Some(pck) = rdr.read_packet().unwrap() {
+			let inf = if pck.last_in_stream() {
+				PacketWriteEndInfo::EndStream
+			} else if pck.last_in_page() {
+				PacketWriteEndInfo::EndPage
+			} else {
+				PacketWriteEndInfo::NormalPacket
+			};
+			let absgp = pck.absgp_page();
+			let serial = pck.stream_serial();
+			let mut data = pck.data;
+			if idx == 2 {
+				data.resize(data.len() + 3 * 255 * 255, 0);
+				setup_len = data.len();
+			}
+			wtr.write_packet(data.into_boxed_slice(), serial, inf, absgp).unwrap();
+			idx += 1;
+		}
+	}
+	assert!(count_pages(&buf) >= count_pages(&orig) + 3);
+
+	// The ogg layer reassembles the setup header
+	let mut pck_rdr = PacketReader::new(Cursor::new(&buf));
+	pck_rdr.read_packet().unwrap().unwrap();
+	pck_rdr.read_packet().unwrap().unwrap();
+	assert_eq!(pck_rdr.read_packet().unwrap().unwrap().data.len(), setup_len);
+
+	let mut rdr_orig = OggStreamReader::new(Cursor::new(&orig)).unwrap();
+	let mut rdr = OggStreamReader::new(Cursor::new(&buf)).unwrap();
+	assert_eq!(rdr.setup_hdr.fingerprint(), rdr_orig.setup_hdr.fingerprint());
+	while let Some(pck) = rdr_orig.read_dec_packet_itl().unwrap() {
+		assert_eq!(rdr.read_dec_packet_itl().unwrap().unwrap(), pck);
+	}
+	assert_eq!(rdr.read_dec_packet_itl().unwrap(), None);
+}
+
+#[test]
+fn test_read_header_setup_with_progress() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let f = File::open("test-assets/6ch-moving-sine.ogg").unwrap();
+	let mut rdr = PacketReader::new(f);
+	let ident = read_header_ident(&rdr.read_packet_expected().unwrap().data).unwrap();
+	rdr.read_packet_expected().unwrap();
+	let setup_pck = rdr.read_packet_expected().unwrap().data;
+	// The codebook count directly follows the packet type and "vorbis"
+	let codebook_count = setup_pck[7] as usize + 1;
+	assert!(codebook_count > 1);
+
+	let mut reports = Vec::new();
+	read_header_setup_with_progress(&setup_pck, ident.audio_channels,
+		(ident.blocksize_0, ident.blocksize_1), |p| reports.push(p)).unwrap();
+
+	let codebook_reports = reports.iter()
+		.filter(|p| p.stage == SetupStage::Codebooks)
+		.collect::<Vec<_>>();
+	assert_eq!(codebook_reports.len(), codebook_count);
+	for (i, p) in codebook_reports.iter().enumerate() {
+		assert_eq!(p.done, i + 1);
+		assert_eq!(p.total, codebook_count);
+	}
+	// Reports come in the order of the header
+	assert_eq!(reports[0].stage, SetupStage::Codebooks);
+	assert_eq!(reports.last().unwrap().stage, SetupStage::Modes);
+	let last = reports.last().unwrap();
+	assert_eq!(last.done, last.total);
+}
+
+#[test]
+fn test_read_headers_from_xiph_extradata() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Build the CodecPrivate element a Matroska muxer would write
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	let pcks = (0 .. 3)
+		.map(|_| pck_rdr.read_packet_expected().unwrap().data)
+		.collect::<Vec<_>>();
+	let mut extradata = vec![2];
+	for pck in pcks[.. 2].iter() {
+		extradata.extend(::std::iter::repeat(255).take(pck.len() / 255));
+		extradata.push((pck.len() % 255) as u8);
+	}
+	for pck in pcks.iter() {
+		extradata.extend_from_slice(pck);
+	}
+
+	let (ident, comment, setup) = read_headers_from_xiph_extradata(&extradata).unwrap();
+	let rdr = open_asset("square-stereo.ogg");
+	assert_eq!(ident.audio_channels, rdr.ident_hdr.audio_channels);
+	assert_eq!(ident.audio_sample_rate, rdr.ident_hdr.audio_sample_rate);
+	assert_eq!(comment, rdr.comment_hdr);
+	assert_eq!(setup.fingerprint(), rdr.setup_hdr.fingerprint());
+
+	// Malformed extradata gives errors instead of panics
+	for len in 0 .. extradata.len() - 1 {
+		assert!(read_headers_from_xiph_extradata(&extradata[.. len]).is_err());
+	}
+}
@@ -0,0 +1,1709 @@
+// Vorbis decoder written in Rust
+//
+// Copyright (c) 2026 est31 <MTest31@outlook.com>
+// and contributors. All rights reserved.
+// Licensed under MIT license, or Apache 2 license,
+// at your option. Please see the LICENSE file
+// attached to this source distribution for details.
+
+extern crate test_assets;
+extern crate cmp;
+extern crate lewton;
+extern crate ogg;
+
+use std::fs::File;
+use std::io::Cursor;
+use std::time::Duration;
+use ogg::{PacketReader, PacketWriter, PacketWriteEndInfo};
+use lewton::inside_ogg::{DecodeStats, OggStreamReader, read_headers};
+use lewton::VorbisError;
+use lewton::audio::{AudioReadError, BlockTransition, DecodeQuality, DecodeScratch, PacketDecoder,
+	PreviousWindowRight, RawVorbisDecoder, StageTimes, VorbisDecoder, decode_packet_catch_unwind,
+	get_decoded_sample_count, get_packet_blockflag, read_audio_packet, read_audio_packet_floor_only,
+	read_audio_packet_generic, read_audio_packet_generic_opts, read_audio_packet_into,
+	read_audio_packet_with_spectra, read_audio_spectra};
+use lewton::source::LewtonDecoder;
+use lewton::samples::{downmix_to_stereo, BoxedSamples, InterleavedSamples, NullSamples,
+	PcmByteFormat, Sample};
+
+fn open_asset(name :&str) -> OggStreamReader<File> {
+	let f = File::open(format!("test-assets/{}", name)).unwrap();
+	OggStreamReader::new(f).unwrap()
+}
+
+#[test]
+fn test_read_dec_packet_full() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr_full = open_asset("square-stereo.ogg");
+	let mut rdr = open_asset("square-stereo.ogg");
+	let bs_1 = rdr.ident_hdr.blocksize_1;
+	let mut pck_count = 0;
+	loop {
+		let full = rdr_full.read_dec_packet_full().unwrap();
+		let samples = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap();
+		let (full, samples) = match (full, samples) {
+			(Some(f), Some(s)) => (f, s),
+			(None, None) => break,
+			_ => panic!("Packet count mismatch"),
+		};
+		assert_eq!(full.samples, samples);
+		assert_eq!(full.spectra.len(), rdr.ident_hdr.audio_channels as usize);
+		for spectrum in full.spectra.iter() {
+			let len = spectrum.len();
+			assert!(len == (1 << bs_1) / 2 || len == (1 << rdr.ident_hdr.blocksize_0) / 2);
+		}
+		assert_eq!(rdr_full.get_last_absgp(), rdr.get_last_absgp());
+		pck_count += 1;
+	}
+	assert!(pck_count > 0);
+}
+
+fn decode_all(rdr :&mut OggStreamReader<File>) -> Vec<Vec<f32>> {
+	let mut res = vec![Vec::new(); rdr.ident_hdr.audio_channels as usize];
+	while let Some(pck) = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+		for (ch, samples) in res.iter_mut().zip(pck.into_iter()) {
+			ch.extend_from_slice(&samples);
+		}
+	}
+	res
+}
+
+#[test]
+fn test_set_position() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let all = decode_all(&mut rdr);
+	let end_absgp = rdr.get_last_absgp().unwrap();
+
+	// Skip over more than one packet
+	let to_skip = (1 << rdr.ident_hdr.blocksize_1) + 100;
+	let mut rdr = open_asset("square-stereo.ogg");
+	rdr.set_position(0, to_skip as u64);
+	let skipped = decode_all(&mut rdr);
+	assert_eq!(rdr.get_last_absgp(), Some(end_absgp));
+	for (ch_all, ch_skipped) in all.iter().zip(skipped.iter()) {
+		assert_eq!(&ch_all[to_skip ..], &ch_skipped[..]);
+	}
+
+	// Until the end of the first page is reached,
+	// the absgp is counted from the passed position.
+	let mut rdr = open_asset("square-stereo.ogg");
+	rdr.set_position(1000, 0);
+	let mut pos = 1000;
+	for _ in 0 .. 3 {
+		let pck = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap().unwrap();
+		pos += pck[0].len() as u64;
+		assert_eq!(rdr.get_last_absgp(), Some(pos));
+	}
+}
+
+#[test]
+fn test_read_budgeted() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let channels = rdr.ident_hdr.audio_channels as usize;
+	let mut pcks = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		pcks.push(pck);
+	}
+
+	// The packet budget stops decoding
+	let mut rdr = open_asset("square-stereo.ogg");
+	let budgeted = rdr.read_budgeted(3, ::std::u64::MAX).unwrap();
+	assert_eq!(budgeted, pcks[.. 3].concat());
+	// The reader continues where it stopped
+	assert_eq!(rdr.read_dec_packet_itl().unwrap().unwrap(), pcks[3]);
+
+	// The sample budget stops decoding
+	let max_samples = 5000;
+	let mut rdr = open_asset("square-stereo.ogg");
+	let budgeted = rdr.read_budgeted(::std::usize::MAX, max_samples).unwrap();
+	let mut expected = Vec::new();
+	let mut pck_iter = pcks.iter();
+	while expected.len() < max_samples as usize * channels {
+		expected.extend_from_slice(pck_iter.next().unwrap());
+	}
+	assert_eq!(budgeted, expected);
+	assert_eq!(&rdr.read_dec_packet_itl().unwrap().unwrap(), pck_iter.next().unwrap());
+
+	// At the end of the stream, an empty Vec is returned
+	let _ = rdr.read_budgeted(::std::usize::MAX, ::std::u64::MAX).unwrap();
+	assert!(rdr.read_budgeted(10, 10).unwrap().is_empty());
+}
+
+#[test]
+fn test_as_packet_reader_mut() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Multiplex another logical stream with the vorbis stream.
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut rdr = PacketReader::new(f);
+	let mut buf = Vec::new();
+	let mut vorbis_serial = 0;
+	{
+		let mut wtr = PacketWriter::new(&mut buf);
+		let mut idx = 0;
+		while let Some(pck) = rdr.read_packet().unwrap() {
+			let inf = if pck.last_in_stream() {
+				PacketWriteEndInfo::EndStream
+			} else if pck.last_in_page() {
+				PacketWriteEndInfo::EndPage
+			} else {
+				PacketWriteEndInfo::NormalPacket
+			};
+			let absgp = pck.absgp_page();
+			vorbis_serial = pck.stream_serial();
+			let other_serial = vorbis_serial.wrapping_add(1);
+			wtr.write_packet(pck.data.into_boxed_slice(), vorbis_serial, inf, absgp).unwrap();
+			if idx == 0 {
+				wtr.write_packet(vec![1, 2, 3].into_boxed_slice(), other_serial,
+					PacketWriteEndInfo::EndPage, 0).unwrap();
+			} else if idx == 2 {
+				wtr.write_packet(vec![4, 5, 6].into_boxed_slice(), other_serial,
+					PacketWriteEndInfo::EndStream, 0).unwrap();
+			}
+			idx += 1;
+		}
+	}
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut expected = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		expected.push(pck);
+	}
+
+	let mut rdr = OggStreamReader::new(Cursor::new(&buf)).unwrap();
+	let pck = rdr.as_packet_reader_mut().read_packet().unwrap().unwrap();
+	assert_ne!(pck.stream_serial(), vorbis_serial);
+	assert_eq!(pck.data, vec![4, 5, 6]);
+	let mut decoded = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		decoded.push(pck);
+	}
+	assert_eq!(decoded, expected);
+}
+
+#[test]
+fn test_block_size_histogram() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("long-short.ogg");
+	let mut pck_count = 0;
+	while let Some(_) = rdr.read_dec_packet().unwrap() {
+		pck_count += 1;
+	}
+
+	let mut rdr = open_asset("long-short.ogg");
+	let (short_count, long_count) = rdr.block_size_histogram().unwrap();
+	assert!(short_count > 0);
+	assert!(long_count > 0);
+	assert_eq!(short_count + long_count, pck_count);
+	assert!(rdr.read_dec_packet().unwrap().is_none());
+}
+
+#[test]
+fn test_pad_final_packet() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	fn last_packet(rdr :&mut OggStreamReader<File>) -> Vec<Vec<f32>> {
+		let mut last = None;
+		while let Some(pck) = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+			last = Some(pck);
+		}
+		last.unwrap()
+	}
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let truncated = last_packet(&mut rdr);
+	let truncation = rdr.last_truncation();
+	assert!(truncation > 0);
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	rdr.pad_final_packet(true);
+	let padded = last_packet(&mut rdr);
+	assert_eq!(rdr.last_truncation(), truncation);
+	for (ch_padded, ch_truncated) in padded.iter().zip(truncated.iter()) {
+		assert_eq!(ch_padded.len(), ch_truncated.len() + truncation);
+		assert_eq!(&ch_padded[.. ch_truncated.len()], &ch_truncated[..]);
+		assert!(ch_padded[ch_truncated.len() ..].iter().all(|s| *s == 0.0));
+	}
+}
+
+#[test]
+fn test_total_trimmed() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let full = decode_all(&mut rdr);
+	let end = rdr.get_last_absgp().unwrap();
+	assert_eq!(full[0].len() as u64, end);
+	assert_eq!(rdr.total_trimmed_start(), 0);
+	assert!(rdr.total_trimmed_end() > 0);
+	assert_eq!(rdr.total_trimmed_end(), rdr.last_truncation() as u64);
+
+	// Drop samples from the start via the skip count
+	let mut rdr = open_asset("square-stereo.ogg");
+	rdr.set_position(0, 100);
+	let skipped = decode_all(&mut rdr);
+	assert_eq!(rdr.total_trimmed_start(), 100);
+	assert_eq!(rdr.total_trimmed_end(), rdr.last_truncation() as u64);
+	assert_eq!(&skipped[0][..], &full[0][100 ..]);
+
+	// The totals accumulate over seeks
+	rdr.seek_absgp_pg(0).unwrap();
+	decode_all(&mut rdr);
+	assert_eq!(rdr.total_trimmed_start(), 100);
+	assert_eq!(rdr.total_trimmed_end(), 2 * rdr.last_truncation() as u64);
+}
+
+#[test]
+fn test_last_packet_trimmed() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	rdr.set_position(0, 100);
+	let mut trimmed = Vec::new();
+	while let Some(_) = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+		trimmed.push((rdr.last_packet_trimmed_start(), rdr.last_packet_trimmed_end()));
+	}
+	let sum_start :usize = trimmed.iter().map(|t| t.0).sum();
+	let sum_end :usize = trimmed.iter().map(|t| t.1).sum();
+	assert_eq!(sum_start as u64, rdr.total_trimmed_start());
+	assert_eq!(sum_start, 100);
+	assert_eq!(sum_end as u64, rdr.total_trimmed_end());
+	// Only the final packet gets truncated at the end
+	let (last, rest) = trimmed.split_last().unwrap();
+	assert!(last.1 > 0);
+	assert_eq!(last.1, rdr.last_truncation());
+	assert!(rest.iter().all(|t| t.1 == 0));
+}
+
+#[test]
+fn test_trim_silence() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	fn peak(pck :&[Vec<f32>]) -> f32 {
+		pck.iter().flat_map(|ch| ch.iter()).fold(0.0, |p, s| p.max(s.abs()))
+	}
+
+	let threshold = 0.1;
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut pcks = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+		pcks.push(pck);
+	}
+	// At least the first packet (which is empty) gets dropped
+	let first_loud = pcks.iter().position(|p| peak(p) >= threshold).unwrap();
+	assert!(first_loud > 0);
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	rdr.trim_silence(threshold);
+	let mut trimmed = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+		trimmed.push(pck);
+	}
+	assert!(peak(&trimmed[0]) >= threshold);
+	assert_eq!(&trimmed[..], &pcks[first_loud ..]);
+}
+
+#[test]
+fn test_decode_region() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let channels = rdr.ident_hdr.audio_channels as usize;
+	let all = rdr.decode_region(0, ::std::u64::MAX).unwrap();
+	let total = rdr.get_last_absgp().unwrap();
+	assert_eq!((all.len() / channels) as u64, total);
+
+	// Adjacent regions concatenate to the full decode
+	for &(start, len) in [(0, 100), (30_000, 5000), (51_000, 3000), (100, 20_000)].iter() {
+		let first = rdr.decode_region(start, len).unwrap();
+		let second = rdr.decode_region(start + len, len).unwrap();
+		let region = &all[start as usize * channels .. (start + 2 * len) as usize * channels];
+		assert_eq!([first, second].concat(), region);
+	}
+
+	// After the first page, the positions match the ones of normal decoding
+	let mut rdr_linear = open_asset("square-stereo.ogg");
+	let mut pck_end = 0;
+	while pck_end < 60_000 {
+		rdr_linear.read_dec_packet_itl().unwrap().unwrap();
+		pck_end = rdr_linear.get_last_absgp().unwrap_or(0);
+	}
+	let pck = rdr_linear.read_dec_packet_itl().unwrap().unwrap();
+	let pck_len = (pck.len() / channels) as u64;
+	assert_eq!(rdr.decode_region(pck_end, pck_len).unwrap(), pck);
+
+	// At the end of the stream, fewer samples are returned
+	let last = rdr.decode_region(total - 10, 100).unwrap();
+	assert_eq!(&last[..], &all[(total as usize - 10) * channels ..]);
+}
+
+#[test]
+fn test_packet_count() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in &["square-stereo.ogg", "long-short.ogg"] {
+		let mut rdr = open_asset(name);
+		let mut decoded_count = 0;
+		while let Some(_) = rdr.read_dec_packet().unwrap() {
+			decoded_count += 1;
+		}
+		// The scan doesn't move the reader
+		assert_eq!(rdr.packet_count().unwrap(), decoded_count);
+		assert!(rdr.read_dec_packet().unwrap().is_none());
+		// The cached value is returned
+		assert_eq!(rdr.packet_count().unwrap(), decoded_count);
+	}
+}
+
+#[test]
+fn test_read_dec_packet_bytes() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut rdr_bytes = open_asset("square-stereo.ogg");
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		let bytes = rdr_bytes.read_dec_packet_bytes(PcmByteFormat::I16Be).unwrap().unwrap();
+		let expected = pck.iter()
+			.flat_map(|s| s.to_be_bytes().to_vec())
+			.collect::<Vec<u8>>();
+		assert_eq!(bytes, expected);
+	}
+	assert!(rdr_bytes.read_dec_packet_bytes(PcmByteFormat::I16Be).unwrap().is_none());
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut rdr_bytes = open_asset("square-stereo.ogg");
+	while let Some(pck) = rdr.read_dec_packet_generic::<InterleavedSamples<f32>>().unwrap() {
+		let bytes = rdr_bytes.read_dec_packet_bytes(PcmByteFormat::F32Le).unwrap().unwrap();
+		let expected = pck.samples.iter()
+			.flat_map(|s| s.to_le_bytes().to_vec())
+			.collect::<Vec<u8>>();
+		assert_eq!(bytes, expected);
+	}
+}
+
+#[test]
+fn test_seek_timestamp() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let rate = open_asset("square-stereo.ogg").ident_hdr.audio_sample_rate as u64;
+	// 1.5 seconds, in the 90 kHz MPEG time base
+	for &(ts, absgp) in &[(135_000, rate * 3 / 2), (0, 0)] {
+		let mut rdr_ts = open_asset("square-stereo.ogg");
+		rdr_ts.seek_timestamp(ts, 90_000).unwrap();
+		let mut rdr_absgp = open_asset("square-stereo.ogg");
+		rdr_absgp.seek_absgp(absgp).unwrap();
+		loop {
+			let pck_ts = rdr_ts.read_dec_packet_itl().unwrap();
+			let pck_absgp = rdr_absgp.read_dec_packet_itl().unwrap();
+			assert_eq!(pck_ts, pck_absgp);
+			assert_eq!(rdr_ts.get_last_absgp(), rdr_absgp.get_last_absgp());
+			if pck_ts.is_none() {
+				break;
+			}
+		}
+	}
+}
+
+#[test]
+fn test_last_block_transition() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("long-short.ogg");
+	assert_eq!(rdr.last_block_transition(), None);
+	let mut transitions = Vec::new();
+	while let Some(_) = rdr.read_dec_packet().unwrap() {
+		transitions.push(rdr.last_block_transition());
+	}
+	// The first packet has no predecessor
+	assert_eq!(transitions[0], None);
+	// The block size a transition goes to is the one the next one starts from
+	for w in transitions[1 ..].windows(2) {
+		let (prev, cur) = (w[0].unwrap(), w[1].unwrap());
+		let prev_to_long = match prev {
+			BlockTransition::LongToLong | BlockTransition::ShortToLong => true,
+			BlockTransition::LongToShort | BlockTransition::ShortToShort => false,
+		};
+		let cur_from_long = match cur {
+			BlockTransition::LongToLong | BlockTransition::LongToShort => true,
+			BlockTransition::ShortToLong | BlockTransition::ShortToShort => false,
+		};
+		assert_eq!(prev_to_long, cur_from_long);
+	}
+	// All transition types occur in the file
+	assert!(transitions.contains(&Some(BlockTransition::LongToLong)));
+	assert!(transitions.contains(&Some(BlockTransition::LongToShort)));
+	assert!(transitions.contains(&Some(BlockTransition::ShortToLong)));
+	assert!(transitions.contains(&Some(BlockTransition::ShortToShort)));
+
+	// Seeking resets the transition
+	rdr.seek_absgp_pg(0).unwrap();
+	assert_eq!(rdr.last_block_transition(), None);
+}
+
+#[test]
+fn test_packet_decoder_shared_headers() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	let (headers, _) = read_headers(&mut pck_rdr).unwrap();
+	let mut packets = Vec::new();
+	while let Some(pck) = pck_rdr.read_packet().unwrap() {
+		packets.push(pck.data);
+	}
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut expected = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		expected.push(pck);
+	}
+	// The last packet gets truncated by the reader
+	expected.pop();
+	packets.pop();
+
+	// Multiple decoders can borrow the same headers
+	let mut dec_a = PacketDecoder::new(&headers);
+	let mut dec_b = PacketDecoder::new(&headers);
+	assert!(!dec_a.is_warmed_up());
+	let start_b = 10;
+	for (i, (pck, expected)) in packets.iter().zip(expected.iter()).enumerate() {
+		let decoded :InterleavedSamples<i16> = dec_a.decode_packet(pck).unwrap();
+		assert!(dec_a.is_warmed_up());
+		assert_eq!(&decoded.samples, expected);
+		if i >= start_b {
+			let decoded :InterleavedSamples<i16> = dec_b.decode_packet(pck).unwrap();
+			if i == start_b {
+				// The first packet only warms up the decoder
+				assert!(decoded.samples.is_empty());
+			} else {
+				assert_eq!(&decoded.samples, expected);
+			}
+		}
+	}
+}
+
+#[test]
+fn test_one_entry_codebook_decode() {
+	println!();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_4(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// The file uses a codebook with a single entry in its audio packets.
+	// Every packet must decode, to the length it claims to have.
+	let f = File::open("test-assets/one-entry-codebook-test.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	let (headers, _) = read_headers(&mut pck_rdr).unwrap();
+	let mut dec = PacketDecoder::new(&headers);
+	let mut total_len = 0;
+	while let Some(pck) = pck_rdr.read_packet().unwrap() {
+		let warmed_up = dec.is_warmed_up();
+		let decoded :Vec<Vec<f32>> = dec.decode_packet(&pck.data).unwrap();
+		let expected_len = get_decoded_sample_count(&headers.0, &headers.2, &pck.data).unwrap();
+		if warmed_up {
+			assert_eq!(decoded[0].len(), expected_len);
+		}
+		total_len += decoded[0].len();
+	}
+	assert!(total_len > 0);
+}
+
+#[test]
+fn test_null_samples() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in &["square-stereo.ogg", "long-short.ogg"] {
+		let mut rdr = open_asset(name);
+		let total_len = decode_all(&mut rdr)[0].len();
+
+		let mut rdr = open_asset(name);
+		let mut null_len = 0;
+		while let Some(pck) = rdr.read_dec_packet_generic::<NullSamples>().unwrap() {
+			null_len += pck.num_samples;
+		}
+		assert_eq!(null_len, total_len);
+	}
+}
+
+#[test]
+fn test_read_dec_packet_with_floor0_amp() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// A floor0 file has amplitudes for its used floors
+	let mut rdr = open_asset("sketch008-floor0.ogg");
+	let channels = rdr.ident_hdr.audio_channels as usize;
+	let mut amp_count = 0;
+	while let Some((pck, amps)) = rdr.read_dec_packet_with_floor0_amp().unwrap() {
+		assert_eq!(pck.len(), channels);
+		assert_eq!(amps.len(), channels);
+		amp_count += amps.iter().filter(|a| a.is_some()).count();
+	}
+	assert!(amp_count > 0);
+
+	// Floor1 files have no amplitudes
+	let mut rdr = open_asset("sketch008.ogg");
+	let mut rdr_cmp = open_asset("sketch008.ogg");
+	while let Some((pck, amps)) = rdr.read_dec_packet_with_floor0_amp().unwrap() {
+		assert!(amps.iter().all(|a| a.is_none()));
+		let pck_cmp = rdr_cmp.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap().unwrap();
+		assert_eq!(pck, pck_cmp);
+	}
+}
+
+#[test]
+fn test_decode_packet_catch_unwind() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_1(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let read_packets = |name :&str| {
+		let f = File::open(format!("test-assets/{}", name)).unwrap();
+		let mut pck_rdr = PacketReader::new(f);
+		let (headers, _) = read_headers(&mut pck_rdr).unwrap();
+		let mut packets = Vec::new();
+		while let Some(pck) = pck_rdr.read_packet().unwrap() {
+			packets.push(pck.data);
+		}
+		(headers, packets)
+	};
+	let (mono_hdrs, mono_pcks) = read_packets("48k-mono.ogg");
+	let (stereo_hdrs, stereo_pcks) = read_packets("square-stereo.ogg");
+
+	// Priming the decoder with a mono packet and then passing
+	// a stereo packet makes read_audio_packet_generic panic
+	let mut pwr = PreviousWindowRight::new();
+	read_audio_packet(&mono_hdrs.0, &mono_hdrs.2, &mono_pcks[0], &mut pwr).unwrap();
+	let res :Result<Vec<Vec<f32>>, _> = decode_packet_catch_unwind(&stereo_hdrs.0,
+		&stereo_hdrs.2, &stereo_pcks[0], &mut pwr);
+	assert_eq!(res.unwrap_err(), AudioReadError::AudioBadFormat);
+	assert!(pwr.is_empty());
+
+	// Afterwards, the decoder works again
+	let mut pwr_cmp = PreviousWindowRight::new();
+	for pck in stereo_pcks[.. 5].iter() {
+		let res :Vec<Vec<f32>> = decode_packet_catch_unwind(&stereo_hdrs.0,
+			&stereo_hdrs.2, pck, &mut pwr).unwrap();
+		let expected :Vec<Vec<f32>> = read_audio_packet_generic(&stereo_hdrs.0,
+			&stereo_hdrs.2, pck, &mut pwr_cmp).unwrap();
+		assert_eq!(res, expected);
+	}
+}
+
+#[test]
+fn test_boxed_samples() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut rdr_boxed = open_asset("square-stereo.ogg");
+	while let Some(pck) = rdr.read_dec_packet().unwrap() {
+		let boxed = rdr_boxed.read_dec_packet_generic::<BoxedSamples<i16>>().unwrap().unwrap();
+		let parts = boxed.into_raw_parts();
+		assert_eq!(parts.len(), pck.len());
+		for ((ptr, len), ch) in parts.into_iter().zip(pck.iter()) {
+			// Take back ownership, as the other side of an FFI boundary would
+			let ch_boxed = unsafe {
+				Box::from_raw(::std::slice::from_raw_parts_mut(ptr, len))
+			};
+			assert_eq!(&ch_boxed[..], &ch[..]);
+		}
+	}
+}
+
+#[test]
+fn test_current_stream_byte_range() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_2(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// The range of a single stream file covers the entire file,
+	// but not the trailing junk
+	let mut data = ::std::fs::read("test-assets/square-stereo.ogg").unwrap();
+	let file_len = data.len() as u64;
+	data.extend_from_slice(b"trailing junk");
+	let mut rdr = OggStreamReader::new(Cursor::new(data.clone())).unwrap();
+	let mut rdr_ref = OggStreamReader::new(Cursor::new(data)).unwrap();
+	rdr.read_dec_packet_itl().unwrap();
+	rdr_ref.read_dec_packet_itl().unwrap();
+	assert_eq!(rdr.current_stream_byte_range().unwrap(), 0 .. file_len);
+	// Afterwards, the reader continues where it left off
+	assert_eq!(rdr.read_dec_packet_itl().unwrap(), rdr_ref.read_dec_packet_itl().unwrap());
+
+	// Each link of a chained file reports its own range
+	let file_len = ::std::fs::metadata("test-assets/chain-test1.ogg").unwrap().len();
+	let mut rdr = open_asset("chain-test1.ogg");
+	let first_range = rdr.current_stream_byte_range().unwrap();
+	assert_eq!(first_range.start, 0);
+	let first_serial = rdr.stream_serial();
+	while rdr.stream_serial() == first_serial {
+		rdr.read_dec_packet().unwrap().unwrap();
+	}
+	let second_range = rdr.current_stream_byte_range().unwrap();
+	assert_eq!(second_range.start, first_range.end);
+	assert!(second_range.end <= file_len);
+}
+
+#[test]
+fn test_read_dec_packet_detailed() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let f = File::open("test-assets/long-short.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	read_headers(&mut pck_rdr).unwrap();
+	let mut compressed_lens = Vec::new();
+	while let Some(pck) = pck_rdr.read_packet().unwrap() {
+		compressed_lens.push(pck.data.len());
+	}
+
+	let mut rdr = open_asset("long-short.ogg");
+	let mut rdr_cmp = open_asset("long-short.ogg");
+	let mut next_start = 0;
+	let mut lens_iter = compressed_lens.iter();
+	while let Some(pck) = rdr.read_dec_packet_detailed().unwrap() {
+		// The packets are contiguous
+		assert_eq!(pck.start_sample, next_start);
+		next_start += pck.samples[0].len() as u64;
+		assert_eq!(pck.compressed_len, *lens_iter.next().unwrap());
+		let blocksize = 1 << if pck.long_block {
+			rdr.ident_hdr.blocksize_1
+		} else {
+			rdr.ident_hdr.blocksize_0
+		};
+		assert!(pck.samples[0].len() <= blocksize);
+		let pck_cmp = rdr_cmp.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap().unwrap();
+		assert_eq!(pck.samples, pck_cmp);
+	}
+	assert!(lens_iter.next().is_none());
+	assert_eq!(next_start, decode_all(&mut open_asset("long-short.ogg"))[0].len() as u64);
+}
+
+#[test]
+fn test_concatenated_files() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_1(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let decode_itl = |rdr :&mut OggStreamReader<_>| {
+		let mut samples = Vec::new();
+		while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+			samples.extend_from_slice(&pck);
+		}
+		samples
+	};
+	let expected_first = decode_itl(&mut open_asset("square-stereo.ogg"));
+	let expected_second = decode_itl(&mut open_asset("48k-mono.ogg"));
+
+	// Files with different sample rates, concatenated like with `cat`
+	let mut data = ::std::fs::read("test-assets/square-stereo.ogg").unwrap();
+	data.extend_from_slice(&::std::fs::read("test-assets/48k-mono.ogg").unwrap());
+	let mut rdr = OggStreamReader::new(Cursor::new(data)).unwrap();
+	assert_eq!(rdr.ident_hdr.audio_sample_rate, 44100);
+
+	let mut first = Vec::new();
+	let mut second = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		if rdr.ident_hdr.audio_sample_rate == 44100 {
+			first.extend_from_slice(&pck);
+		} else {
+			assert_eq!(rdr.ident_hdr.audio_sample_rate, 48000);
+			assert_eq!(rdr.ident_hdr.audio_channels, 1);
+			second.extend_from_slice(&pck);
+		}
+	}
+	assert_eq!(first, expected_first);
+	assert_eq!(second, expected_second);
+}
+
+#[test]
+fn test_lewton_decoder() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut expected = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		expected.extend_from_slice(&pck);
+	}
+	let end = rdr.get_last_absgp().unwrap();
+
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut decoder = LewtonDecoder::new(f).unwrap();
+	assert_eq!(decoder.channels(), 2);
+	assert_eq!(decoder.sample_rate(), 44100);
+	let duration = decoder.total_duration().unwrap();
+	assert_eq!(duration.as_secs(), end / 44100);
+	let frame_len = decoder.current_frame_len().unwrap();
+	assert!(frame_len > 0);
+	assert_eq!(frame_len % 2, 0);
+	let samples = decoder.by_ref().collect::<Vec<i16>>();
+	assert_eq!(decoder.current_frame_len(), Some(0));
+	assert_eq!(samples, expected);
+}
+
+#[test]
+fn test_for_each_packet_itl() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("long-short.ogg");
+	let mut expected = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		expected.extend_from_slice(&pck);
+	}
+
+	let mut rdr = open_asset("long-short.ogg");
+	let mut samples = Vec::new();
+	let mut max_len = 0;
+	let mut max_len_ptr = None;
+	rdr.for_each_packet_itl(|pck| {
+		samples.extend_from_slice(pck);
+		// Once the buffer had the size of the largest packet,
+		// it never gets reallocated.
+		if pck.len() > max_len {
+			max_len = pck.len();
+			max_len_ptr = Some(pck.as_ptr());
+		} else {
+			assert_eq!(max_len_ptr, Some(pck.as_ptr()));
+		}
+	}).unwrap();
+	assert_eq!(samples, expected);
+}
+
+#[test]
+fn test_granule_for_sample() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let channels = rdr.ident_hdr.audio_channels as usize;
+	assert_eq!(rdr.granule_for_sample(0).unwrap(), 0);
+	assert_eq!(rdr.granule_for_sample(1234).unwrap(), 1234);
+	let all = rdr.decode_region(0, ::std::u64::MAX).unwrap();
+
+	// Rewrite the file so that the stream
+	// starts at a nonzero granule position,
+	// like a capture of a live stream would.
+	let offset = 1_000_000;
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut rdr = PacketReader::new(f);
+	let mut buf = Vec::new();
+	{
+		let mut wtr = PacketWriter::new(&mut buf);
+		let mut idx = 0;
+		while let Some(pck) = rdr.read_packet().unwrap() {
+			let inf = if pck.last_in_stream() {
+				PacketWriteEndInfo::EndStream
+			} else if pck.last_in_page() {
+				PacketWriteEndInfo::EndPage
+			} else {
+				PacketWriteEndInfo::NormalPacket
+			};
+			let absgp = if idx < 3 { 0 } else { pck.absgp_page() + offset };
+			let serial = pck.stream_serial();
+			wtr.write_packet(pck.data.into_boxed_slice(), serial, inf, absgp).unwrap();
+			idx += 1;
+		}
+	}
+
+	let mut rdr = OggStreamReader::new(Cursor::new(&buf)).unwrap();
+	assert_eq!(rdr.granule_for_sample(0).unwrap(), offset);
+	for &idx in [0, 100, 30_000, 51_000].iter() {
+		let absgp = rdr.granule_for_sample(idx).unwrap();
+		assert_eq!(absgp, offset + idx);
+		let region = rdr.decode_region(absgp, 100).unwrap();
+		assert_eq!(&region[..], &all[idx as usize * channels .. (idx as usize + 100) * channels]);
+	}
+}
+
+#[test]
+fn test_decode_stats() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	assert_eq!(rdr.stats(), DecodeStats::default());
+	let packet_count = rdr.packet_count().unwrap();
+	let mut samples = 0;
+	let mut compressed_bytes = 0;
+	while let Some(pck) = rdr.read_dec_packet_detailed().unwrap() {
+		samples += pck.samples[0].len() as u64;
+		compressed_bytes += pck.compressed_len as u64;
+	}
+	let stats = rdr.stats();
+	assert_eq!(stats.packets, packet_count);
+	assert_eq!(stats.samples, samples);
+	assert_eq!(stats.samples, rdr.get_last_absgp().unwrap());
+	assert_eq!(stats.compressed_bytes, compressed_bytes);
+	assert_eq!(stats.recovered_errors, 0);
+
+	// The counters accumulate over seeks
+	rdr.seek_absgp_pg(0).unwrap();
+	decode_all(&mut rdr);
+	assert_eq!(rdr.stats().packets, 2 * packet_count);
+	assert_eq!(rdr.stats().samples, 2 * samples);
+}
+
+#[test]
+fn test_read_raw_audio_packet() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Read the audio packets directly via the ogg reader
+	let f = File::open("test-assets/long-short.ogg").unwrap();
+	let mut ogg_rdr = PacketReader::new(f);
+	let mut expected = Vec::new();
+	let mut end_absgp = 0;
+	while let Some(pck) = ogg_rdr.read_packet().unwrap() {
+		end_absgp = pck.absgp_page();
+		let last_in_page = pck.last_in_page();
+		expected.push((pck.data, last_in_page));
+	}
+	// Drop the header packets
+	expected.drain(.. 3);
+
+	let mut rdr = open_asset("long-short.ogg");
+	let mut raw = Vec::new();
+	while let Some(pck) = rdr.read_raw_audio_packet().unwrap() {
+		raw.push(pck);
+	}
+	assert_eq!(raw, expected);
+	assert_eq!(rdr.get_last_absgp(), Some(end_absgp));
+}
+
+#[test]
+fn test_estimate_effective_bits() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("6ch-moving-sine.ogg");
+	let bits = rdr.estimate_effective_bits().unwrap();
+	println!("Estimated effective bits: {}", bits);
+	assert!(bits > 10.0);
+
+	// Afterwards, the reader is at the start of the stream
+	let mut rdr_fresh = open_asset("6ch-moving-sine.ogg");
+	assert_eq!(decode_all(&mut rdr), decode_all(&mut rdr_fresh));
+}
+
+#[test]
+fn test_read_dec_packet_itl_with_gains() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("6ch-moving-sine.ogg");
+	let mut rdr_gains = open_asset("6ch-moving-sine.ogg");
+	let channels = rdr.ident_hdr.audio_channels as usize;
+	assert_eq!(channels, 6);
+	match rdr_gains.read_dec_packet_itl_with_gains(&[1.0; 5]) {
+		Err(VorbisError::ChannelCountMismatch(6)) => (),
+		v => panic!("Expected a channel count mismatch but got {:?}", v),
+	}
+
+	// Mute the second channel
+	let gains = [1.0, 0.0, 1.0, 1.0, 1.0, 1.0];
+	let mut muted_samples = 0;
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		let pck_gains = rdr_gains.read_dec_packet_itl_with_gains(&gains).unwrap().unwrap();
+		assert_eq!(pck.len(), pck_gains.len());
+		for (i, (s, s_gains)) in pck.iter().zip(pck_gains.iter()).enumerate() {
+			if i % channels == 1 {
+				assert_eq!(*s_gains, 0);
+				if *s != 0 {
+					muted_samples += 1;
+				}
+			} else {
+				assert_eq!(s, s_gains);
+			}
+		}
+	}
+	assert!(muted_samples > 0);
+	assert_eq!(rdr_gains.read_dec_packet_itl_with_gains(&gains).unwrap(), None);
+}
+
+#[test]
+fn test_read_dec_packet_itl_const() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut rdr_const = open_asset("square-stereo.ogg");
+	match rdr_const.read_dec_packet_itl_const::<1>() {
+		Err(VorbisError::ChannelCountMismatch(2)) => (),
+		v => panic!("Expected a channel count mismatch but got {:?}", v),
+	}
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		assert_eq!(rdr_const.read_dec_packet_itl_const::<2>().unwrap().unwrap(), pck);
+	}
+	assert_eq!(rdr_const.read_dec_packet_itl_const::<2>().unwrap(), None);
+}
+
+#[test]
+fn test_audio_byte_len() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let file_len = ::std::fs::metadata("test-assets/square-stereo.ogg").unwrap().len();
+	let mut rdr = open_asset("square-stereo.ogg");
+	let audio_len = rdr.audio_byte_len().unwrap();
+	assert!(audio_len < file_len);
+
+	// The difference is the size of the header pages: the ident header
+	// has its own page, the comment and setup headers follow on the next.
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut ogg_rdr = PacketReader::new(f);
+	let mut headers_len = 0;
+	for _ in 0 .. 3 {
+		headers_len += ogg_rdr.read_packet().unwrap().unwrap().data.len() as u64;
+	}
+	let page_overhead = file_len - audio_len - headers_len;
+	// Two page headers of 27 bytes plus their segment tables
+	assert!(page_overhead >= 2 * 27);
+	assert!(page_overhead < 2 * (27 + 255));
+
+	// The reader is at the start of the stream afterwards
+	let mut rdr_fresh = open_asset("square-stereo.ogg");
+	assert_eq!(decode_all(&mut rdr), decode_all(&mut rdr_fresh));
+}
+
+#[test]
+fn test_read_dec_packet_pick_channel() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("6ch-moving-sine.ogg");
+	let full = decode_all(&mut rdr);
+	for ch in 0 .. 6 {
+		let mut rdr = open_asset("6ch-moving-sine.ogg");
+		let mut picked = Vec::new();
+		while let Some(pck) = rdr.read_dec_packet_pick_channel(ch).unwrap() {
+			picked.extend_from_slice(&pck);
+		}
+		let expected = full[ch].iter().map(|s| <i16 as Sample>::from_float(*s)).collect::<Vec<i16>>();
+		assert_eq!(picked, expected);
+	}
+
+	let mut rdr = open_asset("6ch-moving-sine.ogg");
+	match rdr.read_dec_packet_pick_channel(6) {
+		Err(VorbisError::ChannelCountMismatch(6)) => (),
+		v => panic!("Expected a channel count mismatch but got {:?}", v),
+	}
+}
+
+#[test]
+fn test_last_stage_times() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	assert_eq!(rdr.last_stage_times(), StageTimes::default());
+	// Decode a few packets so that the windowing takes place
+	for _ in 0 .. 3 {
+		rdr.read_dec_packet().unwrap().unwrap();
+	}
+	let times = rdr.last_stage_times();
+	if lewton::features().profiling {
+		let total = times.floor + times.residue + times.coupling +
+			times.imdct + times.overlap_add;
+		assert!(total > Duration::default());
+		assert!(times.imdct > Duration::default());
+	} else {
+		assert_eq!(times, StageTimes::default());
+	}
+}
+
+#[test]
+fn test_vorbis_decoder_ffmpeg_extradata() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Emulate what FFmpeg's Ogg demuxer hands out:
+	// the headers as extradata with 16 bit big endian lengths,
+	// and the audio packets without any framing.
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	let mut extradata = Vec::new();
+	for _ in 0 .. 3 {
+		let pck = pck_rdr.read_packet_expected().unwrap();
+		extradata.push((pck.data.len() >> 8) as u8);
+		extradata.push(pck.data.len() as u8);
+		extradata.extend_from_slice(&pck.data);
+	}
+	assert_eq!(&extradata[..2], &[0, 30]);
+	let mut packets = Vec::new();
+	while let Some(pck) = pck_rdr.read_packet().unwrap() {
+		packets.push(pck.data);
+	}
+
+	let mut dec = VorbisDecoder::from_ffmpeg_extradata(&extradata).unwrap();
+	assert_eq!(dec.headers().0.audio_channels, 2);
+	let mut decoded = Vec::new();
+	for pck in packets.iter() {
+		let samples :InterleavedSamples<i16> = dec.decode(pck).unwrap();
+		decoded.extend_from_slice(&samples.samples);
+	}
+
+	// The reader additionally trims the last packet
+	// to the end position stored in the Ogg framing.
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut expected = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		expected.extend_from_slice(&pck);
+	}
+	assert!(decoded.len() >= expected.len());
+	assert_eq!(&decoded[..expected.len()], &expected[..]);
+
+	// Truncated extradata is rejected
+	assert!(VorbisDecoder::from_ffmpeg_extradata(&extradata[..100]).is_err());
+}
+
+#[test]
+fn test_scan_peak() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_2(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Full scale sine, which clips after lossy encoding
+	let mut rdr = open_asset("sketch008.ogg");
+	let peak = rdr.scan_peak().unwrap();
+	assert!(peak >= 0.95, "peak = {}", peak);
+	// The reader is positioned at the start again
+	let mut first = open_asset("sketch008.ogg");
+	assert_eq!(rdr.read_dec_packet().unwrap(), first.read_dec_packet().unwrap());
+
+	// Sine with half amplitude
+	let peak = open_asset("square-stereo.ogg").scan_peak().unwrap();
+	assert!(peak > 0.4 && peak < 0.6, "peak = {}", peak);
+
+	let peak = open_asset("bimS-silence.ogg").scan_peak().unwrap();
+	assert_eq!(peak, 0.0);
+}
+
+#[test]
+fn test_last_packet_empty() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Insert zero length packets into the stream
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut rdr = PacketReader::new(f);
+	let mut buf = Vec::new();
+	{
+		let mut wtr = PacketWriter::new(&mut buf);
+		let mut idx = 0;
+		while let Some(pck) = rdr.read_packet().unwrap() {
+			let inf = if pck.last_in_stream() {
+				PacketWriteEndInfo::EndStream
+			} else if pck.last_in_page() {
+				PacketWriteEndInfo::EndPage
+			} else {
+				PacketWriteEndInfo::NormalPacket
+			};
+			let serial = pck.stream_serial();
+			let absgp = pck.absgp_page();
+			if idx == 10 || idx == 11 {
+				wtr.write_packet(Vec::new().into_boxed_slice(), serial,
+					PacketWriteEndInfo::NormalPacket, absgp).unwrap();
+			}
+			wtr.write_packet(pck.data.into_boxed_slice(), serial, inf, absgp).unwrap();
+			idx += 1;
+		}
+	}
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut expected = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		assert!(!rdr.last_packet_empty());
+		expected.extend_from_slice(&pck);
+	}
+
+	let mut rdr = OggStreamReader::new(Cursor::new(&buf)).unwrap();
+	let mut decoded = Vec::new();
+	let mut empty_packets = 0;
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		if rdr.last_packet_empty() {
+			assert!(pck.is_empty());
+			empty_packets += 1;
+		}
+		decoded.extend_from_slice(&pck);
+	}
+	assert_eq!(empty_packets, 2);
+	assert_eq!(decoded, expected);
+}
+
+#[test]
+fn test_zero_length_reaches_eof() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// The stream's length as stated by the absgp of its last page
+	let f = File::open("test-assets/zero-length.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	let mut end_absgp = 0;
+	while let Some(pck) = pck_rdr.read_packet().unwrap() {
+		end_absgp = pck.absgp_page();
+	}
+
+	let mut rdr = open_asset("zero-length.ogg");
+	let mut empty_packets = 0;
+	let mut sample_count = 0;
+	while let Some(pck) = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+		if rdr.last_packet_empty() {
+			empty_packets += 1;
+		}
+		sample_count += pck[0].len() as u64;
+	}
+	assert!(empty_packets > 0);
+	assert_eq!(sample_count, end_absgp);
+}
+
+#[test]
+fn test_check_invariants() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_2(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in ["long-short.ogg", "square-stereo.ogg", "chain-test1.ogg"].iter() {
+		let mut rdr = open_asset(name);
+		rdr.check_invariants().unwrap();
+		// Scan the stream so that the range checks are active
+		rdr.audio_byte_len().unwrap();
+		while let Some(_) = rdr.read_dec_packet_itl().unwrap() {
+			rdr.check_invariants().unwrap();
+		}
+
+		let mut rdr = open_asset(name);
+		rdr.audio_byte_len().unwrap();
+		rdr.seek_absgp_pg(20_000).unwrap();
+		rdr.check_invariants().unwrap();
+		while let Some(_) = rdr.read_dec_packet_itl().unwrap() {
+			rdr.check_invariants().unwrap();
+		}
+	}
+
+	// Skipping samples via set_position
+	let mut rdr = open_asset("square-stereo.ogg");
+	rdr.read_dec_packet_itl().unwrap();
+	rdr.read_dec_packet_itl().unwrap();
+	rdr.check_invariants().unwrap();
+	rdr.set_position(0, 100);
+	assert!(rdr.check_invariants().is_err());
+}
+
+#[test]
+fn test_read_audio_packet_into() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in ["long-short.ogg", "6ch-moving-sine.ogg", "square-stereo.ogg"].iter() {
+		let f = File::open(format!("test-assets/{}", name)).unwrap();
+		let mut pck_rdr = PacketReader::new(f);
+		let (headers, _) = read_headers(&mut pck_rdr).unwrap();
+		let mut packets = Vec::new();
+		while let Some(pck) = pck_rdr.read_packet().unwrap() {
+			packets.push(pck.data);
+		}
+
+		let mut pwr = PreviousWindowRight::new();
+		let mut pwr_into = PreviousWindowRight::new();
+		let mut scratch = DecodeScratch::new();
+		let mut out = Vec::new();
+		let mut buf_ptrs = Vec::new();
+		for (i, pck) in packets.iter().enumerate() {
+			let expected :Vec<Vec<f32>> = read_audio_packet_generic(&headers.0,
+				&headers.2, pck, &mut pwr).unwrap();
+			read_audio_packet_into(&headers.0, &headers.2, pck,
+				&mut pwr_into, &mut scratch, &mut out).unwrap();
+			assert_eq!(out, expected);
+			// Once both block sizes have been decoded, the
+			// buffers are large enough and don't move any more.
+			let ptrs = out.iter().map(|ch| ch.as_ptr()).collect::<Vec<_>>();
+			if i > packets.len() / 2 && !buf_ptrs.is_empty() {
+				assert_eq!(ptrs, buf_ptrs);
+			}
+			buf_ptrs = ptrs;
+		}
+	}
+}
+
+#[test]
+fn test_packets_iterators() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut cmp_rdr = open_asset("square-stereo.ogg");
+	let mut count = 0;
+	for pck in rdr.packets() {
+		assert_eq!(pck.unwrap(), cmp_rdr.read_dec_packet().unwrap().unwrap());
+		count += 1;
+	}
+	assert!(count > 0);
+	assert_eq!(cmp_rdr.read_dec_packet().unwrap(), None);
+	// The iterator stays at the end
+	assert!(rdr.packets().next().is_none());
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut cmp_rdr = open_asset("square-stereo.ogg");
+	let itl = rdr.packets_itl().collect::<Result<Vec<_>, _>>().unwrap();
+	let mut cmp_itl = Vec::new();
+	while let Some(pck) = cmp_rdr.read_dec_packet_itl().unwrap() {
+		cmp_itl.push(pck);
+	}
+	assert_eq!(itl, cmp_itl);
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let samples :usize = rdr.packets_generic::<Vec<Vec<f32>>>()
+		.map(|pck| pck.unwrap()[0].len())
+		.sum();
+	assert_eq!(samples, cmp_itl.iter().map(|pck| pck.len() / 2).sum::<usize>());
+}
+
+#[test]
+fn test_total_samples() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_1(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in ["square-stereo.ogg", "long-short.ogg", "sketch008.ogg"].iter() {
+		let mut rdr = open_asset(name);
+		let total = rdr.total_samples().unwrap();
+		let mut decoded = 0;
+		while let Some(pck) = rdr.read_dec_packet().unwrap() {
+			decoded += pck[0].len() as u64;
+		}
+		assert_eq!(total, decoded, "{}", name);
+		// The cached value is returned, without rewinding the reader
+		assert_eq!(rdr.total_samples().unwrap(), total);
+		assert_eq!(rdr.read_dec_packet().unwrap(), None);
+	}
+
+	let mut rdr = open_asset("48k-mono.ogg");
+	assert_eq!(rdr.total_samples().unwrap(), 96000);
+	let duration = rdr.total_duration().unwrap();
+	let secs = duration.as_secs_f64();
+	assert!((secs - 2.0).abs() < 0.01, "duration = {:?}", duration);
+}
+
+#[test]
+fn test_seek_absgp() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in ["square-stereo.ogg", "thingy.ogg"].iter() {
+		let mut rdr = open_asset(name);
+		let channels = rdr.ident_hdr.audio_channels as usize;
+		// Unlike linear decoding, decode_region drops
+		// samples at negative positions, like seek_absgp
+		let all = rdr.decode_region(0, ::std::u64::MAX).unwrap();
+		let total = (all.len() / channels) as u64;
+		for &pos in [0, 1, 100, 3000, 30_000, 51_234, 87_000, total - 10].iter() {
+			rdr.seek_absgp(pos).unwrap();
+			let mut samples = Vec::new();
+			while samples.len() < 1000 * channels {
+				match rdr.read_dec_packet_itl().unwrap() {
+					Some(pck) => samples.extend_from_slice(&pck),
+					None => break,
+				}
+			}
+			rdr.check_invariants().unwrap();
+			let start = pos as usize * channels;
+			let len = ::std::cmp::min(samples.len(), all.len() - start);
+			assert!(len > 0, "{} at {}", name, pos);
+			assert_eq!(&samples[.. len], &all[start .. start + len], "{} at {}", name, pos);
+		}
+		rdr.seek_absgp(total + 10_000).unwrap();
+		assert_eq!(rdr.read_dec_packet_itl().unwrap().map_or(0, |pck| pck.len()), 0);
+	}
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut rdr_absgp = open_asset("square-stereo.ogg");
+	let rate = rdr.ident_hdr.audio_sample_rate as u64;
+	for &(secs, absgp) in [(1.5, rate * 3 / 2), (-3.0, 0), (::std::f64::NAN, 0)].iter() {
+		rdr.seek_time(secs).unwrap();
+		rdr_absgp.seek_absgp(absgp).unwrap();
+		assert_eq!(rdr.read_dec_packet_itl().unwrap(), rdr_absgp.read_dec_packet_itl().unwrap());
+	}
+	let total = rdr.total_samples().unwrap();
+	for &(frac, absgp) in [(0.5, total / 2), (2.0, total), (-1.0, 0)].iter() {
+		rdr.seek_fraction(frac).unwrap();
+		rdr_absgp.seek_absgp(absgp).unwrap();
+		assert_eq!(rdr.read_dec_packet_itl().unwrap(), rdr_absgp.read_dec_packet_itl().unwrap());
+	}
+}
+
+#[test]
+fn test_into_next_stream() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_2(),
+		"test-assets", true).unwrap();
+	println!();
+
+	// Decode the entire chained file, remembering
+	// the packets of the second stream
+	let mut rdr = open_asset("chain-test1.ogg");
+	let first_serial = rdr.stream_serial();
+	let mut second_pcks = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		if rdr.stream_serial() != first_serial {
+			second_pcks.push(pck);
+		}
+	}
+	assert!(!second_pcks.is_empty());
+
+	// Go to the second stream after a few packets of the first
+	let mut rdr = open_asset("chain-test1.ogg");
+	assert_eq!(rdr.ident_hdr.audio_channels, 2);
+	for _ in 0 .. 5 {
+		rdr.read_dec_packet_itl().unwrap().unwrap();
+	}
+	let mut next = rdr.into_next_stream().unwrap().unwrap();
+	assert_ne!(next.stream_serial(), first_serial);
+	assert_eq!(next.ident_hdr.audio_channels, 1);
+	assert_eq!(next.ident_hdr.audio_sample_rate, 22050);
+	let mut pcks = Vec::new();
+	while let Some(pck) = next.read_dec_packet_itl().unwrap() {
+		pcks.push(pck);
+	}
+	// The first packet of a stream doesn't yield any samples,
+	// the transparent switch to the next stream doesn't return it
+	assert!(pcks[0].is_empty());
+	assert_eq!(&pcks[1 ..], &second_pcks[..]);
+
+	// There is no third stream
+	assert!(next.into_next_stream().unwrap().is_none());
+	assert!(open_asset("square-stereo.ogg").into_next_stream().unwrap().is_none());
+}
+
+#[test]
+fn test_raw_vorbis_decoder() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let f = File::open("test-assets/square-stereo.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	let ((ident, _comment, setup), _) = read_headers(&mut pck_rdr).unwrap();
+	let mut packets = Vec::new();
+	while let Some(pck) = pck_rdr.read_packet().unwrap() {
+		packets.push(pck.data);
+	}
+
+	let mut dec = RawVorbisDecoder::from_headers(ident.clone(), setup.clone());
+	assert_eq!(dec.ident_hdr().audio_channels, 2);
+	let mut pwr = PreviousWindowRight::new();
+	for pck in packets.iter() {
+		let decoded :Vec<Vec<f32>> = dec.decode_packet(pck).unwrap();
+		let expected :Vec<Vec<f32>> = read_audio_packet_generic(&ident, &setup,
+			pck, &mut pwr).unwrap();
+		assert_eq!(decoded, expected);
+	}
+
+	// After a reset, the next packet only warms up the decoder again
+	dec.reset();
+	let decoded :Vec<Vec<f32>> = dec.decode_packet(&packets[10]).unwrap();
+	assert!(decoded.iter().all(|ch| ch.is_empty()));
+	let decoded :Vec<Vec<f32>> = dec.decode_packet(&packets[11]).unwrap();
+	assert!(!decoded[0].is_empty());
+}
+
+#[test]
+fn test_pcm_reader() {
+	use std::io::{self, Read};
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("square-stereo.ogg");
+	let mut expected = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		for s in pck {
+			expected.push(s as u8);
+			expected.push((s >> 8) as u8);
+		}
+	}
+
+	let mut pcm = open_asset("square-stereo.ogg").into_pcm_reader();
+	let mut bytes = Vec::new();
+	io::copy(&mut pcm, &mut bytes).unwrap();
+	assert_eq!(bytes, expected);
+	assert_eq!(pcm.read(&mut [0; 16]).unwrap(), 0);
+
+	// Buffers smaller than a sample
+	let mut pcm = open_asset("square-stereo.ogg").into_pcm_reader();
+	let mut bytes = Vec::new();
+	let mut buf = [0];
+	while pcm.read(&mut buf).unwrap() == 1 {
+		bytes.push(buf[0]);
+	}
+	assert_eq!(bytes, expected);
+
+	// Corrupted pages yield errors
+	let mut data = Vec::new();
+	File::open("test-assets/square-stereo.ogg").unwrap().read_to_end(&mut data).unwrap();
+	let len = data.len();
+	data[len / 2] ^= 0xff;
+	let mut pcm = OggStreamReader::new(Cursor::new(data)).unwrap().into_pcm_reader();
+	let err = io::copy(&mut pcm, &mut io::sink()).unwrap_err();
+	assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_read_audio_packet_floor_only() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_2(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in ["square-stereo.ogg", "bimS-silence.ogg"].iter() {
+		let f = File::open(format!("test-assets/{}", name)).unwrap();
+		let mut pck_rdr = PacketReader::new(f);
+		let ((ident, _, setup), _) = read_headers(&mut pck_rdr).unwrap();
+		let mut pwr = PreviousWindowRight::new();
+		let mut pwr_floor = PreviousWindowRight::new();
+		let mut energy = 0.0;
+		while let Some(pck) = pck_rdr.read_packet().unwrap() {
+			let decoded :Vec<Vec<f32>> = read_audio_packet_generic(&ident, &setup,
+				&pck.data, &mut pwr).unwrap();
+			let preview :Vec<Vec<f32>> = read_audio_packet_floor_only(&ident, &setup,
+				&pck.data, &mut pwr_floor).unwrap();
+			// Same amount of samples, but different ones
+			assert_eq!(preview.len(), decoded.len());
+			for (p, d) in preview.iter().zip(decoded.iter()) {
+				assert_eq!(p.len(), d.len());
+				energy += p.iter().map(|s| s * s).sum::<f32>();
+			}
+		}
+		// The preview follows the loudness of the stream
+		if *name == "bimS-silence.ogg" {
+			assert_eq!(energy, 0.0);
+		} else {
+			assert!(energy > 0.0);
+		}
+	}
+}
+
+#[test]
+fn test_half_rate_decoding() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	test_assets::download_test_files(&cmp::get_xiph_asset_defs_1(),
+		"test-assets", true).unwrap();
+	println!();
+
+	for name in ["square-stereo.ogg", "48k-mono.ogg", "6ch-moving-sine.ogg"].iter() {
+		let f = File::open(format!("test-assets/{}", name)).unwrap();
+		let mut pck_rdr = PacketReader::new(f);
+		let ((ident, _, setup), _) = read_headers(&mut pck_rdr).unwrap();
+		let mut pwr = PreviousWindowRight::new();
+		let mut pwr_full = PreviousWindowRight::new();
+		let mut pwr_half = PreviousWindowRight::new();
+		let mut full = Vec::new();
+		let mut half = Vec::new();
+		while let Some(pck) = pck_rdr.read_packet().unwrap() {
+			let decoded :Vec<Vec<f32>> = read_audio_packet_generic(&ident, &setup,
+				&pck.data, &mut pwr).unwrap();
+			let decoded_full :Vec<Vec<f32>> = read_audio_packet_generic_opts(&ident, &setup,
+				&pck.data, &mut pwr_full, DecodeQuality::Full).unwrap();
+			let decoded_half :Vec<Vec<f32>> = read_audio_packet_generic_opts(&ident, &setup,
+				&pck.data, &mut pwr_half, DecodeQuality::Half).unwrap();
+			assert_eq!(decoded, decoded_full);
+			assert_eq!(decoded_half.len(), decoded.len());
+			for (h, d) in decoded_half.iter().zip(decoded.iter()) {
+				assert_eq!(h.len() * 2, d.len());
+			}
+			full.extend_from_slice(&decoded[0]);
+			half.extend_from_slice(&decoded_half[0]);
+		}
+		// The test files consist of low frequency sines,
+		// which the half rate output reproduces closely.
+		let rms = |v :&[f32]| (v.iter().map(|x| x * x).sum::<f32>() / v.len() as f32).sqrt();
+		let decimated = (0 .. half.len())
+			.map(|m| (full[2 * m] + full[2 * m + 1]) / 2.0)
+			.collect::<Vec<f32>>();
+		let err = decimated.iter().zip(half.iter())
+			.map(|(d, h)| d - h)
+			.collect::<Vec<f32>>();
+		assert!((rms(&half) / rms(&full) - 1.0).abs() < 0.02, "{}: {} {}", name, rms(&half), rms(&full));
+		assert!(rms(&err) < 0.1 * rms(&full), "{}: err {}", name, rms(&err));
+	}
+}
+
+#[test]
+fn test_read_audio_spectra() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let f = File::open("test-assets/long-short.ogg").unwrap();
+	let mut pck_rdr = PacketReader::new(f);
+	let ((ident, _, setup), _) = read_headers(&mut pck_rdr).unwrap();
+	let mut pwr = PreviousWindowRight::new();
+	let mut pcks = Vec::new();
+	while let Some(pck) = pck_rdr.read_packet().unwrap() {
+		let spectra = read_audio_spectra(&ident, &setup, &pck.data).unwrap();
+		let (_, expected) :(Vec<Vec<f32>>, _) = read_audio_packet_with_spectra(&ident,
+			&setup, &pck.data, &mut pwr).unwrap();
+		assert_eq!(spectra, expected);
+		let n = if get_packet_blockflag(&setup, &pck.data).unwrap() {
+			1 << ident.blocksize_1
+		} else {
+			1 << ident.blocksize_0
+		};
+		assert_eq!(spectra.len(), 2);
+		for ch in spectra.iter() {
+			assert_eq!(ch.len(), n / 2);
+		}
+		pcks.push((pck.data, spectra));
+	}
+	// The order of the packets doesn't matter
+	for (pck, spectra) in pcks.iter().rev() {
+		assert_eq!(&read_audio_spectra(&ident, &setup, pck).unwrap(), spectra);
+	}
+}
+
+#[test]
+fn test_downmix_to_stereo() {
+	println!();
+	test_assets::download_test_files(&cmp::get_libnogg_asset_defs(),
+		"test-assets", true).unwrap();
+	println!();
+
+	let mut rdr = open_asset("6ch-moving-sine.ogg");
+	let layout = rdr.ident_hdr.channel_layout();
+	let sr = ::std::f32::consts::FRAC_1_SQRT_2;
+	let mut n = 0;
+	while let Some(pck) = rdr.read_dec_packet_generic::<Vec<Vec<f32>>>().unwrap() {
+		let (left, right) = downmix_to_stereo(&pck, layout);
+		assert_eq!(left.len(), pck[0].len());
+		assert_eq!(right.len(), pck[0].len());
+		for i in 0 .. left.len() {
+			let center = pck[1][i] * sr;
+			assert!((left[i] - (pck[0][i] + center + pck[3][i] * sr)).abs() < 1e-5);
+			assert!((right[i] - (pck[2][i] + center + pck[4][i] * sr)).abs() < 1e-5);
+		}
+		n += left.len();
+	}
+	assert!(n > 0);
+}
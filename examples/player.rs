@@ -11,6 +11,7 @@ extern crate byteorder;
 use std::env;
 use lewton::VorbisError;
 use lewton::inside_ogg::OggStreamReader;
+use lewton::samples::{downmix_to_stereo, interleave_slices};
 use std::fs::File;
 use std::thread::sleep;
 use std::time::{Instant, Duration};
@@ -39,9 +40,10 @@ fn run() -> Result<(), VorbisError> {
 		.expect("could not create streaming src");
 	let sample_rate = srr.ident_hdr.audio_sample_rate as i32;
 
-	if srr.ident_hdr.audio_channels > 2 {
+	let channels = srr.ident_hdr.audio_channels;
+	if channels > 2 {
 		// the openal crate can't process these many channels directly
-		println!("Stream error: {} channels are too many!", srr.ident_hdr.audio_channels);
+		println!("Downmixing {} channels to stereo", channels);
 	}
 
 	println!("Sample rate: {}", srr.ident_hdr.audio_sample_rate);
@@ -51,15 +53,30 @@ fn run() -> Result<(), VorbisError> {
 	let mut len_play = 0.0;
 	let mut start_play_time = None;
 	let start_decode_time = Instant::now();
-	let sample_channels = srr.ident_hdr.audio_channels as f32 *
+	let sample_channels = channels.min(2) as f32 *
 		srr.ident_hdr.audio_sample_rate as f32;
-	while let Some(pck_samples) = srr.read_dec_packet_itl()? {
+	let layout = srr.ident_hdr.channel_layout();
+	loop {
+		let pck_samples = if channels > 2 {
+			let pck = match srr.read_dec_packet_generic::<Vec<Vec<f32>>>()? {
+				Some(pck) => pck,
+				None => break,
+			};
+			let (left, right) = downmix_to_stereo(&pck, layout);
+			let mut pck_samples :Vec<i16> = Vec::new();
+			interleave_slices(&[&left, &right], &mut pck_samples);
+			pck_samples
+		} else {
+			match srr.read_dec_packet_itl()? {
+				Some(pck_samples) => pck_samples,
+				None => break,
+			}
+		};
 		println!("Decoded packet no {}, with {} samples.", n, pck_samples.len());
 		n += 1;
-		let buf = match srr.ident_hdr.audio_channels {
+		let buf = match channels {
 			1 => cxt.new_buffer::<Mono<i16>,_>(&pck_samples, sample_rate),
-			2 => cxt.new_buffer::<Stereo<i16>,_>(&pck_samples, sample_rate),
-			n => panic!("unsupported number of channels: {}", n),
+			_ => cxt.new_buffer::<Stereo<i16>,_>(&pck_samples, sample_rate),
 		}.unwrap();
 
 		str_src.queue_buffer(buf).unwrap();
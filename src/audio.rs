@@ -16,12 +16,19 @@ use std::error;
 use std::fmt;
 use std::cmp::min;
 use std::iter;
+use std::panic;
+use std::time::Duration;
+#[cfg(feature = "profiling")]
+use std::time::Instant;
 use tinyvec::TinyVec;
 use crate::ilog;
-use bitpacking::BitpackCursor;
+use WithPosition;
+use bitpacking::{BitpackCursor, HuffmanReadErr, EndOfPacket};
 use header::{Codebook, Floor, FloorTypeZero, FloorTypeOne,
-	HuffmanVqReadErr, IdentHeader, Mapping, Residue, SetupHeader};
-use samples::Samples;
+	HeaderReadError, HeaderSet, HuffmanVqReadErr, IdentHeader, Mapping, Residue,
+	SetupHeader, read_headers_from_ffmpeg_extradata};
+use samples::{Samples, InterleavedSamples};
+use header_cached::CachedBlocksizeDerived;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum AudioReadError {
@@ -40,11 +47,8 @@ pub enum AudioReadError {
 	BufferNotAddressable,
 }
 
-// For the () error type returned by the bitpacking layer
-// TODO that type choice was a bit unfortunate,
-// perhaps one day fix this
-impl From<()> for AudioReadError {
-	fn from(_ :()) -> AudioReadError {
+impl From<EndOfPacket> for AudioReadError {
+	fn from(_ :EndOfPacket) -> AudioReadError {
 		AudioReadError::EndOfPacket
 	}
 }
@@ -63,13 +67,19 @@ impl fmt::Display for AudioReadError {
 	}
 }
 
-enum DecodedFloor<'a> {
-	TypeZero(Vec<f32>, u64, &'a FloorTypeZero),
-	TypeOne(Vec<u32>, &'a FloorTypeOne),
+/// The kind of floor decoded for a channel
+///
+/// The decoded floor data is stored in the per channel
+/// buffers of `DecodeScratch`, to reuse their allocations.
+#[derive(Clone, Copy)]
+enum DecodedFloor {
+	/// Floor type 0 with the given amplitude
+	TypeZero(u64),
+	TypeOne,
 	Unused,
 }
 
-impl <'a> DecodedFloor<'a> {
+impl DecodedFloor {
 	fn is_unused(&self) -> bool {
 		match self {
 			&DecodedFloor::Unused => true,
@@ -83,14 +93,25 @@ enum FloorSpecialCase {
 	PacketUndecodable,
 }
 
-impl From<()> for FloorSpecialCase {
-	fn from(_ :()) -> Self {
-		// () always means end of packet condition in the places
-		// the conversion is used.
+impl From<EndOfPacket> for FloorSpecialCase {
+	fn from(_ :EndOfPacket) -> Self {
+		// End of packet always means the floor is unused
+		// in the places the conversion is used.
 		return FloorSpecialCase::Unused;
 	}
 }
 
+impl From<HuffmanReadErr> for FloorSpecialCase {
+	fn from(e :HuffmanReadErr) -> Self {
+		use self::FloorSpecialCase::*;
+		match e {
+			HuffmanReadErr::EndOfPacket => Unused,
+			// Undecodable, see the docs of VorbisHuffmanIter::next
+			HuffmanReadErr::EmptyTree => PacketUndecodable,
+		}
+	}
+}
+
 impl From<HuffmanVqReadErr> for FloorSpecialCase {
 	fn from(e :HuffmanVqReadErr) -> Self {
 		use ::header::HuffmanVqReadErr::*;
@@ -100,16 +121,19 @@ impl From<HuffmanVqReadErr> for FloorSpecialCase {
 			// Undecodable per spec, see paragraph about
 			// VQ lookup type zero in section 3.3.
 			NoVqLookupForCodebook => PacketUndecodable,
+			EmptyCodebook => PacketUndecodable,
+			LookupOutOfBounds => PacketUndecodable,
 		}
 	}
 }
 
 // Note that the output vector contains the cosine values of the coefficients,
 // not the bare values like in the spec. This is in order to optimize.
+// Returns the amplitude.
 fn floor_zero_decode(rdr :&mut BitpackCursor, codebooks :&[Codebook],
-		fl :&FloorTypeZero) -> Result<(Vec<f32>, u64), FloorSpecialCase> {
-	// TODO this needs to become 128 bits wide, not just 64,
-	// as floor0_amplitude_bits can be up to 127.
+		fl :&FloorTypeZero, coefficients :&mut Vec<f32>) -> Result<u64, FloorSpecialCase> {
+	// floor0_amplitude_bits is a six bit field,
+	// so the amplitude always fits into 64 bits.
 	let amplitude = try!(rdr.read_dyn_u64(fl.floor0_amplitude_bits));
 	if amplitude <= 0 {
 		// This channel is unused in this frame,
@@ -123,7 +147,7 @@ fn floor_zero_decode(rdr :&mut BitpackCursor, codebooks :&[Codebook],
 		// Undecodable per spec
 		None => try!(Err(FloorSpecialCase::PacketUndecodable)),
 		Some(codebook_idx) => {
-			let mut coefficients = Vec::with_capacity(fl.floor0_order as usize);
+			coefficients.clear();
 			let mut last = 0.0;
 			let codebook = &codebooks[*codebook_idx as usize];
 			loop {
@@ -143,13 +167,13 @@ fn floor_zero_decode(rdr :&mut BitpackCursor, codebooks :&[Codebook],
 						// larger than floor0_order and saves an allocation
 						// in this case
 						if coefficients.len() == fl.floor0_order as usize {
-							return Ok((coefficients, amplitude));
+							return Ok(amplitude);
 						}
 					}
 				}
 				last += last_new;
 				if coefficients.len() >= fl.floor0_order as usize {
-					return Ok((coefficients, amplitude));
+					return Ok(amplitude);
 				}
 			}
 		},
@@ -158,37 +182,45 @@ fn floor_zero_decode(rdr :&mut BitpackCursor, codebooks :&[Codebook],
 }
 
 fn floor_zero_compute_curve(cos_coefficients :&[f32], amplitude :u64,
-		fl :&FloorTypeZero, blockflag :bool, n :u16) -> Vec<f32> {
+		fl :&FloorTypeZero, blockflag :bool, n :u16, output :&mut Vec<f32>) {
 	let cached_bark_cos_omega =
 		&fl.cached_bark_cos_omega[blockflag as usize];
 	let mut i = 0;
-	let mut output = Vec::with_capacity(n as usize);
+	output.clear();
+	// Shifting a plain integer literal would overflow
+	// for amplitude bit counts of 31 and above.
 	let lfv_common_term = amplitude as f32 * fl.floor0_amplitude_offset as f32 /
-		((1 << fl.floor0_amplitude_bits) - 1) as f32;
+		((1u64 << fl.floor0_amplitude_bits) - 1) as f32;
+
+	// The borders only depend on the order, so compute them once
+	let order_odd = fl.floor0_order & 1 == 1;
+	let (p_upper_border, q_upper_border) = if order_odd {
+		((fl.floor0_order as usize - 3) / 2,
+			(fl.floor0_order as usize - 1) / 2)
+	} else {
+		let v = (fl.floor0_order as usize - 2) / 2;
+		(v, v)
+	};
+	let p_coefficients = cos_coefficients.iter().skip(1).step_by(2)
+		.take(p_upper_border + 1);
+	let q_coefficients = cos_coefficients.iter().step_by(2)
+		.take(q_upper_border + 1);
+
 	while i < n as usize {
 		let cos_omega = cached_bark_cos_omega[i];
 
 		// Compute p and q
-		let (p_upper_border, q_upper_border) =
-		if fl.floor0_order & 1 == 1 {
-			((fl.floor0_order as usize - 3) / 2,
-				(fl.floor0_order as usize - 1) / 2)
-		} else {
-			let v = (fl.floor0_order as usize - 2) / 2;
-			(v, v)
-		};
-		let (mut p, mut q) =
-		if fl.floor0_order & 1 == 1 {
+		let (mut p, mut q) = if order_odd {
 			(1.0 - cos_omega * cos_omega, 0.25)
 		} else {
 			((1.0 - cos_omega) / 2.0, (1.0 + cos_omega) / 2.0)
 		};
-		for j in 0 .. p_upper_border + 1 {
-			let pm = cos_coefficients[2 * j + 1] - cos_omega;
+		for c in p_coefficients.clone() {
+			let pm = c - cos_omega;
 			p *= 4.0 * pm * pm;
 		}
-		for j in 0 .. q_upper_border + 1 {
-			let qm = cos_coefficients[2 * j] - cos_omega;
+		for c in q_coefficients.clone() {
+			let qm = c - cos_omega;
 			q *= 4.0 * qm * qm;
 		}
 
@@ -208,18 +240,124 @@ fn floor_zero_compute_curve(cos_coefficients :&[f32], amplitude :u64,
 			};
 		}
 	}
-	return output;
+}
+
+#[test]
+fn test_floor_zero_compute_curve() {
+	use header_cached::compute_bark_map_cos_omega;
+	// The implementation prior to hoisting the border computation
+	// out of the loop, to ensure the output stays bit identical.
+	fn reference(cos_coefficients :&[f32], amplitude :u64,
+			fl :&FloorTypeZero, blockflag :bool, n :u16) -> Vec<f32> {
+		let cached_bark_cos_omega =
+			&fl.cached_bark_cos_omega[blockflag as usize];
+		let mut i = 0;
+		let mut output = Vec::with_capacity(n as usize);
+		let lfv_common_term = amplitude as f32 * fl.floor0_amplitude_offset as f32 /
+			((1 << fl.floor0_amplitude_bits) - 1) as f32;
+		while i < n as usize {
+			let cos_omega = cached_bark_cos_omega[i];
+			let (p_upper_border, q_upper_border) =
+			if fl.floor0_order & 1 == 1 {
+				((fl.floor0_order as usize - 3) / 2,
+					(fl.floor0_order as usize - 1) / 2)
+			} else {
+				let v = (fl.floor0_order as usize - 2) / 2;
+				(v, v)
+			};
+			let (mut p, mut q) =
+			if fl.floor0_order & 1 == 1 {
+				(1.0 - cos_omega * cos_omega, 0.25)
+			} else {
+				((1.0 - cos_omega) / 2.0, (1.0 + cos_omega) / 2.0)
+			};
+			for j in 0 .. p_upper_border + 1 {
+				let pm = cos_coefficients[2 * j + 1] - cos_omega;
+				p *= 4.0 * pm * pm;
+			}
+			for j in 0 .. q_upper_border + 1 {
+				let qm = cos_coefficients[2 * j] - cos_omega;
+				q *= 4.0 * qm * qm;
+			}
+			let linear_floor_value = (0.11512925 *
+				(lfv_common_term / (p+q).sqrt() - fl.floor0_amplitude_offset as f32)
+			).exp();
+			let mut iteration_condition = cos_omega;
+			while cos_omega == iteration_condition {
+				output.push(linear_floor_value);
+				i += 1;
+				iteration_condition = match cached_bark_cos_omega.get(i) {
+					Some(v) => *v,
+					None => break,
+				};
+			}
+		}
+		return output;
+	}
+	for &order in [3u8, 4, 15, 16, 32].iter() {
+		let fl = FloorTypeZero {
+			floor0_order : order,
+			floor0_amplitude_bits : 6,
+			floor0_amplitude_offset : 140,
+			floor0_number_of_books : 1,
+			floor0_book_list : vec![0],
+			cached_bark_cos_omega : [
+				compute_bark_map_cos_omega(128, 44100, 256),
+				compute_bark_map_cos_omega(1024, 44100, 256),
+			],
+		};
+		let coefficients = (0 .. order as usize)
+			.map(|i| (i as f32 * 0.7 + 0.3).cos())
+			.collect::<Vec<_>>();
+		for &(blockflag, n) in [(false, 128), (true, 1024)].iter() {
+			for &amplitude in [1, 30, 63].iter() {
+				let mut curve = Vec::new();
+				floor_zero_compute_curve(&coefficients,
+					amplitude, &fl, blockflag, n, &mut curve);
+				let expected = reference(&coefficients, amplitude, &fl, blockflag, n);
+				assert_eq!(curve.len(), n as usize);
+				let bits = |v :&[f32]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+				assert_eq!(bits(&curve), bits(&expected));
+			}
+		}
+	}
+}
+
+#[test]
+fn test_floor_zero_max_amplitude_bits() {
+	use header_cached::compute_bark_map_cos_omega;
+	let fl = FloorTypeZero {
+		floor0_order : 16,
+		floor0_amplitude_bits : 63,
+		floor0_amplitude_offset : 140,
+		floor0_number_of_books : 1,
+		floor0_book_list : vec![0],
+		cached_bark_cos_omega : [
+			compute_bark_map_cos_omega(128, 44100, 256),
+			compute_bark_map_cos_omega(1024, 44100, 256),
+		],
+	};
+	let coefficients = (0 .. 16)
+		.map(|i| (i as f32 * 0.7 + 0.3).cos())
+		.collect::<Vec<_>>();
+	for &amplitude in [1, 1 << 40, (1 << 63) - 1].iter() {
+		let mut curve = Vec::new();
+		floor_zero_compute_curve(&coefficients,
+			amplitude, &fl, false, 128, &mut curve);
+		assert_eq!(curve.len(), 128);
+		assert!(curve.iter().all(|v| !v.is_nan()));
+	}
 }
 
 // Returns Err if the floor is "unused"
 fn floor_one_decode(rdr :&mut BitpackCursor, codebooks :&[Codebook],
-		fl :&FloorTypeOne) -> Result<Vec<u32>, FloorSpecialCase> {
+		fl :&FloorTypeOne, floor1_y :&mut Vec<u32>) -> Result<(), FloorSpecialCase> {
 	// TODO perhaps it means invalid audio packet if reading the nonzero
 	// flag doesn't succeed bc end of packet. Perhaps it does not.
 	if !try!(rdr.read_bit_flag()) {
-		try!(Err(()));
+		return Err(FloorSpecialCase::Unused);
 	}
-	let mut floor1_y = Vec::new();
+	floor1_y.clear();
 	let v = &[256, 128, 86, 64];
 	let range = v[(fl.floor1_multiplier - 1) as usize];
 	let b = ::ilog(range - 1);
@@ -247,7 +385,7 @@ fn floor_one_decode(rdr :&mut BitpackCursor, codebooks :&[Codebook],
 			}
 		}
 	}
-	return Ok(floor1_y);
+	return Ok(());
 }
 
 fn extr_neighbor<F>(v :&[u32], max_idx :usize,
@@ -282,12 +420,12 @@ fn extr_neighbor<F>(v :&[u32], max_idx :usize,
 	(min_idx + offset, max_neighbor)
 }
 
-fn low_neighbor(v :&[u32], x :usize) -> (usize, u32) {
+pub(crate) fn low_neighbor(v :&[u32], x :usize) -> (usize, u32) {
 	extr_neighbor(v, x, |a, b| a.cmp(&b), "smaller")
 }
 
 
-fn high_neighbor(v :&[u32], x :usize) -> (usize, u32) {
+pub(crate) fn high_neighbor(v :&[u32], x :usize) -> (usize, u32) {
 	extr_neighbor(v, x, |a, b| b.cmp(&a), "bigger")
 }
 
@@ -303,6 +441,26 @@ fn test_low_neighbor() {
 }
 
 
+#[test]
+fn test_compute_floor1_neighbors() {
+	use header_cached::compute_floor1_neighbors;
+	let lists :&[&[u32]] = &[
+		&[0, 128, 64, 32, 96, 16, 48, 80, 112],
+		&[0, 256, 7, 3, 200, 100, 1, 2, 255],
+		&[0, 1],
+	];
+	for v in lists {
+		let neighbors = compute_floor1_neighbors(v);
+		assert_eq!(neighbors.len(), v.len() - 2);
+		for (i, &(low, high)) in neighbors.iter().enumerate() {
+			assert_eq!(low, low_neighbor(v, i + 2).0);
+			assert_eq!(high, high_neighbor(v, i + 2).0);
+		}
+	}
+	assert_eq!(compute_floor1_neighbors(&[0, 128, 64, 32, 96]),
+		[(0, 1), (0, 2), (2, 1)]);
+}
+
 #[test]
 fn test_high_neighbor() {
 	let v = [1, 4, 2, 3, 6, 5];
@@ -388,19 +546,21 @@ fn test_render_point() {
 	assert_eq!(render_point(70, 20, 128, 67, 90), 36);
 }
 
-fn floor_one_curve_compute_amplitude(floor1_y :&[u32], fl :&FloorTypeOne) -> (Vec<u32>, Vec<bool>) {
+fn floor_one_curve_compute_amplitude(floor1_y :&[u32], fl :&FloorTypeOne,
+		floor1_final_y :&mut Vec<u32>, floor1_step2_flag :&mut Vec<bool>) {
 	let v = &[256, 128, 86, 64];
 	let range = v[(fl.floor1_multiplier - 1) as usize] as i32;
-	let mut floor1_step2_flag = Vec::new();
+	floor1_step2_flag.clear();
 	floor1_step2_flag.push(true);
 	floor1_step2_flag.push(true);
-	let mut floor1_final_y = Vec::new();
+	floor1_final_y.clear();
 	floor1_final_y.push(floor1_y[0]);
 	floor1_final_y.push(floor1_y[1]);
 
-	for (i, el) in fl.floor1_x_list.iter().enumerate().skip(2) {
-		let cur_low_neighbor = low_neighbor(&fl.floor1_x_list, i);
-		let cur_high_neighbor = high_neighbor(&fl.floor1_x_list, i);
+	for ((i, el), &(low_idx, high_idx)) in fl.floor1_x_list.iter().enumerate().skip(2)
+			.zip(fl.floor1_neighbors.iter()) {
+		let cur_low_neighbor = (low_idx, fl.floor1_x_list[low_idx]);
+		let cur_high_neighbor = (high_idx, fl.floor1_x_list[high_idx]);
 		let predicted = render_point(
 			cur_low_neighbor.1, floor1_final_y[cur_low_neighbor.0],
 			cur_high_neighbor.1, floor1_final_y[cur_high_neighbor.0], *el) as i32;
@@ -428,10 +588,9 @@ fn floor_one_curve_compute_amplitude(floor1_y :&[u32], fl :&FloorTypeOne) -> (Ve
 		}
 	}
 	// Clamp all entries of floor1_final_y to range
-	for el in &mut floor1_final_y {
+	for el in floor1_final_y.iter_mut() {
 		*el = min(range as u32 - 1, *el);
 	}
-	return (floor1_final_y, floor1_step2_flag);
 }
 
 static FLOOR1_INVERSE_DB_TABLE :&[f32] = &[
@@ -500,6 +659,23 @@ static FLOOR1_INVERSE_DB_TABLE :&[f32] = &[
 	0.64356699,    0.68538959,    0.72993007,    0.77736504,
 	0.82788260,    0.88168307,    0.9389798,     1.];
 
+/// Returns the entry of the floor1 inverse dB table at the given index
+///
+/// This is the table from section 10.1 of the spec, that maps the
+/// values of the rendered floor1 curve to the amplitudes
+/// the residue gets multiplied with. The table has 256 entries,
+/// so every index is valid.
+pub fn floor1_inverse_db(index :u8) -> f32 {
+	FLOOR1_INVERSE_DB_TABLE[index as usize]
+}
+
+#[test]
+fn test_floor1_inverse_db() {
+	assert_eq!(FLOOR1_INVERSE_DB_TABLE.len(), 256);
+	assert_eq!(floor1_inverse_db(255), 1.0);
+	assert_eq!(floor1_inverse_db(0), 1.0649863e-07);
+}
+
 fn render_line(x0 :u32, y0 :u32, x1 :u32, y1 :u32, v :&mut Vec<u32>) {
 	// TODO find out whether the type choices in this method are okay
 	let dy = y1 as i32 - y0 as i32;
@@ -523,8 +699,9 @@ fn render_line(x0 :u32, y0 :u32, x1 :u32, y1 :u32, v :&mut Vec<u32>) {
 	}
 }
 
-fn floor_one_curve_synthesis(floor1_final_y :Vec<u32>,
-		floor1_step2_flag :Vec<bool>, fl :&FloorTypeOne, n :u16) -> Vec<f32> {
+fn floor_one_curve_synthesis(floor1_final_y :&[u32],
+		floor1_step2_flag :&[bool], fl :&FloorTypeOne, n :u16,
+		floor :&mut Vec<u32>, out :&mut Vec<f32>) {
 	let floor1_final_y_s = |i :usize| { floor1_final_y[fl.floor1_x_list_sorted[i].0] };
 	let floor1_x_list_s = |i :usize| { fl.floor1_x_list_sorted[i].1 };
 	let floor1_step2_flag_s = |i :usize| {
@@ -532,56 +709,65 @@ fn floor_one_curve_synthesis(floor1_final_y :Vec<u32>,
 	let mut hx = 0;
 	let mut lx = 0;
 	let mut hy = 0;
-	let mut floor = Vec::with_capacity(n as usize);
+	floor.clear();
 	let mut ly = floor1_final_y_s(0) * fl.floor1_multiplier as u32;
 	for i in 1 .. fl.floor1_x_list.len() {
 		if floor1_step2_flag_s(i) {
 			hy = floor1_final_y_s(i) * fl.floor1_multiplier as u32;
 			hx = floor1_x_list_s(i);
-			render_line(lx, ly, hx, hy, &mut floor);
+			render_line(lx, ly, hx, hy, floor);
 			lx = hx;
 			ly = hy;
 		}
 	}
 	if hx < n as u32 {
-		render_line(hx, hy, n as u32, hy, &mut floor);
+		render_line(hx, hy, n as u32, hy, floor);
 	} else if hx > n as u32 {
 		floor.truncate(n as usize);
 	}
 
-	floor.into_iter()
-		.map(|idx| FLOOR1_INVERSE_DB_TABLE[idx as usize])
-		.collect()
+	out.clear();
+	out.extend(floor.iter().map(|idx| FLOOR1_INVERSE_DB_TABLE[*idx as usize]));
+}
+
+/// Returns the floor of the given channel
+fn channel_floor<'a>(mapping :&Mapping, floors :&'a [Floor], channel :usize) -> &'a Floor {
+	let submap_number = mapping.mapping_mux[channel] as usize;
+	let floor_number = mapping.mapping_submap_floors[submap_number];
+	&floors[floor_number as usize]
 }
 
-fn floor_decode<'a>(rdr :&mut BitpackCursor,
+// Stores the decoded floors in scratch.floors, and
+// their data in the per channel floor buffers of scratch.
+fn floor_decode(rdr :&mut BitpackCursor,
 		ident :&IdentHeader, mapping :&Mapping, codebooks :&[Codebook],
-		floors :&'a [Floor]) -> Result<Vec<DecodedFloor<'a>>, ()> {
-	let mut decoded_floor_infos = Vec::with_capacity(ident.audio_channels as usize);
-	for i in 0 .. ident.audio_channels as usize {
-		let submap_number = mapping.mapping_mux[i] as usize;
-		let floor_number = mapping.mapping_submap_floors[submap_number];
-		let floor = &floors[floor_number as usize];
+		floors :&[Floor], scratch :&mut DecodeScratch) -> Result<(), ()> {
+	let channels = ident.audio_channels as usize;
+	scratch.floors.clear();
+	scratch.floor0_coefficients.resize_with(channels, Vec::new);
+	scratch.floor1_y.resize_with(channels, Vec::new);
+	for i in 0 .. channels {
 		use self::FloorSpecialCase::*;
-		let floor_res = match floor {
+		let floor_res = match channel_floor(mapping, floors, i) {
 			&Floor::TypeZero(ref fl) => {
-				match floor_zero_decode(rdr, codebooks, fl) {
-					Ok((coeff, amp)) => DecodedFloor::TypeZero(coeff, amp, fl),
+				match floor_zero_decode(rdr, codebooks, fl,
+						&mut scratch.floor0_coefficients[i]) {
+					Ok(amp) => DecodedFloor::TypeZero(amp),
 					Err(Unused) => DecodedFloor::Unused,
 					Err(PacketUndecodable) => try!(Err(())),
 				}
 			},
 			&Floor::TypeOne(ref fl) => {
-				match floor_one_decode(rdr, codebooks, fl) {
-					Ok(dfl) => DecodedFloor::TypeOne(dfl, fl),
+				match floor_one_decode(rdr, codebooks, fl, &mut scratch.floor1_y[i]) {
+					Ok(()) => DecodedFloor::TypeOne,
 					Err(Unused) => DecodedFloor::Unused,
 					Err(PacketUndecodable) => try!(Err(())),
 				}
 			},
 		};
-		decoded_floor_infos.push(floor_res);
+		scratch.floors.push(floor_res);
 	}
-	return Ok(decoded_floor_infos);
+	return Ok(());
 }
 
 fn residue_packet_read_partition(rdr :&mut BitpackCursor, codebook :&Codebook,
@@ -617,10 +803,16 @@ fn residue_packet_read_partition(rdr :&mut BitpackCursor, codebook :&Codebook,
 	Ok(())
 }
 
+// Adds the decoded residue into `vectors`, which consists of
+// blocks of n/2 scalars, one for each channel. Each entry of
+// `channels` consists of the block number the channel is stored
+// at and its do_not_decode flag.
 fn residue_packet_decode_inner(rdr :&mut BitpackCursor, cur_blocksize :u16,
-		do_not_decode_flag :&[bool], resid :&Residue, codebooks :&[Codebook]) -> Result<Vec<f32>, ()> {
+		channels :&[(usize, bool)], resid :&Residue,
+		codebooks :&[Codebook], vectors :&mut [f32], classifications :&mut Vec<u32>)
+		-> Result<(), ()> {
 
-	let ch = do_not_decode_flag.len();
+	let ch = channels.len();
 	let actual_size = (cur_blocksize / 2) as usize;
 
 	// Older versions of the spec say max() here,
@@ -636,12 +828,9 @@ fn residue_packet_decode_inner(rdr :&mut BitpackCursor, cur_blocksize :u16,
 	let partitions_to_read = n_to_read / resid.residue_partition_size as usize;
 	let residue_classbok_ht = &cur_codebook.codebook_huffman_tree;
 
-	// Allocate and zero all vectors that will be returned
-	let mut vectors = vec![0.; ch * actual_size];
-
 	if n_to_read == 0 {
 		// No residue to decode
-		return Ok(vectors);
+		return Ok(());
 	}
 
 	if classwords_per_codeword == 0 {
@@ -651,24 +840,22 @@ fn residue_packet_decode_inner(rdr :&mut BitpackCursor, cur_blocksize :u16,
 	}
 
 	'pseudo_return: loop {
-		// ENdofpacketisnOrmal macro. Local replacement for try.
-		macro_rules! eno {
-			($expr:expr) => (match $expr {
-				$crate::std::result::Result::Ok(val) => val,
-				$crate::std::result::Result::Err(_) => break 'pseudo_return,
-			})
-		}
 		let cl_stride :usize = partitions_to_read + classwords_per_codeword;
-		let mut classifications = vec![0; ch as usize * cl_stride];
+		classifications.clear();
+		classifications.resize(ch as usize * cl_stride, 0);
 		for pass in 0 .. 8 {
 			let mut partition_count = 0;
 			while partition_count < partitions_to_read {
 				if pass == 0 {
-					for (j, do_not_decode) in do_not_decode_flag.iter().enumerate() {
-						if *do_not_decode {
+					for (j, &(_, do_not_decode)) in channels.iter().enumerate() {
+						if do_not_decode {
 							continue;
 						}
-						let mut temp = eno!(rdr.read_huffman(residue_classbok_ht));
+						let mut temp = match rdr.read_huffman(residue_classbok_ht) {
+							Ok(v) => v,
+							Err(HuffmanReadErr::EndOfPacket) => break 'pseudo_return,
+							Err(HuffmanReadErr::EmptyTree) => try!(Err(())),
+						};
 						for i in (0 .. classwords_per_codeword).rev() {
 							classifications[j * cl_stride + i + partition_count] =
 							temp % resid.residue_classifications as u32;
@@ -680,12 +867,13 @@ fn residue_packet_decode_inner(rdr :&mut BitpackCursor, cur_blocksize :u16,
 					if partition_count >= partitions_to_read {
 						break;
 					}
-					for (j, do_not_decode) in do_not_decode_flag.iter().enumerate() {
-						if *do_not_decode {
+					for (j, &(_, do_not_decode)) in channels.iter().enumerate() {
+						if do_not_decode {
 							continue;
 						}
 						let offs = limit_residue_begin + partition_count * resid.residue_partition_size as usize;
-						let vec_j_offs = &mut vectors[(j * actual_size + offs) .. ((j + 1) * actual_size)];
+						let c = channels[j].0;
+						let vec_j_offs = &mut vectors[(c * actual_size + offs) .. ((c + 1) * actual_size)];
 						let vqclass = classifications[j * cl_stride + partition_count] as usize;
 						let vqbook_opt = resid.residue_books[vqclass].get_val(pass);
 						if let Some(vqbook) = vqbook_opt {
@@ -701,6 +889,7 @@ fn residue_packet_decode_inner(rdr :&mut BitpackCursor, cur_blocksize :u16,
 										EndOfPacket => break 'pseudo_return,
 										NoVqLookupForCodebook =>
 											panic!("Codebook must have a value mapping"),
+										EmptyCodebook | LookupOutOfBounds => try!(Err(())),
 									}
 								},
 							}
@@ -713,21 +902,25 @@ fn residue_packet_decode_inner(rdr :&mut BitpackCursor, cur_blocksize :u16,
 		break;
 	}
 
-	return Ok(vectors);
+	return Ok(());
 }
 
 
 // Ok means "fine" (or end of packet, but thats "fine" too!),
 // Err means "not fine" -- the whole packet must be discarded
+//
+// The decoded vectors are stored directly at their channel's
+// position inside scratch.residue, which must be zeroed before.
 fn residue_packet_decode(rdr :&mut BitpackCursor, cur_blocksize :u16,
-		do_not_decode_flag :&[bool], resid :&Residue, codebooks :&[Codebook]) -> Result<Vec<f32>, ()> {
+		channels :&[(usize, bool)], resid :&Residue,
+		codebooks :&[Codebook], scratch :&mut DecodeScratch) -> Result<(), ()> {
 
-	let ch = do_not_decode_flag.len();
+	let ch = channels.len();
 	let vec_size = (cur_blocksize / 2) as usize;
 
 	if resid.residue_type == 2 {
 		let mut to_decode_found = false;
-		for do_not_decode in do_not_decode_flag {
+		for &(_, do_not_decode) in channels {
 			if !do_not_decode {
 				to_decode_found = true;
 				break;
@@ -736,26 +929,32 @@ fn residue_packet_decode(rdr :&mut BitpackCursor, cur_blocksize :u16,
 		if !to_decode_found {
 			// Don't attempt to decode, but return vectors,
 			// as required per spec only residue 2 has this.
-			return Ok(vec![0.; ch * vec_size]);
+			// They have been zeroed already.
+			return Ok(());
 		} else {
-			// Construct a do_not_decode flag array
-			let c_do_not_decode_flag = [false];
-
-			let vectors = try!(residue_packet_decode_inner(rdr,
-				cur_blocksize * ch as u16, &c_do_not_decode_flag,
-				resid, codebooks));
-
-			// Post decode step
-			let mut vectors_deinterleaved = Vec::with_capacity(ch * vec_size);
-			for j in 0 .. ch {
-				let iter = vectors.chunks(ch).map(|chunk| chunk[j]);
-				vectors_deinterleaved.extend(iter);
+			// Decode as a single channel that is to be decoded
+			let c_channels = [(0, false)];
+
+			let vectors = &mut scratch.residue_interleaved;
+			vectors.clear();
+			vectors.resize(ch * vec_size, 0.);
+			try!(residue_packet_decode_inner(rdr,
+				cur_blocksize * ch as u16, &c_channels,
+				resid, codebooks, vectors, &mut scratch.classifications));
+
+			// Post decode step: deinterleave into the channels
+			for (j, &(c, _)) in channels.iter().enumerate() {
+				let dest = &mut scratch.residue[c * vec_size .. (c + 1) * vec_size];
+				for (d, chunk) in dest.iter_mut().zip(vectors.chunks(ch)) {
+					*d = chunk[j];
+				}
 			}
-			return Ok(vectors_deinterleaved);
+			return Ok(());
 		}
 	} else {
 		return residue_packet_decode_inner(rdr, cur_blocksize,
-			do_not_decode_flag, resid, codebooks);
+			channels, resid, codebooks,
+			&mut scratch.residue, &mut scratch.classifications);
 	}
 }
 
@@ -778,15 +977,35 @@ fn inverse_couple(m :f32, a :f32) -> (f32, f32) {
 
 // TODO this is probably slower than a replacement of
 // this function in unsafe code, no idea
-fn dual_mut_idx<T>(v :&mut [T], idx_a :usize, idx_b :usize)
-		-> (&mut T, &mut T) {
+/// Returns mutable references to the chunks
+/// with the given indices of the slice
+fn dual_mut_chunk<T>(v :&mut [T], chunk_len :usize, idx_a :usize, idx_b :usize)
+		-> (&mut [T], &mut [T]) {
 	assert_ne!(idx_a, idx_b, "not allowed, indices must be different!");
 
-	let range = if idx_a < idx_b { idx_a..idx_b+1 } else { idx_b..idx_a+1 };
-	let segment = &mut v[range];
-	let (first, rest) = segment.split_first_mut().unwrap();
-	let (last, _) = rest.split_last_mut().unwrap();
-	(first, last)
+	let (lo, hi) = if idx_a < idx_b { (idx_a, idx_b) } else { (idx_b, idx_a) };
+	let (first, rest) = v.split_at_mut(hi * chunk_len);
+	let lo_chunk = &mut first[lo * chunk_len .. (lo + 1) * chunk_len];
+	let hi_chunk = &mut rest[.. chunk_len];
+	if idx_a < idx_b {
+		(lo_chunk, hi_chunk)
+	} else {
+		(hi_chunk, lo_chunk)
+	}
+}
+
+#[test]
+fn test_dual_mut_chunk() {
+	let mut v = [0, 1, 2, 3, 4, 5, 6, 7];
+	{
+		let (a, b) = dual_mut_chunk(&mut v, 2, 3, 1);
+		assert_eq!((&*a, &*b), (&[6, 7][..], &[2, 3][..]));
+		a[0] = 42;
+		b[1] = 43;
+	}
+	assert_eq!(v, [0, 1, 2, 43, 4, 5, 42, 7]);
+	let (a, b) = dual_mut_chunk(&mut v, 4, 0, 1);
+	assert_eq!((&*a, &*b), (&[0, 1, 2, 43][..], &[4, 5, 42, 7][..]));
 }
 
 fn dct_iv_slow(buffer :&mut [f32]) {
@@ -824,6 +1043,82 @@ fn inverse_mdct_slow(buffer :&mut [f32]) {
 	}
 }
 
+/// Inverse MDCT that does all of its computation in f64
+///
+/// Used instead of the fast f32 implementation if the
+/// `f64_intermediate` feature is enabled. This is a direct
+/// O(n^2) implementation, so it is very slow, but it is useful
+/// to obtain a high-accuracy reference output.
+///
+/// Takes the n/2 bins of the spectrum in `input`, and writes the
+/// n output samples into `buffer`. `mcos` is the cosine table
+/// obtained from `compute_mdct_cos_f64` for the block size.
+///
+/// Note that the output is not bit-identical to any other
+/// decoder, libvorbis included, as those use f32 for the IMDCT.
+fn inverse_mdct_f64(mcos :&[f64], input :&[f64], buffer :&mut [f32]) {
+	let n = buffer.len();
+	let n4 = n >> 2;
+	let n2 = n >> 1;
+	let n3_4 = n - n4;
+	debug_assert_eq!(input.len(), n2);
+	debug_assert_eq!(mcos.len(), 8 * n2);
+
+	// DCT-IV of the input
+	let nmask = (n2 << 3) - 1;
+	let mut temp = vec![0.0f64; n2];
+	for (i, t) in temp.iter_mut().enumerate() {
+		let mut acc = 0.;
+		for (j, x) in input.iter().enumerate() {
+			acc += *x * mcos[((2 * i + 1)*(2*j+1)) & nmask];
+		}
+		*t = acc;
+	}
+
+	for i in 0 .. n4 {
+		buffer[i] = temp[i + n4] as f32;
+	}
+	for i in n4 .. n3_4 {
+		buffer[i] = -temp[n3_4 - i - 1] as f32;
+	}
+	for i in n3_4 .. n {
+		buffer[i] = -temp[i - n3_4] as f32;
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_imdct_f64() {
+	use imdct_test::*;
+	use header_cached::compute_mdct_cos_f64;
+	let input = IMDCT_INPUT_TEST_ARR_1.iter().map(|x| *x as f64).collect::<Vec<_>>();
+	let mut arr_1 = vec![0.0; 2 * input.len()];
+	inverse_mdct_f64(&compute_mdct_cos_f64(8), &input, &mut arr_1);
+	let mismatches = fuzzy_compare_array(
+		&arr_1, &IMDCT_OUTPUT_TEST_ARR_1,
+		0.00005, true);
+	let mismatches_limit = 0;
+	if mismatches > mismatches_limit {
+		panic!("Numer of mismatches {} was larger than limit of {}",
+			mismatches, mismatches_limit);
+	}
+
+	// Compare with the f32 implementation on a larger block
+	let blocksize = 11;
+	let n = 1 << blocksize;
+	let input = (0 .. n / 2)
+		.map(|i| ((i * 7919) % 263) as f32 / 263.0 - 0.5)
+		.collect::<Vec<f32>>();
+	let mut arr_f32 = imdct_prepare(&input);
+	let mut arr_f64 = arr_f32.clone();
+	let cbd = ::header_cached::CachedBlocksizeDerived::from_blocksize(blocksize);
+	::imdct::inverse_mdct(&cbd, &mut arr_f32, blocksize);
+	let input = input.iter().map(|x| *x as f64).collect::<Vec<_>>();
+	inverse_mdct_f64(&compute_mdct_cos_f64(blocksize), &input, &mut arr_f64);
+	let mismatches = fuzzy_compare_array(&arr_f32, &arr_f64, 0.001, true);
+	assert_eq!(mismatches, 0);
+}
+
 #[cfg(test)]
 #[test]
 fn test_imdct_slow() {
@@ -840,23 +1135,194 @@ fn test_imdct_slow() {
 	}
 }
 
+/// Returns `count` empty Vecs with the given capacity each
+fn vecs_with_capacity<T>(count :usize, capacity :usize) -> Vec<Vec<T>> {
+	(0 .. count).map(|_| Vec::with_capacity(capacity)).collect()
+}
+
 /// The right part of the previous window
 ///
 /// This is the only state that needs to be changed
 /// once the headers are read.
 #[derive(Clone)]
 pub struct PreviousWindowRight {
-	data :Option<Vec<Vec<f32>>>,
+	/// The right halves of the last packet, if `has_data` is set
+	data :Vec<Vec<f32>>,
+	has_data :bool,
+	/// The buffers of the halves from two packets ago,
+	/// kept to reuse their allocations
+	spare :Vec<Vec<f32>>,
+	stage_times :StageTimes,
+	last_packet_silent :bool,
+}
+
+// Manual impl to leave out the window data
+impl fmt::Debug for PreviousWindowRight {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		fmt.debug_struct("PreviousWindowRight")
+			.field("is_empty", &self.is_empty())
+			.field("last_packet_silent", &self.last_packet_silent)
+			.finish_non_exhaustive()
+	}
 }
 
 impl PreviousWindowRight {
 	/// Initialisation for new streams
 	pub fn new() -> Self {
-		return PreviousWindowRight{ data : None };
+		return PreviousWindowRight{
+			data : Vec::new(),
+			has_data : false,
+			spare : Vec::new(),
+			stage_times : StageTimes::default(),
+			last_packet_silent : false,
+		};
+	}
+	/// Initialisation for new streams, with the buffers
+	/// allocated for halves of the given length
+	pub fn with_capacity(channels :usize, half_len :usize) -> Self {
+		return PreviousWindowRight{
+			data : vecs_with_capacity(channels, half_len),
+			has_data : false,
+			spare : vecs_with_capacity(channels, half_len),
+			stage_times : StageTimes::default(),
+			last_packet_silent : false,
+		};
+	}
+	/// Resets the state to the uninitialized one,
+	/// keeping the allocations of the buffers
+	pub fn reset(&mut self) {
+		self.has_data = false;
+		self.stage_times = StageTimes::default();
+		self.last_packet_silent = false;
 	}
 	/// If the state is still uninitialized
 	pub fn is_empty(&self) -> bool {
-		self.data.is_none()
+		!self.has_data
+	}
+	/// Returns the time the stages of decoding
+	/// the last packet have taken
+	///
+	/// The times are only measured if the `profiling`
+	/// feature is enabled. Otherwise, they are all zero.
+	pub fn last_stage_times(&self) -> StageTimes {
+		self.stage_times
+	}
+	/// Returns whether the floors of all channels
+	/// were unused in the last decoded packet
+	///
+	/// In that case, the residue decode is skipped, and the
+	/// spectrum of the packet is entirely zero. Note that the
+	/// samples obtained from such a packet can still be nonzero,
+	/// due to the overlap with the previous packet.
+	pub fn last_packet_was_silent(&self) -> bool {
+		self.last_packet_silent
+	}
+}
+
+/// Time the stages of decoding a packet have taken
+///
+/// Obtained via `PreviousWindowRight::last_stage_times`.
+/// The times are only measured if the `profiling` feature is enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageTimes {
+	/// Decoding of the floors, and the synthesis of the floor curves,
+	/// including their multiplication with the residue vectors
+	pub floor :Duration,
+	/// Decoding of the residue vectors
+	pub residue :Duration,
+	/// Inverse channel coupling
+	pub coupling :Duration,
+	/// Inverse MDCT
+	pub imdct :Duration,
+	/// Windowing and overlap add with the previous packet
+	pub overlap_add :Duration,
+}
+
+/// Measures the time between its laps
+///
+/// Without the `profiling` feature, no time is measured,
+/// and all laps take zero time.
+struct StageTimer {
+	#[cfg(feature = "profiling")]
+	last :Instant,
+}
+
+impl StageTimer {
+	fn start() -> Self {
+		StageTimer {
+			#[cfg(feature = "profiling")]
+			last : Instant::now(),
+		}
+	}
+	/// Returns the time since the last lap,
+	/// or since the start for the first lap
+	#[cfg(feature = "profiling")]
+	fn lap(&mut self) -> Duration {
+		let now = Instant::now();
+		let elapsed = now - self.last;
+		self.last = now;
+		elapsed
+	}
+	#[cfg(not(feature = "profiling"))]
+	fn lap(&mut self) -> Duration {
+		Duration::default()
+	}
+}
+
+/**
+Returns whether a packet uses the long block size.
+
+Like `get_decoded_sample_count`, this operation is very cheap
+and doesn't involve actual decoding of the packet.
+*/
+pub fn get_packet_blockflag(setup :&SetupHeader, packet :&[u8])
+		-> Result<bool, AudioReadError> {
+	let mode_number = try!(get_packet_mode(setup, packet));
+	Ok(setup.modes[mode_number as usize].mode_blockflag)
+}
+
+/**
+Returns the number of the mode a packet uses.
+
+Like `get_decoded_sample_count`, this operation is very cheap
+and doesn't involve actual decoding of the packet.
+The returned mode number is guaranteed to exist in the setup header.
+*/
+pub fn get_packet_mode(setup :&SetupHeader, packet :&[u8])
+		-> Result<u8, AudioReadError> {
+	let mut rdr = BitpackCursor::new(packet);
+	if try!(rdr.read_bit_flag()) {
+		try!(Err(AudioReadError::AudioIsHeader));
+	}
+	let mode_number = try!(rdr.read_dyn_u8(ilog(setup.modes.len() as u64 - 1)));
+	if mode_number as usize >= setup.modes.len() {
+		try!(Err(AudioReadError::AudioBadFormat));
+	}
+	Ok(mode_number)
+}
+
+/// Transition between the block sizes of two consecutive packets
+///
+/// The block sizes determine the shape of the window
+/// used for overlapping the two packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockTransition {
+	LongToLong,
+	LongToShort,
+	ShortToLong,
+	ShortToShort,
+}
+
+impl BlockTransition {
+	/// Constructs the transition from the blockflags
+	/// of the previous and the current packet
+	pub fn from_blockflags(prev_long :bool, cur_long :bool) -> Self {
+		match (prev_long, cur_long) {
+			(true, true) => BlockTransition::LongToLong,
+			(true, false) => BlockTransition::LongToShort,
+			(false, true) => BlockTransition::ShortToLong,
+			(false, false) => BlockTransition::ShortToShort,
+		}
 	}
 }
 
@@ -878,7 +1344,11 @@ pub fn get_decoded_sample_count(ident :&IdentHeader, setup :&SetupHeader, packet
 		try!(Err(AudioReadError::AudioIsHeader));
 	}
 	let mode_number = try!(rdr.read_dyn_u8(ilog(setup.modes.len() as u64 - 1)));
-	let mode = &setup.modes[mode_number as usize];
+	let mode = if let Some(mode) = setup.modes.get(mode_number as usize) {
+		mode
+	} else {
+		try!(Err(AudioReadError::AudioBadFormat))
+	};
 	let bs = if mode.mode_blockflag { ident.blocksize_1 } else { ident.blocksize_0 };
 	let n :u16 = 1 << bs;
 	let previous_next_window_flag = if mode.mode_blockflag {
@@ -908,17 +1378,43 @@ pub fn get_decoded_sample_count(ident :&IdentHeader, setup :&SetupHeader, packet
 	Ok((right_win_start - left_win_start) as usize)
 }
 
-/**
-Main audio packet decoding function
-
-Pass your info to this function to get your raw packet data decoded.
+/// The frequency domain data of a packet
+///
+/// Together with the windowing info, this is everything
+/// needed to synthesize the time domain samples.
+struct DecodedSpectra {
+	audio_spectri :Vec<Vec<f32>>,
+	/// The spectra computed in f64, with the same values as
+	/// audio_spectri apart from the rounding. Only filled if
+	/// the `f64_intermediate` feature is enabled.
+	spectri_f64 :Vec<Vec<f64>>,
+	blockflag :bool,
+	previous_next_window_flag :Option<(bool, bool)>,
+	/// Times of the stages done so far
+	stage_times :StageTimes,
+	/// Whether the floors of all channels are unused
+	all_floors_unused :bool,
+}
 
-Panics if the passed PreviousWindowRight struct doesn't match the info
-from the ident header.
-*/
-pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupHeader, packet :&[u8], pwr :&mut PreviousWindowRight)
-		-> Result<S, AudioReadError> {
+/// Decodes a packet up to (but excluding) the inverse MDCT
+///
+/// The spectra are stored in the passed `audio_spectri`,
+/// reusing the allocations of the contained Vecs.
+//
+// If floor_only is set, the residue is not decoded, and
+// the spectra consist of the floor curves alone.
+fn decode_spectra(ident :&IdentHeader, setup :&SetupHeader, packet :&[u8],
+		scratch :&mut DecodeScratch, audio_spectri :Vec<Vec<f32>>, floor_only :bool)
+		-> Result<DecodedSpectra, AudioReadError> {
 	let mut rdr = BitpackCursor::new(packet);
+	decode_spectra_rdr(ident, setup, &mut rdr, scratch, audio_spectri, floor_only)
+}
+
+fn decode_spectra_rdr(ident :&IdentHeader, setup :&SetupHeader, rdr :&mut BitpackCursor,
+		scratch :&mut DecodeScratch, mut audio_spectri :Vec<Vec<f32>>, floor_only :bool)
+		-> Result<DecodedSpectra, AudioReadError> {
+	let mut timer = StageTimer::start();
+	let mut stage_times = StageTimes::default();
 	if try!(rdr.read_bit_flag()) {
 		try!(Err(AudioReadError::AudioIsHeader));
 	}
@@ -937,12 +1433,15 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 		None
 	};
 	// Decode the floors
-	let decoded_floor_infos = try!(floor_decode(&mut rdr, ident, mapping,
-		&setup.codebooks, &setup.floors));
+	match floor_decode(rdr, ident, mapping, &setup.codebooks, &setup.floors, scratch) {
+		Ok(()) => (),
+		Err(_) => return Err(AudioReadError::AudioBadFormat),
+	}
+	stage_times.floor = timer.lap();
 
 	// Now calculate the no_residue vector
 	let mut no_residue = TinyVec::<[bool; 32]>::new();
-	for fl in &decoded_floor_infos {
+	for fl in &scratch.floors {
 		no_residue.push(fl.is_unused());
 	}
 	// and also propagate
@@ -955,43 +1454,42 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 	}
 
 	// Residue decode.
-	let mut residue_vectors = vec![vec![]; mapping.mapping_mux.len()];
 	// Helper variable
 	let resid_vec_len = (n / 2) as usize;
-	for (i, &residue_number) in mapping.mapping_submap_residues.iter().enumerate() {
-		let mut do_not_decode_flag = TinyVec::<[bool; 32]>::new();
+	// The residue vectors of all channels, each occupying n/2 scalars.
+	// The residue decoding writes directly to the part of each channel.
+	scratch.residue.clear();
+	scratch.residue.resize(mapping.mapping_mux.len() * resid_vec_len, 0.);
+	let submap_residues = if floor_only {
+		&[]
+	} else {
+		&mapping.mapping_submap_residues[..]
+	};
+	for (i, &residue_number) in submap_residues.iter().enumerate() {
+		let mut channels = TinyVec::<[(usize, bool); 32]>::new();
 		for (j, &mapping_mux_j) in mapping.mapping_mux.iter().enumerate() {
 			if mapping_mux_j as usize == i {
-				do_not_decode_flag.push(no_residue[j]);
+				channels.push((j, no_residue[j]));
 			}
 		}
 		let cur_residue = &setup.residues[residue_number as usize];
-		let vectors = match residue_packet_decode(&mut rdr, n,
-				&do_not_decode_flag, cur_residue, &setup.codebooks) {
-			Ok(v) => v,
+		match residue_packet_decode(rdr, n,
+				&channels, cur_residue, &setup.codebooks, scratch) {
+			Ok(()) => (),
 			Err(_) => return Err(AudioReadError::AudioBadFormat),
 		};
-		// The vectors Vec<f32> now contains the do_not_decode_flag.len()
-		// many decoded residue vectors, each vector occupying n/2 scalars.
-		let mut ch = 0;
-		for (j, &mapping_mux_j) in mapping.mapping_mux.iter().enumerate() {
-			if mapping_mux_j as usize == i {
-				// TODO get rid of this copy somehow...
-				let vec_at_ch = &vectors[resid_vec_len * ch .. resid_vec_len * (ch + 1)];
-				residue_vectors[j].clear();
-				residue_vectors[j].extend_from_slice(vec_at_ch);
-				ch += 1;
-			}
-		}
 	}
+	let residue_vectors = &mut scratch.residue[..];
+
+	stage_times.residue = timer.lap();
 
-	record_residue_pre_inverse!(residue_vectors);
+	record_residue_pre_inverse!(residue_vectors.chunks(resid_vec_len).collect::<Vec<_>>());
 
 	// Inverse coupling
 	for (&mag, &angle) in
 			mapping.mapping_magnitudes.iter().rev().zip(mapping.mapping_angles.iter().rev()) {
-		let (mag_vector, angle_vector) = dual_mut_idx(&mut residue_vectors,
-			mag as usize, angle as usize);
+		let (mag_vector, angle_vector) = dual_mut_chunk(residue_vectors,
+			resid_vec_len, mag as usize, angle as usize);
 		for (m, a) in mag_vector.iter_mut().zip(angle_vector.iter_mut()) {
 			// https://github.com/rust-lang/rfcs/issues/372
 			// grumble grumble...
@@ -1001,28 +1499,33 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 		}
 	}
 
-	record_residue_post_inverse!(residue_vectors);
+	stage_times.coupling = timer.lap();
+
+	record_residue_post_inverse!(residue_vectors.chunks(resid_vec_len).collect::<Vec<_>>());
 
 	// Dot product
-	let mut audio_spectri = Vec::with_capacity(ident.audio_channels as usize);
-	for (residue_vector, chan_decoded_floor) in
-			residue_vectors.iter().zip(decoded_floor_infos.iter()) {
-		let mut floor_decoded :Vec<f32> = match chan_decoded_floor {
-			&DecodedFloor::TypeZero(ref coefficients, amplitude, ref fl) => {
-				floor_zero_compute_curve(coefficients, amplitude,
-					fl, mode.mode_blockflag, n / 2)
+	audio_spectri.resize_with(ident.audio_channels as usize, Vec::new);
+	let mut spectri_f64 = Vec::new();
+	for (i, (residue_vector, floor_decoded)) in scratch.residue.chunks(resid_vec_len)
+			.zip(audio_spectri.iter_mut()).enumerate() {
+		match (scratch.floors[i], channel_floor(mapping, &setup.floors, i)) {
+			(DecodedFloor::TypeZero(amplitude), &Floor::TypeZero(ref fl)) => {
+				floor_zero_compute_curve(&scratch.floor0_coefficients[i], amplitude,
+					fl, mode.mode_blockflag, n / 2, floor_decoded);
 			},
-			&DecodedFloor::TypeOne(ref floor_y, ref fl) => {
-				let (floor1_final_y, floor1_step2_flag) =
-					floor_one_curve_compute_amplitude(floor_y, fl);
-				floor_one_curve_synthesis(floor1_final_y,
-					floor1_step2_flag, fl, n / 2)
+			(DecodedFloor::TypeOne, &Floor::TypeOne(ref fl)) => {
+				floor_one_curve_compute_amplitude(&scratch.floor1_y[i], fl,
+					&mut scratch.floor1_final_y, &mut scratch.floor1_step2_flag);
+				floor_one_curve_synthesis(&scratch.floor1_final_y,
+					&scratch.floor1_step2_flag, fl, n / 2,
+					&mut scratch.floor1_curve, floor_decoded);
 			},
-			&DecodedFloor::Unused => {
+			_ => {
 				// Generate zero'd floor of length n/2
-				vec![0.; (n / 2) as usize]
+				floor_decoded.clear();
+				floor_decoded.resize((n / 2) as usize, 0.);
 			},
-		};
+		}
 
 		// The only legal length is n/2.
 		// The implementation should ensure this,
@@ -1031,35 +1534,96 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 		debug_assert_eq!(residue_vector.len(), (n / 2) as usize);
 		debug_assert_eq!(floor_decoded.len(), (n / 2) as usize);
 
+		if cfg!(feature = "f64_intermediate") {
+			spectri_f64.push(if floor_only {
+				floor_decoded.iter().map(|fl_sc| *fl_sc as f64).collect()
+			} else {
+				floor_decoded.iter().zip(residue_vector.iter())
+					.map(|(fl_sc, r_sc)| *fl_sc as f64 * *r_sc as f64)
+					.collect()
+			});
+		}
+		if floor_only {
+			continue;
+		}
 		// Now do the multiplication
 		for (fl_sc, r_sc) in floor_decoded.iter_mut().zip(residue_vector.iter()) {
 			*fl_sc *= *r_sc;
 		}
-		audio_spectri.push(floor_decoded);
 	}
+	stage_times.floor += timer.lap();
 
 	record_pre_mdct!(audio_spectri);
 
+	Ok(DecodedSpectra {
+		audio_spectri,
+		spectri_f64,
+		blockflag : mode.mode_blockflag,
+		previous_next_window_flag,
+		stage_times,
+		all_floors_unused : no_residue.iter().all(|v| *v),
+	})
+}
+
+/// Performs the inverse MDCT and the overlap add for the decoded spectra
+///
+/// Panics if the passed PreviousWindowRight struct doesn't match the info
+/// from the ident header.
+fn synthesize_spectra(ident :&IdentHeader, spectra :DecodedSpectra,
+		pwr :&mut PreviousWindowRight, scratch :&mut DecodeScratch)
+		-> Result<Vec<Vec<f32>>, AudioReadError> {
+	synthesize_spectra_sized((ident.blocksize_0, ident.blocksize_1),
+		&ident.cached_bs_derived, spectra, pwr, scratch)
+}
+
+/// Like synthesize_spectra, but with the block sizes given explicitly
+///
+/// The spectra have to consist of n/2 bins for the given block sizes.
+fn synthesize_spectra_sized(blocksizes :(u8, u8),
+		cached_bs_derived :&[CachedBlocksizeDerived; 2], spectra :DecodedSpectra,
+		pwr :&mut PreviousWindowRight, scratch :&mut DecodeScratch)
+		-> Result<Vec<Vec<f32>>, AudioReadError> {
+	let (blocksize_0, blocksize_1) = blocksizes;
+	let DecodedSpectra {
+		mut audio_spectri,
+		spectri_f64,
+		blockflag,
+		previous_next_window_flag,
+		mut stage_times,
+		all_floors_unused,
+	} = spectra;
+	let mut timer = StageTimer::start();
+	let bs = if blockflag { blocksize_1 } else { blocksize_0 };
+	let n :u16 = 1 << bs;
+
 	// Inverse MDCT
-	for ref mut spectrum in audio_spectri.iter_mut() {
+	for (i, ref mut spectrum) in audio_spectri.iter_mut().enumerate() {
 		let size = (n / 2) as usize;
 		let ext = iter::repeat(0.).take(size);
 		spectrum.extend(ext);
-		let cached_bd = &ident.cached_bs_derived[mode.mode_blockflag as usize];
+		let cached_bd = &cached_bs_derived[blockflag as usize];
 		//::imdct::inverse_mdct_naive(cached_bd, &mut spectrum[..]);
-		::imdct::inverse_mdct(cached_bd, &mut spectrum[..], bs);
+		if cfg!(feature = "f64_intermediate") {
+			inverse_mdct_f64(&cached_bd.mdct_cos_f64, &spectri_f64[i][.. size],
+				&mut spectrum[..]);
+		} else {
+			::imdct::inverse_mdct_with_buf(cached_bd, &mut spectrum[..], bs,
+				&mut scratch.imdct_buf);
+		}
 		//inverse_mdct_slow(&mut spectrum[..]);
 	}
 
+	stage_times.imdct = timer.lap();
+
 	record_post_mdct!(audio_spectri);
 
 	// Compute windowing info for left window
 	let window_center = n >> 1;
 	let (left_win_start, _left_win_end, _left_n, left_n_use_bs1) =
 		if previous_next_window_flag.map_or(true, |(prev_win_flag, _)| prev_win_flag) {
-			(0, window_center, n >> 1, mode.mode_blockflag)
+			(0, window_center, n >> 1, blockflag)
 		} else {
-			let bs_0_exp = 1 << ident.blocksize_0;
+			let bs_0_exp = 1 << blocksize_0;
 			((n - bs_0_exp) >> 2, (n + bs_0_exp) >> 2, bs_0_exp >> 1, false)
 		};
 
@@ -1068,7 +1632,7 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 		if previous_next_window_flag.map_or(true, |(_, next_win_flag)| next_win_flag) {
 			(window_center, n)
 		} else {
-			let bs_0_exp = 1 << ident.blocksize_0;
+			let bs_0_exp = 1 << blocksize_0;
 			((n * 3 - bs_0_exp) >> 2, (n * 3 + bs_0_exp) >> 2)
 		};
 
@@ -1079,15 +1643,21 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 	// Overlap add and store last half
 	// in PreviousWindowRight
 	// Only add if prev has elements.
-	let mut future_prev_halves = Vec::with_capacity(ident.audio_channels as usize);
-	if let Some(prev_data) = pwr.data.take() {
+	// Reuse the allocations of the halves from two packets ago
+	let mut future_prev_halves = ::std::mem::take(&mut pwr.spare);
+	future_prev_halves.resize_with(audio_spectri.len(), Vec::new);
+	// On errors, the state stays uninitialized
+	let has_data = ::std::mem::replace(&mut pwr.has_data, false);
+	if has_data {
+		let prev_data = &pwr.data;
 		// TODO maybe check if prev_n matches blocksize_0 or blocksize_1,
 		// and the channel number. Panic if no match of either.
 		assert_eq!(audio_spectri.len(), prev_data.len());
 
-		let win_slope = &ident.cached_bs_derived[left_n_use_bs1 as usize].window_slope;
+		let win_slope = &cached_bs_derived[left_n_use_bs1 as usize].window_slope;
 
-		for (prev_chan, chan) in prev_data.into_iter().zip(audio_spectri.iter_mut()) {
+		for ((prev_chan, chan), future_prev_half) in prev_data.iter()
+				.zip(audio_spectri.iter_mut()).zip(future_prev_halves.iter_mut()) {
 			let plen = prev_chan.len();
 			let left_win_start = left_win_start as usize;
 			let right_win_start = right_win_start as usize;
@@ -1118,9 +1688,8 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 			}
 
   			// and populate the future previous half
-			let future_prev_half = chan[right_win_start..right_win_end].into();
-
-			future_prev_halves.push(future_prev_half);
+			future_prev_half.clear();
+			future_prev_half.extend_from_slice(&chan[right_win_start..right_win_end]);
 
 			// Remove everything left of the left window start,
 			// by moving the the stuff right to it to the left.
@@ -1138,27 +1707,448 @@ pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupH
 			// if len < right_win_start { len } else { right_win_start }
 		}
 	} else {
-		for chan in audio_spectri.iter_mut() {
-			let mut future_prev_half = Vec::with_capacity(
-				(right_win_end - right_win_start) as usize);
-			for s in &chan[right_win_start as usize .. right_win_end as usize] {
-				future_prev_half.push(*s);
-			}
-			future_prev_halves.push(future_prev_half);
+		for (chan, future_prev_half) in audio_spectri.iter_mut()
+				.zip(future_prev_halves.iter_mut()) {
+			future_prev_half.clear();
+			future_prev_half.extend_from_slice(
+				&chan[right_win_start as usize .. right_win_end as usize]);
 			// If there is no previous window right, we have to discard
 			// the whole packet.
 			chan.truncate(0);
 		}
 	}
 
-	pwr.data = Some(future_prev_halves);
+	pwr.spare = ::std::mem::replace(&mut pwr.data, future_prev_halves);
+	pwr.has_data = true;
+	stage_times.overlap_add = timer.lap();
+	pwr.stage_times = stage_times;
+	pwr.last_packet_silent = all_floors_unused;
+
+	Ok(audio_spectri)
+}
+
+/**
+Reusable buffers for `read_audio_packet_into`
+
+Decoding a packet needs several buffers whose sizes depend on
+the block size, like the residue vectors, the residue partition
+classifications, the decoded floors, and the temporary storage of
+the inverse MDCT. By keeping one `DecodeScratch` around, e.g. next
+to the `PreviousWindowRight` of a stream, their allocations get
+reused between packets instead of being done anew for each packet.
+
+Creating a `DecodeScratch` doesn't allocate.
+*/
+#[derive(Default)]
+pub struct DecodeScratch {
+	residue :Vec<f32>,
+	residue_interleaved :Vec<f32>,
+	classifications :Vec<u32>,
+	floors :Vec<DecodedFloor>,
+	/// Per channel cosines of the floor0 coefficients
+	floor0_coefficients :Vec<Vec<f32>>,
+	/// Per channel floor1 Y values, as read from the packet
+	floor1_y :Vec<Vec<u32>>,
+	floor1_final_y :Vec<u32>,
+	floor1_step2_flag :Vec<bool>,
+	floor1_curve :Vec<u32>,
+	imdct_buf :Vec<f32>,
+}
+
+impl DecodeScratch {
+	pub fn new() -> Self {
+		DecodeScratch::default()
+	}
+	/// Creates the buffers with the largest sizes
+	/// the stream with the given ident header needs
+	///
+	/// Buffers whose size depends on the setup header, like the
+	/// residue partition classifications, are not allocated,
+	/// and only grow while decoding the first packets.
+	pub fn with_capacity(ident :&IdentHeader) -> Self {
+		let channels = ident.audio_channels as usize;
+		let n2 = (1usize << ident.blocksize_1) / 2;
+		DecodeScratch {
+			residue : Vec::with_capacity(channels * n2),
+			residue_interleaved : Vec::with_capacity(channels * n2),
+			classifications : Vec::new(),
+			floors : Vec::with_capacity(channels),
+			// The maximum floor0 order, and the maximum
+			// length of the floor1 X list, as per the spec
+			floor0_coefficients : vecs_with_capacity(channels, 255),
+			floor1_y : vecs_with_capacity(channels, 65),
+			floor1_final_y : Vec::with_capacity(65),
+			floor1_step2_flag : Vec::with_capacity(65),
+			floor1_curve : Vec::with_capacity(n2),
+			imdct_buf : Vec::with_capacity(n2),
+		}
+	}
+}
+
+/**
+Reusable state for decoding the audio packets of a stream
+
+Bundles the `PreviousWindowRight` of a stream with a `DecodeScratch`
+and the buffer the decoded samples are written to, so that the
+allocations of all of them get reused between packets.
+
+If created via `with_capacity`, the buffers are allocated upfront
+with the sizes the stream needs. Then, once the first packets have
+been decoded, decoding further packets doesn't allocate at all,
+which is useful for real time playback, or for applications that
+want to do all allocations upfront. This holds for streams with at
+most 32 channels and block sizes of at least 256 samples, which
+covers the output of all common encoders.
+
+Like with `read_audio_packet_generic`, the first packet only
+serves to warm up the decoder, and yields no samples.
+*/
+pub struct DecodeState {
+	pwr :PreviousWindowRight,
+	scratch :DecodeScratch,
+	out :Vec<Vec<f32>>,
+}
+
+impl Default for DecodeState {
+	fn default() -> Self {
+		DecodeState::new()
+	}
+}
+
+impl DecodeState {
+	/// Creates a new state, without allocating
+	pub fn new() -> Self {
+		DecodeState {
+			pwr : PreviousWindowRight::new(),
+			scratch : DecodeScratch::new(),
+			out : Vec::new(),
+		}
+	}
+	/// Creates a new state, with the buffers allocated for
+	/// the largest block size of the given stream
+	pub fn with_capacity(ident :&IdentHeader) -> Self {
+		let channels = ident.audio_channels as usize;
+		let n = 1usize << ident.blocksize_1;
+		DecodeState {
+			pwr : PreviousWindowRight::with_capacity(channels, n / 2),
+			scratch : DecodeScratch::with_capacity(ident),
+			out : vecs_with_capacity(channels, n),
+		}
+	}
+	/// Whether a packet has been decoded already,
+	/// so that the next packet yields samples
+	pub fn is_warmed_up(&self) -> bool {
+		!self.pwr.is_empty()
+	}
+	/// Resets the state, e.g. after seeking, keeping the allocations
+	///
+	/// The next packet decoded afterwards yields no samples again.
+	pub fn reset(&mut self) {
+		self.pwr.reset();
+	}
+	/// Returns the `PreviousWindowRight` of the state
+	///
+	/// This gives access to the information about the last
+	/// decoded packet, like `last_packet_was_silent`.
+	pub fn pwr(&self) -> &PreviousWindowRight {
+		&self.pwr
+	}
+	/// Decodes the given audio packet
+	///
+	/// Returns the samples of the packet, one slice per channel.
+	/// They are overwritten when the next packet gets decoded.
+	/// See `read_audio_packet_into` for details.
+	pub fn decode_packet(&mut self, ident :&IdentHeader, setup :&SetupHeader,
+			packet :&[u8]) -> Result<&[Vec<f32>], AudioReadError> {
+		try!(read_audio_packet_into(ident, setup, packet,
+			&mut self.pwr, &mut self.scratch, &mut self.out));
+		return Ok(&self.out);
+	}
+}
+
+/**
+Audio packet decoding function writing into a caller supplied buffer
+
+Like `read_audio_packet_generic` with `Vec<Vec<f32>>` as output
+format, but `out` gets cleared and refilled with the samples of
+the packet, reusing the allocations of the contained Vecs.
+Together with the buffers in `scratch`, this means that once the
+buffers have grown to the largest block size, decoding a packet
+doesn't allocate any more, which is useful for real time playback.
+`DecodeState` bundles all the buffers.
+
+On errors, the contents of `out` are unspecified.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+*/
+pub fn read_audio_packet_into(ident :&IdentHeader, setup :&SetupHeader, packet :&[u8],
+		pwr :&mut PreviousWindowRight, scratch :&mut DecodeScratch,
+		out :&mut Vec<Vec<f32>>) -> Result<(), AudioReadError> {
+	let storage = ::std::mem::take(out);
+	let spectra = try!(decode_spectra(ident, setup, packet, scratch, storage, false));
+	*out = try!(synthesize_spectra(ident, spectra, pwr, scratch));
+	return Ok(());
+}
+
+/**
+Main audio packet decoding function
+
+Pass your info to this function to get your raw packet data decoded.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+*/
+pub fn read_audio_packet_generic<S :Samples>(ident :&IdentHeader, setup :&SetupHeader, packet :&[u8], pwr :&mut PreviousWindowRight)
+		-> Result<S, AudioReadError> {
+	let mut scratch = DecodeScratch::new();
+	let spectra = try!(decode_spectra(ident, setup, packet, &mut scratch, Vec::new(), false));
+	let audio = try!(synthesize_spectra(ident, spectra, pwr, &mut scratch));
 
 	// Generate final integer samples
-	let final_i16_samples = S::from_floats(audio_spectri);
+	let final_i16_samples = S::from_floats(audio);
 
 	Ok(final_i16_samples)
 }
 
+/**
+Main audio packet decoding function, reporting the position of errors
+
+Like `read_audio_packet_generic`, but on failure, the position inside
+the packet where the error occured is returned along with the error.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+*/
+pub fn read_audio_packet_with_position<S :Samples>(ident :&IdentHeader, setup :&SetupHeader,
+		packet :&[u8], pwr :&mut PreviousWindowRight)
+		-> Result<S, WithPosition<AudioReadError>> {
+	let mut scratch = DecodeScratch::new();
+	let mut rdr = BitpackCursor::new(packet);
+	let res = decode_spectra_rdr(ident, setup, &mut rdr, &mut scratch, Vec::new(), false)
+		.and_then(|spectra| synthesize_spectra(ident, spectra, pwr, &mut scratch));
+	match res {
+		Ok(audio) => Ok(S::from_floats(audio)),
+		Err(error) => {
+			let (byte_offset, bit_offset) = rdr.position();
+			Err(WithPosition { error, byte_offset, bit_offset })
+		},
+	}
+}
+
+/// Output quality for `read_audio_packet_generic_opts`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeQuality {
+	/// Regular decoding, at the sample rate of the stream
+	Full,
+	/// Decoding at half the sample rate of the stream
+	///
+	/// Only the lower half of the spectrum is used, and the inverse
+	/// MDCT and overlap add are done with halved block sizes,
+	/// which saves about half of their cost.
+	Half,
+}
+
+/**
+Audio packet decoding function with a selectable output quality
+
+With `DecodeQuality::Full`, this is the same as
+`read_audio_packet_generic`. With `DecodeQuality::Half`, the upper
+half of the spectrum is dropped, and the samples are synthesized
+at half the sample rate of the stream, i.e. at
+`ident.audio_sample_rate / 2`, like stb_vorbis can do it.
+Each packet yields half the number of samples then.
+This is useful for playback on constrained devices.
+
+The state in `pwr` depends on the quality, so all packets decoded
+with a given `PreviousWindowRight` must use the same quality.
+To switch the quality, e.g. after seeking,
+start with a new `PreviousWindowRight`.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+*/
+pub fn read_audio_packet_generic_opts<S :Samples>(ident :&IdentHeader, setup :&SetupHeader,
+		packet :&[u8], pwr :&mut PreviousWindowRight, quality :DecodeQuality)
+		-> Result<S, AudioReadError> {
+	let mut scratch = DecodeScratch::new();
+	let mut spectra = try!(decode_spectra(ident, setup, packet, &mut scratch, Vec::new(), false));
+	let audio = match quality {
+		DecodeQuality::Full => try!(synthesize_spectra(ident, spectra, pwr, &mut scratch)),
+		DecodeQuality::Half => {
+			for spectrum in spectra.audio_spectri.iter_mut() {
+				let half_len = spectrum.len() / 2;
+				spectrum.truncate(half_len);
+			}
+			let cached_bs_derived = ident.cached_bs_derived_half
+				.get(ident.blocksize_0, ident.blocksize_1);
+			try!(synthesize_spectra_sized((ident.blocksize_0 - 1, ident.blocksize_1 - 1),
+				&cached_bs_derived, spectra, pwr, &mut scratch))
+		},
+	};
+	Ok(S::from_floats(audio))
+}
+
+#[cfg(feature = "ogg")]
+#[test]
+fn test_half_rate_tables_lazy() {
+	use ogg::PacketReader;
+	use inside_ogg::read_headers;
+	let data :&'static [u8] = include_bytes!("../test-data/sine-stereo.ogg");
+	let mut rdr = PacketReader::new(::std::io::Cursor::new(data));
+	let ((ident, _, setup), _) = read_headers(&mut rdr).unwrap();
+	let mut pwr = PreviousWindowRight::new();
+	let mut pwr_half = PreviousWindowRight::new();
+	let (mut full_len, mut half_len) = (0, 0);
+	let mut first = true;
+	while let Some(pck) = rdr.read_packet().unwrap() {
+		let full :Vec<Vec<f32>> = read_audio_packet_generic_opts(&ident, &setup,
+			&pck.data, &mut pwr, DecodeQuality::Full).unwrap();
+		// The tables for half rate decoding are only computed when needed
+		assert_eq!(ident.cached_bs_derived_half.is_computed(), !first);
+		let half :Vec<Vec<f32>> = read_audio_packet_generic_opts(&ident, &setup,
+			&pck.data, &mut pwr_half, DecodeQuality::Half).unwrap();
+		assert!(ident.cached_bs_derived_half.is_computed());
+		first = false;
+		full_len += full[0].len();
+		half_len += half[0].len();
+	}
+	assert_eq!(half_len * 2, full_len);
+}
+
+/**
+Audio packet decoding function for previews, skipping the residue
+
+Decodes the floor curves of the packet, which describe the rough
+spectral envelope of each channel, and passes them through the
+inverse MDCT and the overlap add, skipping the decoding of the
+residue. The residue usually makes up most of a packet, so this
+is faster than `read_audio_packet_generic`, the more so the higher
+the bitrate of the stream is. The inverse MDCT is still done.
+
+As the floor curve only gets multiplied with the residue
+during normal decoding, the residue is taken out of the picture
+entirely here. The resulting samples are **not** the audio of the
+stream, but they follow its loudness and spectral balance, which
+makes them suitable for visualizations like waveform thumbnails,
+or for fast scrubbing.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+*/
+pub fn read_audio_packet_floor_only<S :Samples>(ident :&IdentHeader, setup :&SetupHeader,
+		packet :&[u8], pwr :&mut PreviousWindowRight) -> Result<S, AudioReadError> {
+	let mut scratch = DecodeScratch::new();
+	let spectra = try!(decode_spectra(ident, setup, packet, &mut scratch, Vec::new(), true));
+	let audio = try!(synthesize_spectra(ident, spectra, pwr, &mut scratch));
+	Ok(S::from_floats(audio))
+}
+
+/**
+Decodes the spectra of an audio packet, without synthesizing samples
+
+Does all decoding steps up to the inverse MDCT: the floor and residue
+decoding, the inverse coupling, and the multiplication of the floor
+curves with the residue. The spectra come as one `Vec` per channel,
+in channel order, each containing `n/2` bins, for `n` being the
+block size of the packet. This is useful for spectrograms and
+other analysis tools.
+
+As no samples are synthesized, there is no need for a
+`PreviousWindowRight`, and the packets can be passed in any order.
+If the samples are needed as well, use `read_audio_packet_with_spectra`.
+*/
+pub fn read_audio_spectra(ident :&IdentHeader, setup :&SetupHeader, packet :&[u8])
+		-> Result<Vec<Vec<f32>>, AudioReadError> {
+	let mut scratch = DecodeScratch::new();
+	let spectra = try!(decode_spectra(ident, setup, packet, &mut scratch, Vec::new(), false));
+	Ok(spectra.audio_spectri)
+}
+
+/**
+Audio packet decoding function that also returns the spectra
+
+Like `read_audio_packet_generic`, but additionally returns the
+frequency domain data of the packet, as present right before
+the inverse MDCT.
+
+The spectra come as one `Vec` per channel, in channel order.
+Each `Vec` contains `n/2` bins, for `n` being the block size
+of the packet, with the coefficients being the result of the
+floor curve multiplied with the (inverse coupled) residue.
+Note that the spectra are returned even for packets
+that yield no samples, such as the first packet of a stream.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+*/
+pub fn read_audio_packet_with_spectra<S :Samples>(ident :&IdentHeader, setup :&SetupHeader,
+		packet :&[u8], pwr :&mut PreviousWindowRight)
+		-> Result<(S, Vec<Vec<f32>>), AudioReadError> {
+	let mut scratch = DecodeScratch::new();
+	let spectra = try!(decode_spectra(ident, setup, packet, &mut scratch, Vec::new(), false));
+	let audio_spectri = spectra.audio_spectri.clone();
+	let audio = try!(synthesize_spectra(ident, spectra, pwr, &mut scratch));
+	Ok((S::from_floats(audio), audio_spectri))
+}
+
+/**
+Audio packet decoding function that turns panics into errors
+
+Like `read_audio_packet_generic`, but if decoding panics,
+the panic is caught and `AudioReadError::AudioBadFormat` is returned.
+The passed `PreviousWindowRight` is reset in that case,
+so the next packet only serves to warm up the decoder again.
+
+The decoder aims to never panic on malformed input, so this
+function is only meant as a defense in depth measure,
+e.g. for servers decoding untrusted input.
+Note that panics can only be caught if the crate
+is not compiled with `panic = "abort"`.
+Panics are still reported by the panic hook.
+*/
+pub fn decode_packet_catch_unwind<S :Samples>(ident :&IdentHeader, setup :&SetupHeader,
+		packet :&[u8], pwr :&mut PreviousWindowRight) -> Result<S, AudioReadError> {
+	let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+		read_audio_packet_generic(ident, setup, packet, pwr)
+	}));
+	match res {
+		Ok(r) => r,
+		Err(_) => {
+			*pwr = PreviousWindowRight::new();
+			Err(AudioReadError::AudioBadFormat)
+		},
+	}
+}
+
+/**
+Audio packet decoding function that also returns the floor0 amplitudes
+
+Like `read_audio_packet_generic`, but additionally returns
+the amplitude value of the floor of each channel, in channel order.
+The amplitude is `None` for channels whose floor is unused in the packet,
+and for channels that use floor type 1, as it has no amplitude value.
+
+This is meant as a diagnostic tool for the floor0 decoding code.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+*/
+pub fn read_audio_packet_with_floor0_amps<S :Samples>(ident :&IdentHeader, setup :&SetupHeader,
+		packet :&[u8], pwr :&mut PreviousWindowRight)
+		-> Result<(S, Vec<Option<u64>>), AudioReadError> {
+	let mut scratch = DecodeScratch::new();
+	let spectra = try!(decode_spectra(ident, setup, packet, &mut scratch, Vec::new(), false));
+	let floor0_amplitudes = scratch.floors.iter()
+		.map(|fl| match fl {
+			&DecodedFloor::TypeZero(amplitude) => Some(amplitude),
+			_ => None,
+		})
+		.collect();
+	let audio = try!(synthesize_spectra(ident, spectra, pwr, &mut scratch));
+	Ok((S::from_floats(audio), floor0_amplitudes))
+}
+
 /**
 Main audio packet decoding function
 
@@ -1171,3 +2161,191 @@ pub fn read_audio_packet(ident :&IdentHeader, setup :&SetupHeader, packet :&[u8]
 		-> Result<Vec<Vec<i16>>, AudioReadError> {
 	read_audio_packet_generic(ident, setup, packet, pwr)
 }
+
+/**
+Audio packet decoding function returning interleaved samples
+
+Like `read_audio_packet`, but returns the samples interleaved,
+which is the format most audio APIs expect.
+This is a named shorthand for `read_audio_packet_generic::<InterleavedSamples<i16>>`.
+
+Panics if the passed PreviousWindowRight struct doesn't match the info
+from the ident header.
+
+# Example
+
+Decoding packets from some non-ogg container:
+
+```
+use lewton::VorbisError;
+use lewton::audio::{PreviousWindowRight, read_audio_packet_interleaved};
+use lewton::header::{read_header_ident, read_header_setup};
+
+fn decode_all(ident_pck :&[u8], setup_pck :&[u8], audio_pcks :&[&[u8]])
+        -> Result<Vec<i16>, VorbisError> {
+    let ident = read_header_ident(ident_pck)?;
+    let setup = read_header_setup(setup_pck, ident.audio_channels,
+        (ident.blocksize_0, ident.blocksize_1))?;
+    let mut pwr = PreviousWindowRight::new();
+    let mut samples = Vec::new();
+    for pck in audio_pcks {
+        let decoded = read_audio_packet_interleaved(&ident, &setup, pck, &mut pwr)?;
+        assert_eq!(decoded.channel_count, ident.audio_channels as usize);
+        samples.extend_from_slice(&decoded.samples);
+    }
+    Ok(samples)
+}
+```
+*/
+pub fn read_audio_packet_interleaved(ident :&IdentHeader, setup :&SetupHeader, packet :&[u8], pwr :&mut PreviousWindowRight)
+		-> Result<InterleavedSamples<i16>, AudioReadError> {
+	read_audio_packet_generic(ident, setup, packet, pwr)
+}
+
+/**
+Audio packet decoder borrowing its headers
+
+Bundles references to the headers of a stream with the
+`PreviousWindowRight` state needed to decode its audio packets.
+As the headers are only borrowed, a single `HeaderSet` can be
+shared by many decoders, without cloning the codebook tables,
+e.g. when each request of a server decodes a few packets on its own.
+
+Like with `read_audio_packet_generic`, the first packet decoded
+by a new decoder only serves to warm it up, and yields no samples.
+Use `is_warmed_up` to check whether this has happened already.
+*/
+#[derive(Debug)]
+pub struct PacketDecoder<'a> {
+	ident :&'a IdentHeader,
+	setup :&'a SetupHeader,
+	pwr :PreviousWindowRight,
+}
+
+impl<'a> PacketDecoder<'a> {
+	/// Creates a new decoder for the stream with the given headers
+	pub fn new(headers :&'a HeaderSet) -> Self {
+		PacketDecoder {
+			ident : &headers.0,
+			setup : &headers.2,
+			pwr : PreviousWindowRight::new(),
+		}
+	}
+	/// Whether a packet has been decoded already,
+	/// so that the next packet yields samples
+	pub fn is_warmed_up(&self) -> bool {
+		!self.pwr.is_empty()
+	}
+	/// Decodes the given audio packet
+	///
+	/// See `read_audio_packet_generic` for details.
+	pub fn decode_packet<S :Samples>(&mut self, packet :&[u8]) -> Result<S, AudioReadError> {
+		read_audio_packet_generic(self.ident, self.setup, packet, &mut self.pwr)
+	}
+}
+
+/**
+Audio packet decoder owning its headers
+
+For decoding Vorbis delivered without Ogg framing, e.g. by
+FFmpeg or gstreamer, where the headers are passed as one
+"extradata" blob and every audio packet is passed on its own.
+
+```
+# use lewton::audio::VorbisDecoder;
+# fn example(extradata :&[u8], audio_pcks :&[&[u8]]) -> Result<(), lewton::VorbisError> {
+// The extradata as found in an AVCodecParameters struct
+let mut dec = VorbisDecoder::from_ffmpeg_extradata(extradata)?;
+let mut out :Vec<i16> = Vec::new();
+for pck in audio_pcks {
+    let decoded :lewton::samples::InterleavedSamples<i16> = dec.decode(pck)?;
+    out.extend_from_slice(&decoded.samples);
+}
+# Ok(())
+# }
+```
+
+Like with `PacketDecoder`, the first packet only warms up the decoder.
+*/
+#[derive(Debug)]
+pub struct VorbisDecoder {
+	headers :HeaderSet,
+	pwr :PreviousWindowRight,
+}
+
+impl VorbisDecoder {
+	/// Creates a new decoder from already read headers
+	pub fn new(headers :HeaderSet) -> Self {
+		VorbisDecoder {
+			headers,
+			pwr : PreviousWindowRight::new(),
+		}
+	}
+	/// Creates a new decoder from an FFmpeg style extradata blob
+	///
+	/// See `header::split_ffmpeg_extradata` for the accepted layouts.
+	pub fn from_ffmpeg_extradata(extradata :&[u8]) -> Result<Self, HeaderReadError> {
+		let headers = try!(read_headers_from_ffmpeg_extradata(extradata));
+		return Ok(VorbisDecoder::new(headers));
+	}
+	/// The headers of the stream
+	pub fn headers(&self) -> &HeaderSet {
+		&self.headers
+	}
+	/// Decodes the given audio packet
+	///
+	/// See `read_audio_packet_generic` for details.
+	pub fn decode<S :Samples>(&mut self, packet :&[u8]) -> Result<S, AudioReadError> {
+		read_audio_packet_generic(&self.headers.0, &self.headers.2, packet, &mut self.pwr)
+	}
+	/// Resets the decoder state, e.g. after the source has seeked
+	///
+	/// The next packet decoded afterwards yields no samples again.
+	pub fn reset(&mut self) {
+		self.pwr = PreviousWindowRight::new();
+	}
+}
+
+/**
+Audio packet decoder owning the ident and setup headers
+
+The Rust counterpart of the `LewtonContext` of the C API. Unlike
+`VorbisDecoder`, it doesn't need the comment header, which
+is useful for containers like Matroska or WebM where the Ogg
+layer is absent, and the headers are obtained separately.
+
+Like with `PacketDecoder`, the first packet only warms up the decoder.
+*/
+#[derive(Debug)]
+pub struct RawVorbisDecoder {
+	ident :IdentHeader,
+	setup :SetupHeader,
+	pwr :PreviousWindowRight,
+}
+
+impl RawVorbisDecoder {
+	/// Creates a new decoder from the ident and setup headers
+	pub fn from_headers(ident :IdentHeader, setup :SetupHeader) -> Self {
+		RawVorbisDecoder {
+			ident,
+			setup,
+			pwr : PreviousWindowRight::new(),
+		}
+	}
+	/// The ident header of the stream
+	pub fn ident_hdr(&self) -> &IdentHeader {
+		&self.ident
+	}
+	/// Decodes the given audio packet
+	///
+	/// See `read_audio_packet_generic` for details.
+	pub fn decode_packet<S :Samples>(&mut self, packet :&[u8]) -> Result<S, AudioReadError> {
+		read_audio_packet_generic(&self.ident, &self.setup, packet, &mut self.pwr)
+	}
+	/// Resets the decoder state, e.g. after the source has seeked
+	///
+	/// The next packet decoded afterwards yields no samples again.
+	pub fn reset(&mut self) {
+		self.pwr = PreviousWindowRight::new();
+	}
+}
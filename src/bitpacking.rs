@@ -20,9 +20,60 @@ Note that this implementation doesn't fully align with the spec in the regard th
 This is no problem on most architectures.
 This non-alignment to the spec is due to the fact that the rust language is highly leaned towards byte == u8,
 and doesn't even have a builtin single byte type.
+
+Reading the test vector from section 2.1.6 of the spec:
+
+```
+use lewton::bitpacking::{BitpackCursor, EndOfPacket};
+
+let data = &[0b11111100, 0b01001000, 0b11001110, 0b00000110];
+let mut rdr = BitpackCursor::new(data);
+assert_eq!(rdr.read_dyn_u8(4), Ok(12));
+assert_eq!(rdr.read_dyn_u8(3), Ok(7));
+assert_eq!(rdr.read_dyn_u16(7), Ok(17));
+assert_eq!(rdr.read_dyn_u16(13), Ok(6969));
+assert_eq!(rdr.bits_remaining(), 5);
+assert_eq!(rdr.read_dyn_u8(6), Err(EndOfPacket));
+```
 */
 
-use huffman_tree::{VorbisHuffmanTree, PeekedDataLookupResult};
+use std::error;
+use std::fmt;
+use huffman_tree::PeekedDataLookupResult;
+pub use huffman_tree::{VorbisHuffmanTree, HuffmanError};
+
+/// Error type for the read methods of `BitpackCursor`
+///
+/// Returned if there are not enough bits left to read.
+/// Inside audio packets, this is not necessarily an error,
+/// as the spec defines how to handle an early end of packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndOfPacket;
+
+impl error::Error for EndOfPacket {}
+
+impl fmt::Display for EndOfPacket {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		write!(fmt, "End of packet reached.")
+	}
+}
+
+/// Error type for `BitpackCursor::read_huffman`
+///
+/// Reaching the end of the packet is a normal occurence
+/// in many places, while reading from an empty tree renders
+/// the packet undecodable.
+#[derive(Debug, PartialEq)]
+pub enum HuffmanReadErr {
+	EndOfPacket,
+	EmptyTree,
+}
+
+impl From<EndOfPacket> for HuffmanReadErr {
+	fn from(_ :EndOfPacket) -> HuffmanReadErr {
+		HuffmanReadErr::EndOfPacket
+	}
+}
 
 /// A Cursor on slices to read numbers and bitflags, bit aligned.
 pub struct BitpackCursor <'a> {
@@ -106,7 +157,7 @@ macro_rules! bpc_read_body {
 				1 + octetnum_rounded_up]);// */
 			if $selfarg.byte_cursor + 1 + octetnum_rounded_up > $selfarg.inner.len() {
 				//println!(" => Out of bounds :\\");
-				return Err(());
+				return Err(EndOfPacket);
 			}
 			let buf = &$selfarg.inner[$selfarg.byte_cursor
 				.. $selfarg.byte_cursor + 1 + octetnum_rounded_up];
@@ -133,7 +184,7 @@ macro_rules! bpc_read_body {
 				octetnum_rounded_up]);// */
 			if $selfarg.byte_cursor + octetnum_rounded_up > $selfarg.inner.len() {
 				//println!(" => Out of bounds :\\");
-				return Err(());
+				return Err(EndOfPacket);
 			}
 			let buf = &$selfarg.inner[$selfarg.byte_cursor ..
 				$selfarg.byte_cursor + octetnum_rounded_up];
@@ -178,7 +229,7 @@ macro_rules! bpc_peek_body {
 				1 + octetnum_rounded_up]);// */
 			if $selfarg.byte_cursor + 1 + octetnum_rounded_up > $selfarg.inner.len() {
 				//println!(" => Out of bounds :\\");
-				return Err(());
+				return Err(EndOfPacket);
 			}
 			let buf = &$selfarg.inner[$selfarg.byte_cursor
 				.. $selfarg.byte_cursor + 1 + octetnum_rounded_up];
@@ -203,7 +254,7 @@ macro_rules! bpc_peek_body {
 				octetnum_rounded_up]);// */
 			if $selfarg.byte_cursor + octetnum_rounded_up > $selfarg.inner.len() {
 				//println!(" => Out of bounds :\\");
-				return Err(());
+				return Err(EndOfPacket);
 			}
 			let buf = &$selfarg.inner[$selfarg.byte_cursor ..
 				$selfarg.byte_cursor + octetnum_rounded_up];
@@ -252,8 +303,11 @@ macro_rules! bpc_advance_body {
 
 macro_rules! uk_reader {
 	($fnname:ident, $rettype:ident, $bitnum:expr, $octetnum:expr) => {
+		#[doc = concat!("Reads ", stringify!($bitnum), " bits as unsigned number")]
+		///
+		/// Returns `EndOfPacket` if the end of the data has been reached.
 		#[inline]
-		pub fn $fnname(&mut self) -> Result<$rettype, ()> {
+		pub fn $fnname(&mut self) -> Result<$rettype, EndOfPacket> {
 			bpc_read_body!($rettype, $bitnum, $octetnum, self)
 		}
 	};
@@ -261,8 +315,11 @@ macro_rules! uk_reader {
 
 macro_rules! ik_reader {
 	($fnname:ident, $rettype:ident, $bitnum_of_rettype:expr, $bitnum:expr, $octetnum:expr) => {
+		#[doc = concat!("Reads ", stringify!($bitnum), " bits as signed number")]
+		///
+		/// Returns `EndOfPacket` if the end of the data has been reached.
 		#[inline]
-		pub fn $fnname(&mut self) -> Result<$rettype, ()> {
+		pub fn $fnname(&mut self) -> Result<$rettype, EndOfPacket> {
 			Ok(sign_extend!(try!(
 				bpc_read_body!($rettype, $bitnum, $octetnum, self)),
 				$rettype, $bitnum_of_rettype, $bitnum))
@@ -272,8 +329,12 @@ macro_rules! ik_reader {
 
 macro_rules! ik_dynamic_reader {
 	($fnname:ident, $rettype:ident, $bitnum_of_rettype:expr) => {
+		/// Reads the given number of bits as signed number
+		///
+		/// Returns `EndOfPacket` if the end of the data has been reached.
+		/// Panics if `bit_num` exceeds the bit width of the type.
 		#[inline]
-		pub fn $fnname(&mut self, bit_num :u8) -> Result<$rettype, ()> {
+		pub fn $fnname(&mut self, bit_num :u8) -> Result<$rettype, EndOfPacket> {
 			let octet_num :usize = (bit_num / 8) as usize;
 			assert!(bit_num <= $bitnum_of_rettype);
 			Ok(sign_extend!(try!(
@@ -285,8 +346,13 @@ macro_rules! ik_dynamic_reader {
 
 macro_rules! uk_dynamic_reader {
 	($fnname:ident, $rettype:ident, $bit_num_max:expr) => {
+		/// Reads the given number of bits as unsigned number
+		///
+		/// Reading zero bits yields zero.
+		/// Returns `EndOfPacket` if the end of the data has been reached.
+		/// Panics if `bit_num` exceeds the bit width of the type.
 		#[inline]
-		pub fn $fnname(&mut self, bit_num :u8) -> Result<$rettype, ()> {
+		pub fn $fnname(&mut self, bit_num :u8) -> Result<$rettype, EndOfPacket> {
 			let octet_num :usize = (bit_num / 8) as usize;
 			if bit_num == 0 {
 				// TODO: one day let bpc_read_body handle this,
@@ -361,10 +427,27 @@ fn test_float_32_unpack_issue_24() {
 impl <'a> BitpackCursor <'a> {
 
 	/// Creates a new `BitpackCursor` for the given data array
-	pub fn new(arr : &'a[u8]) -> BitpackCursor {
+	///
+	/// Reading starts at the least significant bit of the first byte.
+	pub fn new(arr : &'a[u8]) -> BitpackCursor<'a> {
 		return BitpackCursor::<'a> { bit_cursor: 0, byte_cursor: 0, inner: arr };
 	}
 
+	/// Returns the current position as byte offset and bit inside that byte
+	pub fn position(&self) -> (usize, u8) {
+		(self.byte_cursor, self.bit_cursor)
+	}
+
+	/// Returns the number of bits that are left to be read
+	pub fn bits_remaining(&self) -> usize {
+		(self.inner.len() * 8).saturating_sub(self.byte_cursor * 8 + self.bit_cursor as usize)
+	}
+
+	/// Returns whether all bits have been read
+	pub fn is_at_end(&self) -> bool {
+		self.bits_remaining() == 0
+	}
+
 	// Unsigned, non-dynamic reader methods
 
 	// u32 based
@@ -393,9 +476,9 @@ impl <'a> BitpackCursor <'a> {
 	uk_reader!(read_u2, u8, 2, 0);
 	uk_reader!(read_u1, u8, 1, 0);
 
-	// Returning bool:
+	/// Reads a single bit, returning whether it is set
 	#[inline]
-	pub fn read_bit_flag(&mut self) -> Result<bool, ()> {
+	pub fn read_bit_flag(&mut self) -> Result<bool, EndOfPacket> {
 		return Ok(try!(self.read_u1()) == 1);
 	}
 
@@ -426,19 +509,21 @@ impl <'a> BitpackCursor <'a> {
 	// Float reading methods
 
 	/// Reads a single floating point number in the vorbis-float32 format
-	pub fn read_f32(&mut self) -> Result<f32, ()> {
+	///
+	/// This is the `float32_unpack` function of section 9.2.2 of the spec.
+	pub fn read_f32(&mut self) -> Result<f32, EndOfPacket> {
 		let val = try!(self.read_u32());
 		Ok(float32_unpack(val))
 	}
 
 	/// Peeks 8 bits of non read yet content without advancing the reader
 	#[inline]
-	pub fn peek_u8(&self) -> Result<u8, ()> {
+	pub fn peek_u8(&self) -> Result<u8, EndOfPacket> {
 		bpc_peek_body!(u8, 8, 1, self)
 	}
 
 	// Advances the reader by the given number of bits (up to 8).
-	pub fn advance_dyn_u8(&mut self, bit_num :u8) -> Result<(), ()> {
+	pub fn advance_dyn_u8(&mut self, bit_num :u8) -> Result<(), EndOfPacket> {
 		let octet_num :usize = (bit_num / 8) as usize;
 		if bit_num == 0 {
 			// TODO: one day let bpc_advance_body handle this,
@@ -452,7 +537,13 @@ impl <'a> BitpackCursor <'a> {
 	}
 
 	/// Reads a huffman word using the codebook abstraction
-	pub fn read_huffman(&mut self, tree :&VorbisHuffmanTree) -> Result<u32, ()> {
+	///
+	/// Returns the index of the read entry.
+	/// Returns `HuffmanReadErr::EmptyTree` if the tree is empty.
+	pub fn read_huffman(&mut self, tree :&VorbisHuffmanTree) -> Result<u32, HuffmanReadErr> {
+		if tree.is_empty() {
+			return Err(HuffmanReadErr::EmptyTree);
+		}
 		//let mut c :usize = 0;
 		//let mut w :usize = 0;
 		let mut iter = match self.peek_u8() {
@@ -537,6 +628,33 @@ fn test_bitpacking_reader_empty() {
 	assert_eq!(cur.read_dyn_u16(0).unwrap(),   0);
 }
 
+#[test]
+fn test_bitpacking_position() {
+	// Uses the test vector from the Vorbis I spec,
+	// section 2.1.6
+	let test_arr = &[0b11111100, 0b01001000, 0b11001110, 0b00000110];
+	let mut cur = BitpackCursor::new(test_arr);
+	assert_eq!(cur.position(), (0, 0));
+	assert_eq!(cur.bits_remaining(), 32);
+	assert_eq!(cur.read_dyn_u8(4).unwrap(), 12);
+	assert_eq!(cur.position(), (0, 4));
+	assert_eq!(cur.bits_remaining(), 28);
+	assert_eq!(cur.read_dyn_u8(3).unwrap(), 7);
+	assert_eq!(cur.position(), (0, 7));
+	assert_eq!(cur.read_dyn_u16(7).unwrap(), 17);
+	assert_eq!(cur.position(), (1, 6));
+	assert_eq!(cur.read_dyn_u16(13).unwrap(), 6969);
+	assert_eq!(cur.position(), (3, 3));
+	assert_eq!(cur.bits_remaining(), 5);
+	assert!(!cur.is_at_end());
+	assert_eq!(cur.read_dyn_u8(5).unwrap(), 0);
+	assert_eq!(cur.position(), (4, 0));
+	assert_eq!(cur.bits_remaining(), 0);
+	assert!(cur.is_at_end());
+	assert!(cur.read_u1().is_err());
+	assert!(BitpackCursor::new(&[]).is_at_end());
+}
+
 #[test]
 fn test_bitpacking_reader_byte_aligned() {
 	// Check that bitpacking readers work with "normal" byte aligned types:
@@ -586,4 +704,23 @@ fn test_capture_pattern_nonaligned() {
 	assert_eq!(cur.read_u24().unwrap(), 0x564342);
 	// Ensure that we incremented by only three bytes, not four
 	assert_eq!(cur.read_u16().unwrap(), 1);
+	assert_eq!(cur.position(), (6, 6));
+}
+
+#[test]
+fn test_read_huffman_empty_tree() {
+	// A codebook without any used entries yields an empty tree.
+	// Reading from it must give an error instead of panicking.
+	let tree = VorbisHuffmanTree::load_from_array(&[0, 0, 0]).unwrap();
+	let test_arr = &[0b10110110, 0b01001001];
+	let mut cur = BitpackCursor::new(test_arr);
+	assert_eq!(cur.read_huffman(&tree), Err(HuffmanReadErr::EmptyTree));
+	// Also when at the end of the packet
+	let mut cur = BitpackCursor::new(&[]);
+	assert_eq!(cur.read_huffman(&tree), Err(HuffmanReadErr::EmptyTree));
+
+	let tree = VorbisHuffmanTree::load_from_array(&[1, 0, 1]).unwrap();
+	let mut cur = BitpackCursor::new(test_arr);
+	assert_eq!(cur.read_huffman(&tree), Ok(0));
+	assert_eq!(cur.read_huffman(&tree), Ok(2));
 }
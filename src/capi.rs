@@ -1,9 +1,9 @@
-use std::os::raw::c_int;
+use std::os::raw::{c_char, c_int};
 use std::slice::from_raw_parts;
 use std::ptr::null_mut;
 
 use header::{read_header_setup, //read_header_comment,
-	read_header_ident, IdentHeader, //CommentHeader,
+	read_header_ident, split_xiph_extradata, IdentHeader, //CommentHeader,
 	SetupHeader};
 use audio::{PreviousWindowRight, read_audio_packet_generic};
 
@@ -18,36 +18,13 @@ pub struct LewtonContext {
 	setup_hdr :SetupHeader,
 }
 
-fn read_xiph_lacing(arr :&mut &[u8]) -> Option<u64> {
-	let mut r = 0;
-	loop {
-		if arr.len() == 0 {
-			return None;
-		}
-		let v = arr[0] as u64;
-		*arr = &arr[1..];
-		r += v;
-		if v < 255 {
-			return Some(r);
-		}
-	}
-}
-
 impl LewtonContext {
-	fn from_extradata(mut extradata :&[u8]) -> Option<Self> {
-		// We must start with a 2 as per matroska encapsulation spec
-		if extradata.len() == 0 || extradata[0] != 2 {
-			return None
-		}
-		extradata = &extradata[1..];
-		let ident_len = read_xiph_lacing(&mut extradata)? as usize;
-		let comment_len = read_xiph_lacing(&mut extradata)? as usize;
-
-		let ident_hdr = read_header_ident(&extradata[0..ident_len]).ok()?;
-		extradata = &extradata[ident_len..];
-		//let comment_hdr = read_header_comment(&extradata[0..comment_len]).ok()?;
-		extradata = &extradata[comment_len..];
-		let setup_hdr = read_header_setup(extradata, ident_hdr.audio_channels,
+	fn from_extradata(extradata :&[u8]) -> Option<Self> {
+		let [ident, _comment, setup] = split_xiph_extradata(extradata).ok()?;
+
+		let ident_hdr = read_header_ident(ident).ok()?;
+		//let comment_hdr = read_header_comment(_comment).ok()?;
+		let setup_hdr = read_header_setup(setup, ident_hdr.audio_channels,
 			(ident_hdr.blocksize_0, ident_hdr.blocksize_1))
 			.ok()?;
 		Some(LewtonContext {
@@ -72,6 +49,21 @@ impl LewtonContext {
 /// use `lewton_samples_drop()` to deallocate the memory
 pub struct LewtonSamples(Vec<Vec<f32>>);
 
+/// Provide the version of lewton as a NUL-terminated string
+///
+/// The returned string is statically allocated and must not be freed.
+#[no_mangle]
+pub extern fn lewton_version() -> *const c_char {
+	concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[test]
+fn test_lewton_version() {
+	use std::ffi::CStr;
+	let version = unsafe { CStr::from_ptr(lewton_version()) };
+	assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+}
+
 /// Create a LewtonContext from an extradata buffer
 ///
 /// Returns either NULL or a newly allocated LewtonContext
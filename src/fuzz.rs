@@ -0,0 +1,53 @@
+// Vorbis decoder written in Rust
+//
+// Copyright (c) 2026 est31 <MTest31@outlook.com>
+// and contributors. All rights reserved.
+// Licensed under MIT license, or Apache 2 license,
+// at your option. Please see the LICENSE file
+// attached to this source distribution for details.
+
+/*!
+Entry points for fuzzing
+
+The decoder is meant to never panic, regardless of its input.
+The functions in this module encode this as an invariant that
+fuzzers like `cargo fuzz` can check, e.g. via a fuzz target like:
+
+```ignore
+fuzz_target!(|data :&[u8]| {
+    lewton::fuzz::fuzz_decode(data);
+});
+```
+*/
+
+use std::io::Cursor;
+use inside_ogg::OggStreamReader;
+
+/// Decodes the given ogg/vorbis file data, ignoring any errors
+///
+/// Constructs an `OggStreamReader` on top of the data, and reads
+/// packets until either the end of the stream is reached,
+/// or an error occurs. Errors are swallowed, only panics
+/// (which are bugs) make it out of this function.
+pub fn fuzz_decode(data :&[u8]) {
+	let mut rdr = match OggStreamReader::new(Cursor::new(data)) {
+		Ok(r) => r,
+		Err(_) => return,
+	};
+	loop {
+		match rdr.read_dec_packet_itl() {
+			Ok(Some(_)) => (),
+			Ok(None) | Err(_) => return,
+		}
+	}
+}
+
+#[test]
+fn test_fuzz_decode_garbage() {
+	fuzz_decode(&[]);
+	fuzz_decode(b"OggS");
+	fuzz_decode(&[0; 1000]);
+	let mut data = b"OggS".to_vec();
+	data.extend((0 .. 2000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8));
+	fuzz_decode(&data);
+}
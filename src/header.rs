@@ -24,10 +24,12 @@ use std::error;
 use std::fmt;
 use std::io::{Cursor, ErrorKind, Read, Error};
 use std::string::FromUtf8Error;
-use byteorder::{ReadBytesExt, LittleEndian};
-use header_cached::{CachedBlocksizeDerived, compute_bark_map_cos_omega};
-use bitpacking::BitpackCursor;
+use byteorder::{ReadBytesExt, BigEndian, LittleEndian};
+use header_cached::{CachedBlocksizeDerived, HalfBlocksizeDerived,
+	compute_bark_map_cos_omega, compute_floor1_neighbors};
+use bitpacking::{BitpackCursor, HuffmanReadErr, EndOfPacket};
 use huffman_tree::{VorbisHuffmanTree, HuffmanError};
+use WithPosition;
 
 /// Errors that can occur during Header decoding
 #[derive(Debug)]
@@ -62,11 +64,8 @@ pub enum HeaderReadError {
 	BufferNotAddressable,
 }
 
-// For the () error type returned by the bitpacking layer
-// TODO that type choice was a bit unfortunate,
-// perhaps one day fix this
-impl From<()> for HeaderReadError {
-	fn from(_ :()) -> HeaderReadError {
+impl From<EndOfPacket> for HeaderReadError {
+	fn from(_ :EndOfPacket) -> HeaderReadError {
 		HeaderReadError::EndOfPacket
 	}
 }
@@ -208,6 +207,209 @@ pub struct IdentHeader {
 	pub blocksize_0 :u8,
 	pub blocksize_1 :u8,
 	pub(crate) cached_bs_derived :[CachedBlocksizeDerived; 2],
+	/// Like cached_bs_derived, but for the halved block sizes
+	/// used by half rate decoding
+	pub(crate) cached_bs_derived_half :HalfBlocksizeDerived,
+}
+
+// Manual impl to leave out the cached window data
+impl fmt::Debug for IdentHeader {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		fmt.debug_struct("IdentHeader")
+			.field("audio_channels", &self.audio_channels)
+			.field("audio_sample_rate", &self.audio_sample_rate)
+			.field("bitrate_maximum", &self.bitrate_maximum)
+			.field("bitrate_nominal", &self.bitrate_nominal)
+			.field("bitrate_minimum", &self.bitrate_minimum)
+			.field("blocksize_0", &self.blocksize_0)
+			.field("blocksize_1", &self.blocksize_1)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Position of a speaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelPosition {
+	Mono,
+	FrontLeft,
+	FrontCenter,
+	FrontRight,
+	SideLeft,
+	SideRight,
+	RearLeft,
+	RearCenter,
+	RearRight,
+	Lfe,
+}
+
+/**
+Channel layout of a stream
+
+The vorbis spec defines the channel order for streams
+with one to eight channels. For streams with more channels,
+the layout is application defined, which is
+indicated by the `Unknown` variant. Decoding such streams works
+just like decoding any other stream: the channels are treated as
+discrete and unordered, and no reordering is applied to them.
+
+Obtained via `IdentHeader::channel_layout`.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelLayout {
+	/// Layout defined by the vorbis spec, with the
+	/// positions of the channels in stream order
+	Known(&'static [ChannelPosition]),
+	/// Layout not defined by the spec, with the channel count
+	Unknown(u8),
+}
+
+impl ChannelLayout {
+	/// Returns the layout the vorbis spec defines for the given channel count
+	///
+	/// For one to eight channels this is the canonical assignment
+	/// of the spec, for all other counts it is `Unknown`.
+	pub fn from_channel_count(n :u8) -> ChannelLayout {
+		use self::ChannelPosition::*;
+		static LAYOUTS :[&[ChannelPosition]; 8] = [
+			&[Mono],
+			&[FrontLeft, FrontRight],
+			&[FrontLeft, FrontCenter, FrontRight],
+			&[FrontLeft, FrontRight, RearLeft, RearRight],
+			&[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight],
+			&[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight, Lfe],
+			&[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight, RearCenter, Lfe],
+			&[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight,
+				RearLeft, RearRight, Lfe],
+		];
+		match LAYOUTS.get((n as usize).wrapping_sub(1)) {
+			Some(positions) => ChannelLayout::Known(positions),
+			None => ChannelLayout::Unknown(n),
+		}
+	}
+	/// Returns the positions of the channels in stream order,
+	/// or `None` if the layout is unknown
+	pub fn positions(&self) -> Option<&'static [ChannelPosition]> {
+		match *self {
+			ChannelLayout::Known(positions) => Some(positions),
+			ChannelLayout::Unknown(_) => None,
+		}
+	}
+	/// Returns the index of the channel at the given position
+	///
+	/// Returns `None` if the layout has no channel at that
+	/// position, or if the layout is unknown.
+	pub fn position_of(&self, position :ChannelPosition) -> Option<usize> {
+		self.positions()
+			.and_then(|positions| positions.iter().position(|p| *p == position))
+	}
+}
+
+/**
+Channel configuration of a stream
+
+Covers the most common channel counts, so that
+they can be matched on conveniently.
+
+Obtained via `IdentHeader::channel_config`.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelConfig {
+	Mono,
+	Stereo,
+	/// Six channels, in the order given by `ChannelLayout`
+	FivePointOne,
+	/// Any other channel count
+	Other(u8),
+}
+
+impl IdentHeader {
+	/// Returns the sample rate of the stream in Hertz
+	///
+	/// This is the same as the `audio_sample_rate` field.
+	pub fn sample_rate_hz(&self) -> u32 {
+		self.audio_sample_rate
+	}
+	/// Returns the number of audio channels of the stream
+	///
+	/// This is the same as the `audio_channels` field.
+	pub fn channel_count(&self) -> u8 {
+		self.audio_channels
+	}
+	/// Returns whether the two streams have the same format
+	///
+	/// This is the case if the sample rate, the channel count
+	/// and the block sizes match. The decoded samples of such
+	/// streams can be concatenated without any conversion.
+	pub fn format_compatible(&self, other :&IdentHeader) -> bool {
+		self.audio_sample_rate == other.audio_sample_rate &&
+			self.audio_channels == other.audio_channels &&
+			self.blocksize_0 == other.blocksize_0 &&
+			self.blocksize_1 == other.blocksize_1
+	}
+	/// Returns the channel configuration of the stream
+	pub fn channel_config(&self) -> ChannelConfig {
+		match self.audio_channels {
+			1 => ChannelConfig::Mono,
+			2 => ChannelConfig::Stereo,
+			6 => ChannelConfig::FivePointOne,
+			n => ChannelConfig::Other(n),
+		}
+	}
+	/// Returns the channel layout, as defined by the vorbis spec
+	pub fn channel_layout(&self) -> ChannelLayout {
+		ChannelLayout::from_channel_count(self.audio_channels)
+	}
+}
+
+/// Summary of the stream properties given by the ident header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VorbisInfo {
+	/// The sample rate of the stream in Hertz
+	pub sample_rate :u32,
+	/// The number of audio channels of the stream
+	pub channels :u8,
+	/// The minimum bit rate hint, or a value `<= 0` if unset
+	pub bitrate_min :i32,
+	/// The nominal bit rate hint, or a value `<= 0` if unset
+	pub bitrate_nominal :i32,
+	/// The maximum bit rate hint, or a value `<= 0` if unset
+	pub bitrate_max :i32,
+	/// The exponent of the short block size
+	pub blocksize_0 :u8,
+	/// The exponent of the long block size
+	pub blocksize_1 :u8,
+	/// The maximum number of samples per channel a packet can yield
+	pub max_block_samples :usize,
+}
+
+impl VorbisInfo {
+	/// Creates the summary from the given ident header
+	pub fn from_ident(ident :&IdentHeader) -> Self {
+		VorbisInfo {
+			sample_rate : ident.audio_sample_rate,
+			channels : ident.audio_channels,
+			bitrate_min : ident.bitrate_minimum,
+			bitrate_nominal : ident.bitrate_nominal,
+			bitrate_max : ident.bitrate_maximum,
+			blocksize_0 : ident.blocksize_0,
+			blocksize_1 : ident.blocksize_1,
+			max_block_samples : 1 << ident.blocksize_1 >> 1,
+		}
+	}
+	/// Returns an estimate of the bit rate of the stream
+	///
+	/// This is the nominal bit rate if it is set, otherwise the average
+	/// of the minimum and maximum bit rates if both are set.
+	/// Returns 0 if the headers give no hint on the bit rate.
+	pub fn estimated_bitrate(&self) -> u32 {
+		if self.bitrate_nominal > 0 {
+			self.bitrate_nominal as u32
+		} else if self.bitrate_min > 0 && self.bitrate_max > 0 {
+			((self.bitrate_min as u64 + self.bitrate_max as u64) / 2) as u32
+		} else {
+			0
+		}
+	}
 }
 
 /**
@@ -254,19 +456,31 @@ pub fn read_header_ident(packet :&[u8]) -> Result<IdentHeader, HeaderReadError>
 			CachedBlocksizeDerived::from_blocksize(blocksize_0),
 			CachedBlocksizeDerived::from_blocksize(blocksize_1),
 		],
+		cached_bs_derived_half : HalfBlocksizeDerived::default(),
 	};
 	return Ok(hdr);
 }
 
-#[test]
-fn test_read_header_ident() {
-	// Valid ident header
-	let test_arr = &[0x01, 0x76, 0x6f, 0x72,
+/// Ident header of a stereo stream with a sample rate of 44100,
+/// and block sizes of 256 and 2048
+#[cfg(test)]
+pub(crate) const TEST_IDENT_PACKET :[u8; 30] = [0x01, 0x76, 0x6f, 0x72,
 	0x62, 0x69, 0x73, 0x00, 0x00, 0x00, 0x00, 0x02,
 	0x44, 0xac, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 	0x80, 0xb5, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
 	0xb8, 0x01];
-	let hdr = read_header_ident(test_arr).unwrap();
+
+/// Reads `TEST_IDENT_PACKET`, with the channel count replaced
+#[cfg(test)]
+pub(crate) fn test_ident_with_channels(channels :u8) -> IdentHeader {
+	let mut packet = TEST_IDENT_PACKET;
+	packet[11] = channels;
+	read_header_ident(&packet).unwrap()
+}
+
+#[test]
+fn test_read_header_ident() {
+	let hdr = read_header_ident(&TEST_IDENT_PACKET).unwrap();
 	assert_eq!(hdr.audio_channels, 2);
 	assert_eq!(hdr.audio_sample_rate, 0x0000ac44);
 	assert_eq!(hdr.bitrate_maximum, 0);
@@ -274,6 +488,153 @@ fn test_read_header_ident() {
 	assert_eq!(hdr.bitrate_minimum, 0);
 	assert_eq!(hdr.blocksize_0, 8);
 	assert_eq!(hdr.blocksize_1, 11);
+	assert_eq!(hdr.channel_layout().positions(),
+		Some(&[ChannelPosition::FrontLeft, ChannelPosition::FrontRight][..]));
+	assert_eq!(hdr.sample_rate_hz(), 44100);
+	assert_eq!(hdr.channel_count(), 2);
+	assert_eq!(hdr.channel_config(), ChannelConfig::Stereo);
+	assert_eq!(format!("{:?}", hdr), "IdentHeader { audio_channels: 2, \
+		audio_sample_rate: 44100, bitrate_maximum: 0, bitrate_nominal: 112000, \
+		bitrate_minimum: 0, blocksize_0: 8, blocksize_1: 11, .. }");
+}
+
+#[test]
+fn test_vorbis_info() {
+	let mut ident = test_ident_with_channels(2);
+	let info = VorbisInfo::from_ident(&ident);
+	assert_eq!(info.sample_rate, 44100);
+	assert_eq!(info.channels, 2);
+	assert_eq!((info.blocksize_0, info.blocksize_1), (8, 11));
+	assert_eq!(info.max_block_samples, 1024);
+	assert_eq!(info.estimated_bitrate(), 112000);
+
+	ident.bitrate_nominal = 0;
+	ident.bitrate_minimum = 96000;
+	ident.bitrate_maximum = 160000;
+	assert_eq!(VorbisInfo::from_ident(&ident).estimated_bitrate(), 128000);
+	ident.bitrate_maximum = -1;
+	assert_eq!(VorbisInfo::from_ident(&ident).estimated_bitrate(), 0);
+}
+
+#[test]
+fn test_channel_config() {
+	let hdr = test_ident_with_channels(6);
+	assert_eq!(hdr.channel_count(), 6);
+	assert_eq!(hdr.channel_config(), ChannelConfig::FivePointOne);
+	let layout = hdr.channel_layout();
+	assert_eq!(layout.position_of(ChannelPosition::FrontCenter), Some(1));
+	assert_eq!(layout.position_of(ChannelPosition::Lfe), Some(5));
+	assert_eq!(layout.position_of(ChannelPosition::SideLeft), None);
+	assert_eq!(ChannelLayout::from_channel_count(7)
+		.position_of(ChannelPosition::RearCenter), Some(5));
+}
+
+#[test]
+fn test_format_compatible() {
+	let mut test_arr = TEST_IDENT_PACKET;
+	let hdr_44100 = read_header_ident(&test_arr).unwrap();
+	assert!(hdr_44100.format_compatible(&hdr_44100));
+
+	// Same header, but with a sample rate of 48000
+	test_arr[12] = 0x80;
+	test_arr[13] = 0xbb;
+	let hdr_48000 = read_header_ident(&test_arr).unwrap();
+	assert_eq!(hdr_48000.audio_sample_rate, 48000);
+	assert!(!hdr_44100.format_compatible(&hdr_48000));
+	assert!(!hdr_48000.format_compatible(&hdr_44100));
+
+	// Same header, but with a different channel count
+	test_arr[11] = 0x01;
+	let hdr_mono = read_header_ident(&test_arr).unwrap();
+	assert!(!hdr_48000.format_compatible(&hdr_mono));
+}
+
+#[test]
+fn test_channel_layout_unknown() {
+	let hdr = test_ident_with_channels(10);
+	assert_eq!(hdr.audio_channels, 10);
+	assert_eq!(hdr.channel_layout(), ChannelLayout::Unknown(10));
+	assert_eq!(hdr.channel_layout().positions(), None);
+	assert_eq!(hdr.channel_layout().position_of(ChannelPosition::FrontLeft), None);
+}
+
+/// Writes values into a vorbis packet, least significant bit first
+#[cfg(test)]
+struct TestBitWriter {
+	data :Vec<u8>,
+	bit :usize,
+}
+
+#[cfg(test)]
+impl TestBitWriter {
+	fn new() -> Self {
+		TestBitWriter {
+			data : Vec::new(),
+			bit : 0,
+		}
+	}
+	fn write(&mut self, val :u32, bits :usize) -> &mut Self {
+		for i in 0 .. bits {
+			if self.bit % 8 == 0 {
+				self.data.push(0);
+			}
+			let last = self.data.len() - 1;
+			self.data[last] |= (((val >> i) & 1) as u8) << (self.bit % 8);
+			self.bit += 1;
+		}
+		self
+	}
+}
+
+#[test]
+fn test_decode_unknown_channel_layout() {
+	use audio::{PreviousWindowRight, read_audio_packet};
+	let ident = test_ident_with_channels(10);
+	assert_eq!(ident.channel_layout(), ChannelLayout::Unknown(10));
+
+	// A minimal setup header, with a single mode using short blocks,
+	// and a single submap for all channels, without coupling
+	let mut w = TestBitWriter::new();
+	w.write(5, 8);
+	for b in b"vorbis".iter() {
+		w.write(*b as u32, 8);
+	}
+	// One codebook with two entries of length 1, without lookup
+	w.write(0, 8).write(0x564342, 24).write(1, 16).write(2, 24)
+		.write(0, 1).write(0, 1).write(0, 5).write(0, 5).write(0, 4);
+	// One time domain transform
+	w.write(0, 6).write(0, 16);
+	// One floor of type 1 without partitions, with a range of 256
+	w.write(0, 6).write(1, 16).write(0, 5).write(0, 2).write(8, 4);
+	// One residue of type 1, with an empty range
+	w.write(0, 6).write(1, 16).write(0, 24).write(0, 24).write(31, 24)
+		.write(0, 6).write(0, 8).write(0, 3).write(0, 1);
+	// One mapping with one submap, without coupling
+	w.write(0, 6).write(0, 16).write(0, 1).write(0, 1).write(0, 2)
+		.write(0, 8).write(0, 8).write(0, 8);
+	// One mode, and the framing bit
+	w.write(0, 6).write(0, 1).write(0, 16).write(0, 16).write(0, 8).write(1, 1);
+	let setup = read_header_setup(&w.data, ident.audio_channels,
+		(ident.blocksize_0, ident.blocksize_1)).unwrap();
+
+	// Audio packets using the mode, with the floors of
+	// the even channels used and the odd ones unused
+	let mut w = TestBitWriter::new();
+	w.write(0, 1);
+	for ch in 0 .. 10 {
+		if ch % 2 == 0 {
+			w.write(1, 1).write(ch * 20, 8).write(ch * 10, 8);
+		} else {
+			w.write(0, 1);
+		}
+	}
+	let mut pwr = PreviousWindowRight::new();
+	for i in 0 .. 4 {
+		let pck = read_audio_packet(&ident, &setup, &w.data, &mut pwr).unwrap();
+		assert_eq!(pck.len(), 10);
+		let expected_len = if i == 0 { 0 } else { 1 << (ident.blocksize_0 - 1) };
+		assert!(pck.iter().all(|ch| ch.len() == expected_len));
+	}
 }
 
 /**
@@ -297,55 +658,543 @@ pub struct CommentHeader {
 	pub comment_list :Vec<(String, String)>,
 }
 
+/// Loop points of a stream, in samples
+///
+/// Obtained via `CommentHeader::loop_points`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoopPoints {
+	/// The first sample of the loop
+	pub start :u64,
+	/// The sample after the last sample of the loop
+	///
+	/// If `None`, the loop extends until the end of the stream.
+	pub end :Option<u64>,
+}
+
+/// ReplayGain information of a stream
+///
+/// Obtained via `CommentHeader::replay_gain`.
+/// Fields are `None` if the respective tag is absent or malformed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ReplayGain {
+	/// The gain to apply to the track, in dB
+	pub track_gain_db :Option<f32>,
+	/// The gain to apply to the album, in dB
+	pub album_gain_db :Option<f32>,
+	/// The peak sample value of the track, relative to full scale
+	pub track_peak :Option<f32>,
+	/// The peak sample value of the album, relative to full scale
+	pub album_peak :Option<f32>,
+}
+
+/// A picture embedded into the comments
+///
+/// Obtained via `CommentHeader::pictures`. The fields follow
+/// the picture metadata block of the FLAC format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Picture {
+	/// The type of the picture, as defined by the ID3v2 APIC frame,
+	/// e.g. 3 for the front cover
+	pub picture_type :u32,
+	/// The MIME type of the image data, e.g. `image/png`
+	///
+	/// It may also be `-->`, in which case the data
+	/// is an URL to the image instead.
+	pub mime :String,
+	/// The description of the picture
+	pub description :String,
+	/// The width of the picture in pixels
+	pub width :u32,
+	/// The height of the picture in pixels
+	pub height :u32,
+	/// The color depth of the picture in bits per pixel
+	pub depth :u32,
+	/// The number of colors for indexed pictures, or 0
+	pub colors :u32,
+	/// The image data, in the format given by the MIME type
+	pub data :Vec<u8>,
+}
+
+/// Decodes standard base64, with optional padding
+///
+/// Whitespace is ignored, as some taggers break long values into lines.
+fn decode_base64(s :&str) -> Option<Vec<u8>> {
+	let mut res = Vec::with_capacity(s.len() / 4 * 3);
+	let mut acc :u32 = 0;
+	let mut bits = 0;
+	let mut padding = 0;
+	for b in s.bytes() {
+		let v = match b {
+			b'A' ..= b'Z' => b - b'A',
+			b'a' ..= b'z' => b - b'a' + 26,
+			b'0' ..= b'9' => b - b'0' + 52,
+			b'+' => 62,
+			b'/' => 63,
+			b'=' => {
+				padding += 1;
+				continue;
+			},
+			b' ' | b'\t' | b'\r' | b'\n' => continue,
+			_ => return None,
+		};
+		if padding > 0 {
+			// Data after the padding
+			return None;
+		}
+		acc = (acc << 6) | v as u32;
+		bits += 6;
+		if bits >= 8 {
+			bits -= 8;
+			res.push((acc >> bits) as u8);
+			acc &= (1 << bits) - 1;
+		}
+	}
+	// A single leftover character can't encode a whole byte
+	if bits >= 6 || padding > 2 {
+		return None;
+	}
+	return Some(res);
+}
+
+fn read_picture(data :&[u8]) -> Option<Picture> {
+	let mut rdr = Cursor::new(data);
+	macro_rules! read_u32 {
+		() => {
+			rdr.read_u32::<BigEndian>().ok()?
+		}
+	}
+	macro_rules! read_bytes {
+		() => {{
+			let len = read_u32!() as usize;
+			let remaining = data.len() - rdr.position() as usize;
+			if len > remaining {
+				return None;
+			}
+			let mut bytes = vec![0; len];
+			rdr.read_exact(&mut bytes).ok()?;
+			bytes
+		}}
+	}
+	let picture_type = read_u32!();
+	let mime = String::from_utf8(read_bytes!()).ok()?;
+	let description = String::from_utf8(read_bytes!()).ok()?;
+	let width = read_u32!();
+	let height = read_u32!();
+	let depth = read_u32!();
+	let colors = read_u32!();
+	let data = read_bytes!();
+	return Some(Picture {
+		picture_type,
+		mime,
+		description,
+		width,
+		height,
+		depth,
+		colors,
+		data,
+	});
+}
+
+impl CommentHeader {
+	/// Returns the value of the first comment with the given key
+	///
+	/// As required by the spec, keys are compared case insensitively,
+	/// with only ASCII characters being case folded.
+	/// Comments without a value (`KEY=`) yield `Some("")`.
+	pub fn get(&self, key :&str) -> Option<&str> {
+		self.comment_list.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case(key))
+			.map(|(_, v)| v.as_str())
+	}
+	/// Returns the values of all comments with the given key
+	///
+	/// Keys like `ARTIST` may occur multiple times. The values
+	/// are returned in the order they appear in the header.
+	/// Keys are compared like in `get`.
+	pub fn get_all<'a>(&'a self, key :&'a str) -> impl Iterator<Item=&'a str> + 'a {
+		self.comment_list.iter()
+			.filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+			.map(|(_, v)| v.as_str())
+	}
+	/// Returns the pictures embedded via `METADATA_BLOCK_PICTURE` comments
+	///
+	/// Each such comment contains a base64 encoded FLAC picture block,
+	/// usually the cover art of the album.
+	/// Malformed comments are skipped. If there are picture comments,
+	/// but none of them could be read, an error is returned.
+	pub fn pictures(&self) -> Result<Vec<Picture>, HeaderReadError> {
+		let mut found_any = false;
+		let pictures = self.get_all("METADATA_BLOCK_PICTURE")
+			.inspect(|_| found_any = true)
+			.filter_map(decode_base64)
+			.filter_map(|data| read_picture(&data))
+			.collect::<Vec<_>>();
+		if found_any && pictures.is_empty() {
+			try!(Err(HeaderReadError::HeaderBadFormat));
+		}
+		return Ok(pictures);
+	}
+	/// Returns the ReplayGain information stored in the comments
+	///
+	/// It is read from the `REPLAYGAIN_TRACK_GAIN`, `REPLAYGAIN_ALBUM_GAIN`,
+	/// `REPLAYGAIN_TRACK_PEAK` and `REPLAYGAIN_ALBUM_PEAK` tags.
+	/// Gains are commonly written with a " dB" suffix, like `-6.5 dB`,
+	/// which is removed prior to parsing.
+	pub fn replay_gain(&self) -> ReplayGain {
+		let get_f32 = |key :&str| -> Option<f32> {
+			let v = self.get(key)?.trim();
+			let v = if v.len() >= 2 && v.as_bytes()[v.len() - 2 ..].eq_ignore_ascii_case(b"db") {
+				v[.. v.len() - 2].trim_end()
+			} else {
+				v
+			};
+			v.parse().ok()
+		};
+		return ReplayGain {
+			track_gain_db : get_f32("REPLAYGAIN_TRACK_GAIN"),
+			album_gain_db : get_f32("REPLAYGAIN_ALBUM_GAIN"),
+			track_peak : get_f32("REPLAYGAIN_TRACK_PEAK"),
+			album_peak : get_f32("REPLAYGAIN_ALBUM_PEAK"),
+		};
+	}
+	fn get_comment_u64(&self, keys :&[&str]) -> Option<u64> {
+		keys.iter()
+			.filter_map(|key| self.get(key))
+			.filter_map(|v| v.trim().parse().ok())
+			.next()
+	}
+	/// Returns the loop points stored in the comments, if there are any
+	///
+	/// Many games store the loop points of background music
+	/// as sample indices in the comments. The start is read
+	/// from the `LOOPSTART` or `LOOP_START` tags, the end
+	/// from the `LOOPEND` or `LOOP_END` tags, or, if neither
+	/// are present, computed from the `LOOPLENGTH` or `LOOP_LENGTH` tags.
+	///
+	/// Returns `None` if there is no valid loop start tag.
+	pub fn loop_points(&self) -> Option<LoopPoints> {
+		let start = self.get_comment_u64(&["LOOPSTART", "LOOP_START"])?;
+		let end = self.get_comment_u64(&["LOOPEND", "LOOP_END"])
+			.or_else(|| self.get_comment_u64(&["LOOPLENGTH", "LOOP_LENGTH"])
+				.and_then(|len| start.checked_add(len)));
+		return Some(LoopPoints {
+			start,
+			end,
+		});
+	}
+}
+
+/// Builds a comment header with the given comments
+#[cfg(test)]
+fn test_comment_header<V :AsRef<str>>(comments :&[(&str, V)]) -> CommentHeader {
+	CommentHeader {
+		vendor : String::new(),
+		comment_list : comments.iter()
+			.map(|(k, v)| (k.to_string(), v.as_ref().to_string())).collect(),
+	}
+}
+
+#[test]
+fn test_loop_points() {
+	let hdr = test_comment_header::<&str>;
+	assert_eq!(hdr(&[("TITLE", "Foo")]).loop_points(), None);
+	assert_eq!(hdr(&[("LOOPSTART", "44100")]).loop_points(),
+		Some(LoopPoints { start : 44100, end : None }));
+	assert_eq!(hdr(&[("LOOPSTART", "44100"), ("LOOPLENGTH", "88200")]).loop_points(),
+		Some(LoopPoints { start : 44100, end : Some(132300) }));
+	assert_eq!(hdr(&[("loop_start", "10"), ("LOOP_END", "20")]).loop_points(),
+		Some(LoopPoints { start : 10, end : Some(20) }));
+	assert_eq!(hdr(&[("LOOPSTART", "abc")]).loop_points(), None);
+}
+
+#[test]
+fn test_replay_gain() {
+	let hdr = test_comment_header::<&str>;
+	assert_eq!(hdr(&[("TITLE", "Foo")]).replay_gain(), ReplayGain::default());
+	assert_eq!(hdr(&[
+		("REPLAYGAIN_TRACK_GAIN", "-6.48 dB"),
+		("REPLAYGAIN_TRACK_PEAK", "0.98765"),
+		("replaygain_album_gain", "+1.5dB"),
+		("REPLAYGAIN_ALBUM_PEAK", " 1.2 "),
+	]).replay_gain(), ReplayGain {
+		track_gain_db : Some(-6.48),
+		album_gain_db : Some(1.5),
+		track_peak : Some(0.98765),
+		album_peak : Some(1.2),
+	});
+	// Malformed values only affect their own field
+	assert_eq!(hdr(&[
+		("REPLAYGAIN_TRACK_GAIN", "loud"),
+		("REPLAYGAIN_ALBUM_GAIN", "3 DB"),
+		("REPLAYGAIN_TRACK_PEAK", "dB"),
+		("REPLAYGAIN_ALBUM_PEAK", "1\u{e9}"),
+	]).replay_gain(), ReplayGain {
+		album_gain_db : Some(3.0),
+		.. ReplayGain::default()
+	});
+}
+
+#[test]
+fn test_decode_base64() {
+	assert_eq!(decode_base64(""), Some(vec![]));
+	assert_eq!(decode_base64("TWFu"), Some(b"Man".to_vec()));
+	assert_eq!(decode_base64("TWE="), Some(b"Ma".to_vec()));
+	assert_eq!(decode_base64("TQ=="), Some(b"M".to_vec()));
+	assert_eq!(decode_base64("TQ"), Some(b"M".to_vec()));
+	assert_eq!(decode_base64("TW\nFu"), Some(b"Man".to_vec()));
+	assert_eq!(decode_base64("+/+/"), Some(vec![0xfb, 0xff, 0xbf]));
+	assert_eq!(decode_base64("T"), None);
+	assert_eq!(decode_base64("TQ==TQ=="), None);
+	assert_eq!(decode_base64("TW!u"), None);
+}
+
+#[test]
+fn test_pictures() {
+	fn block(mime :&str, data :&[u8]) -> Vec<u8> {
+		let mut r = Vec::new();
+		let u32_be = |r :&mut Vec<u8>, v :u32| r.extend_from_slice(&[
+			(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]);
+		u32_be(&mut r, 3);
+		u32_be(&mut r, mime.len() as u32);
+		r.extend_from_slice(mime.as_bytes());
+		u32_be(&mut r, 5);
+		r.extend_from_slice(b"Cover");
+		for v in [600, 400, 24, 0].iter() {
+			u32_be(&mut r, *v);
+		}
+		u32_be(&mut r, data.len() as u32);
+		r.extend_from_slice(data);
+		r
+	}
+	fn base64(data :&[u8]) -> String {
+		const CHARS :&[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+		let mut r = String::new();
+		for chunk in data.chunks(3) {
+			let v = chunk.iter().enumerate()
+				.fold(0u32, |v, (i, b)| v | (*b as u32) << (16 - 8 * i));
+			for i in 0 .. 4 {
+				if i <= chunk.len() {
+					r.push(CHARS[(v >> (18 - 6 * i) & 63) as usize] as char);
+				} else {
+					r.push('=');
+				}
+			}
+		}
+		r
+	}
+	let hdr = test_comment_header::<String>;
+	let png = block("image/png", &[0x89, b'P', b'N', b'G', 1, 2, 3]);
+	let jpg = block("image/jpeg", &[0xff, 0xd8, 0xff]);
+
+	assert_eq!(hdr(&[("TITLE", "Foo".to_string())]).pictures(), Ok(vec![]));
+
+	let pictures = hdr(&[
+		("METADATA_BLOCK_PICTURE", base64(&png)),
+		// Truncated picture block
+		("METADATA_BLOCK_PICTURE", base64(&jpg[..jpg.len() - 1])),
+		// Not base64
+		("METADATA_BLOCK_PICTURE", "%%%".to_string()),
+		("metadata_block_picture", base64(&jpg)),
+	]).pictures().unwrap();
+	assert_eq!(pictures.len(), 2);
+	assert_eq!(pictures[0], Picture {
+		picture_type : 3,
+		mime : "image/png".to_string(),
+		description : "Cover".to_string(),
+		width : 600,
+		height : 400,
+		depth : 24,
+		colors : 0,
+		data : vec![0x89, b'P', b'N', b'G', 1, 2, 3],
+	});
+	assert_eq!(pictures[1].mime, "image/jpeg");
+	assert_eq!(pictures[1].data, vec![0xff, 0xd8, 0xff]);
+
+	assert_eq!(hdr(&[("METADATA_BLOCK_PICTURE", "AAAA".to_string())]).pictures(),
+		Err(HeaderReadError::HeaderBadFormat));
+}
+
+#[test]
+fn test_comment_get() {
+	let hdr = CommentHeader {
+		vendor : String::new(),
+		comment_list : [("TITLE", "Foo"), ("artist", "A"), ("Artist", "B"),
+				("EMPTY", ""), ("ARTIST", "C"), ("\u{130}D", "x")].iter()
+			.map(|&(k, v)| (k.to_string(), v.to_string())).collect(),
+	};
+	assert_eq!(hdr.get("title"), Some("Foo"));
+	assert_eq!(hdr.get("ARTIST"), Some("A"));
+	assert_eq!(hdr.get_all("ARTIST").collect::<Vec<_>>(), vec!["A", "B", "C"]);
+	assert_eq!(hdr.get("empty"), Some(""));
+	assert_eq!(hdr.get("ALBUM"), None);
+	assert_eq!(hdr.get_all("ALBUM").count(), 0);
+	// Only ASCII characters are case folded
+	assert_eq!(hdr.get("\u{130}d"), Some("x"));
+	assert_eq!(hdr.get("id"), None);
+}
+
+/**
+Configuration of the deviations from the vorbis spec that are tolerated
+
+Some encoders produce files that slightly violate the spec.
+By default, lewton follows other decoder libraries and tolerates
+those deviations where possible. For applications like conformance
+checkers this is undesirable, so each tolerance can be turned off
+individually, or all at once via the `strict_spec` constructor.
+
+The `Default` implementation returns the lenient configuration.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StrictnessConfig {
+	/// Whether to silently skip comments that are not UTF-8 formatted.
+	///
+	/// If `false`, `HeaderReadError::Utf8DecodeError` is returned instead.
+	pub tolerate_non_utf8_comments :bool,
+	/// Whether to silently skip comments that don't contain a `=` sign.
+	///
+	/// If `false`, `HeaderReadError::HeaderBadFormat` is returned instead.
+	pub tolerate_missing_comment_separator :bool,
+	/// Whether to accept comment field names with characters
+	/// outside of `0x20` through `0x7D` (`0x3D` excluded).
+	///
+	/// If `false`, `HeaderReadError::HeaderBadFormat` is returned instead.
+	pub tolerate_invalid_comment_field_names :bool,
+	/// Whether to ignore packets that follow the setup header
+	/// on the same ogg page.
+	///
+	/// The spec requires the first audio packet to begin a fresh page.
+	/// If `false`, `HeaderReadError::HeaderBadFormat` is returned
+	/// when the setup header doesn't end its page.
+	pub tolerate_packets_after_setup :bool,
+}
+
+impl StrictnessConfig {
+	/// Returns the configuration where all deviations are tolerated
+	pub fn lenient() -> Self {
+		StrictnessConfig {
+			tolerate_non_utf8_comments : true,
+			tolerate_missing_comment_separator : true,
+			tolerate_invalid_comment_field_names : true,
+			tolerate_packets_after_setup : true,
+		}
+	}
+	/// Returns the configuration where no deviation is tolerated
+	pub fn strict_spec() -> Self {
+		StrictnessConfig {
+			tolerate_non_utf8_comments : false,
+			tolerate_missing_comment_separator : false,
+			tolerate_invalid_comment_field_names : false,
+			tolerate_packets_after_setup : false,
+		}
+	}
+}
+
+impl Default for StrictnessConfig {
+	fn default() -> Self {
+		StrictnessConfig::lenient()
+	}
+}
+
 /**
 Reading the Comment header
 
 You should call this function with the second packet in the stream.
 
-The function does not check whether the comment field names consist
-of characters `0x20` through `0x7D` (`0x3D` excluded), as the vorbis
-spec requires.
+The function is lenient: comments that are not UTF-8 formatted or
+that lack a `=` sign are silently skipped, and it does not check
+whether the comment field names consist of characters `0x20` through
+`0x7D` (`0x3D` excluded), as the vorbis spec requires.
+Use `read_header_comment_strict` to reject such headers instead.
 */
 pub fn read_header_comment(packet :&[u8]) -> Result<CommentHeader, HeaderReadError> {
+	read_header_comment_with_config(packet, StrictnessConfig::default())
+}
+
+/**
+Reading the Comment header, rejecting any spec violations
+
+Unlike `read_header_comment`, this returns
+`HeaderReadError::Utf8DecodeError` for comments that are not UTF-8
+formatted, and `HeaderReadError::HeaderBadFormat` for comments that
+lack a `=` sign or whose field names contain invalid characters.
+This is meant for validators, for playback the lenient function
+is the better choice.
+*/
+pub fn read_header_comment_strict(packet :&[u8]) -> Result<CommentHeader, HeaderReadError> {
+	read_header_comment_with_config(packet, StrictnessConfig::strict_spec())
+}
+
+/**
+Reading the Comment header, with the given strictness settings
+
+Like `read_header_comment`, but comments that are not UTF-8
+formatted or that lack a `=` sign are only skipped, and invalid
+field names only accepted, if the passed configuration tolerates it.
+*/
+pub fn read_header_comment_with_config(packet :&[u8], config :StrictnessConfig) ->
+		Result<CommentHeader, HeaderReadError> {
 	let mut rdr = Cursor::new(packet);
 	let hd_id = try!(read_header_begin_cursor(&mut rdr));
 	if hd_id != 3 {
 		try!(Err(HeaderReadError::HeaderBadType(hd_id)));
 	}
+	// The declared lengths and counts are checked against
+	// the number of bytes left in the packet before allocating
+	// anything, so that a crafted header can't make us
+	// allocate large amounts of memory.
+	macro_rules! remaining {
+		() => {
+			packet.len() - rdr.position() as usize
+		};
+	}
 	// First read the vendor string
 	let vendor_length = try!(rdr.read_u32::<LittleEndian>()) as usize;
+	if vendor_length > remaining!() {
+		try!(Err(HeaderReadError::HeaderBadFormat));
+	}
 	let mut vendor_buf = vec![0; vendor_length]; // TODO fix this, we initialize memory for NOTHING!!! Out of some reason, this is seen as "unsafe" by rustc.
 	try!(rdr.read_exact(&mut vendor_buf));
 	let vendor = try!(String::from_utf8(vendor_buf));
 
 	// Now read the comments
 	let comment_count = try!(rdr.read_u32::<LittleEndian>()) as usize;
+	// Every comment needs at least four bytes for its length
+	if comment_count > remaining!() / 4 {
+		try!(Err(HeaderReadError::HeaderBadFormat));
+	}
 	let mut comment_list = Vec::with_capacity(comment_count);
 	for _ in 0 .. comment_count {
 		let comment_length = try!(rdr.read_u32::<LittleEndian>()) as usize;
+		if comment_length > remaining!() {
+			try!(Err(HeaderReadError::HeaderBadFormat));
+		}
 		let mut comment_buf = vec![0; comment_length]; // TODO fix this, we initialize memory for NOTHING!!! Out of some reason, this is seen as "unsafe" by rustc.
 		try!(rdr.read_exact(&mut comment_buf));
 		let comment = match String::from_utf8(comment_buf) {
 			Ok(comment) => comment,
-			// Uncomment for closer compliance with the spec.
 			// The spec explicitly states that the comment entries
 			// should be UTF-8 formatted, however it seems that other
 			// decoder libraries tolerate non-UTF-8 formatted strings
 			// in comments. This has led to some files circulating
 			// with such errors inside. If we deny to decode such files,
-			// lewton would be the odd one out. Thus we just
+			// lewton would be the odd one out. Thus by default we just
 			// gracefully ignore them.
-			Err(_) => continue,
+			Err(_) if config.tolerate_non_utf8_comments => continue,
+			Err(_) => try!(Err(HeaderReadError::Utf8DecodeError)),
 		};
 		let eq_idx = match comment.find("=") {
 			Some(k) => k,
-			// Uncomment for closer compliance with the spec.
 			// It appears that some ogg files have fields without a = sign in the comments.
 			// Well there is not much we can do but gracefully ignore their stuff.
-			None => continue // try!(Err(HeaderReadError::HeaderBadFormat))
+			None if config.tolerate_missing_comment_separator => continue,
+			None => try!(Err(HeaderReadError::HeaderBadFormat)),
 		};
 		let (key_eq, val) = comment.split_at(eq_idx + 1);
 		let (key, _) = key_eq.split_at(eq_idx);
+		if !config.tolerate_invalid_comment_field_names &&
+				!key.bytes().all(|b| b >= 0x20 && b <= 0x7d && b != 0x3d) {
+			try!(Err(HeaderReadError::HeaderBadFormat));
+		}
 		comment_list.push((String::from(key), String::from(val)));
 	}
 	let framing = try!(rdr.read_u8());
@@ -359,6 +1208,97 @@ pub fn read_header_comment(packet :&[u8]) -> Result<CommentHeader, HeaderReadErr
 	return Ok(hdr);
 }
 
+#[cfg(test)]
+fn build_comment_header(comments :&[&[u8]]) -> Vec<u8> {
+	fn push_u32(v :&mut Vec<u8>, n :u32) {
+		v.extend_from_slice(&[n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]);
+	}
+	let mut r = vec![0x03, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73];
+	let vendor = b"lewton";
+	push_u32(&mut r, vendor.len() as u32);
+	r.extend_from_slice(vendor);
+	push_u32(&mut r, comments.len() as u32);
+	for c in comments {
+		push_u32(&mut r, c.len() as u32);
+		r.extend_from_slice(c);
+	}
+	r.push(1);
+	r
+}
+
+#[test]
+fn test_read_header_comment_huge_counts() {
+	// A comment count that can't fit into the packet
+	let mut hdr = build_comment_header(&[b"A=b"]);
+	let count_pos = 7 + 4 + 6;
+	hdr[count_pos .. count_pos + 4].copy_from_slice(&[0xff, 0xff, 0xff, 0x7f]);
+	assert_eq!(read_header_comment(&hdr).unwrap_err(), HeaderReadError::HeaderBadFormat);
+
+	// A comment length that can't fit into the packet
+	let mut hdr = build_comment_header(&[b"A=b"]);
+	let len_pos = count_pos + 4;
+	hdr[len_pos .. len_pos + 4].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+	assert_eq!(read_header_comment(&hdr).unwrap_err(), HeaderReadError::HeaderBadFormat);
+
+	// A vendor length that can't fit into the packet
+	let mut hdr = build_comment_header(&[]);
+	hdr[7 .. 11].copy_from_slice(&[0x00, 0x00, 0x00, 0x01]);
+	assert_eq!(read_header_comment(&hdr).unwrap_err(), HeaderReadError::HeaderBadFormat);
+
+	// Lengths that exactly fit are still accepted
+	let hdr = build_comment_header(&[b"A=b"]);
+	assert_eq!(read_header_comment(&hdr).unwrap().comment_list,
+		vec![(String::from("A"), String::from("b"))]);
+}
+
+#[test]
+fn test_read_header_comment_strictness() {
+	let strict = StrictnessConfig::strict_spec();
+
+	let valid = build_comment_header(&[b"TITLE=Foo", b"ARTIST=Bar"]);
+	let hdr = read_header_comment_with_config(&valid, strict).unwrap();
+	assert_eq!(hdr.vendor, "lewton");
+	assert_eq!(hdr.comment_list, vec![("TITLE".to_string(), "Foo".to_string()),
+		("ARTIST".to_string(), "Bar".to_string())]);
+
+	// Missing = sign
+	let no_eq = build_comment_header(&[b"TITLE=Foo", b"NOSEPARATOR"]);
+	let hdr = read_header_comment(&no_eq).unwrap();
+	assert_eq!(hdr.comment_list.len(), 1);
+	match read_header_comment_with_config(&no_eq, strict) {
+		Err(HeaderReadError::HeaderBadFormat) => (),
+		_ => panic!("Expected HeaderBadFormat error"),
+	}
+
+	// Non UTF-8 comment
+	let non_utf8 = build_comment_header(&[b"TITLE=\xff\xfe", b"ARTIST=Bar"]);
+	let hdr = read_header_comment(&non_utf8).unwrap();
+	assert_eq!(hdr.comment_list, vec![("ARTIST".to_string(), "Bar".to_string())]);
+	match read_header_comment_with_config(&non_utf8, strict) {
+		Err(HeaderReadError::Utf8DecodeError) => (),
+		_ => panic!("Expected Utf8DecodeError error"),
+	}
+
+	// Missing framing bit, rejected in both modes
+	let mut no_framing = valid.clone();
+	*no_framing.last_mut().unwrap() = 0;
+	assert!(read_header_comment(&no_framing).is_err());
+	assert!(read_header_comment_with_config(&no_framing, strict).is_err());
+
+	// Field names with invalid characters
+	for name in [&b"TI\x7eTLE=Foo"[..], b"TI\x1fTLE=Foo", b"\xc3\xa4=Foo"].iter() {
+		let invalid = build_comment_header(&[name, b"ARTIST=Bar"]);
+		assert_eq!(read_header_comment(&invalid).unwrap().comment_list.len(), 2);
+		assert_eq!(read_header_comment_strict(&invalid).unwrap_err(),
+			HeaderReadError::HeaderBadFormat);
+	}
+	let edge = build_comment_header(&[b" }=Foo"]);
+	assert_eq!(read_header_comment_strict(&edge).unwrap().comment_list,
+		vec![(" }".to_string(), "Foo".to_string())]);
+	assert_eq!(read_header_comment_strict(&valid).unwrap(),
+		read_header_comment(&valid).unwrap());
+}
+
 #[derive(Clone)]
 pub(crate) struct Codebook {
 	pub codebook_dimensions :u16,
@@ -421,6 +1361,9 @@ pub(crate) struct FloorTypeOne {
 	pub floor1_class_masterbooks :Vec<u8>,
 	pub floor1_x_list :Vec<u32>,
 	pub floor1_x_list_sorted :Vec<(usize, u32)>,
+	/// The low and high neighbor indices of the
+	/// x list entries, starting with the third entry
+	pub floor1_neighbors :Vec<(usize, usize)>,
 }
 
 #[derive(Clone)]
@@ -476,6 +1419,263 @@ pub struct SetupHeader {
 	pub(crate) modes :Vec<ModeInfo>,
 }
 
+// The tables are large and not very meaningful when printed,
+// so only their sizes are shown.
+impl fmt::Debug for SetupHeader {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		fmt.debug_struct("SetupHeader")
+			.field("codebooks", &self.codebooks.len())
+			.field("floors", &self.floors.len())
+			.field("residues", &self.residues.len())
+			.field("mappings", &self.mappings.len())
+			.field("modes", &self.modes.len())
+			.finish()
+	}
+}
+
+impl SetupHeader {
+	/// Returns the number of mappings in the setup header
+	pub fn mapping_count(&self) -> usize {
+		self.mappings.len()
+	}
+	/// Returns a read-only view on the mapping with the given index
+	///
+	/// Returns `None` if there is no mapping with that index.
+	pub fn mapping<'a>(&'a self, index :usize) -> Option<MappingInfo<'a>> {
+		self.mappings.get(index).map(|mapping| MappingInfo { mapping })
+	}
+	/// Returns the number of modes in the setup header
+	pub fn mode_count(&self) -> usize {
+		self.modes.len()
+	}
+	/// Returns a read-only view on the mapping the mode with the given index uses
+	///
+	/// The mode of a packet can be obtained via `audio::get_packet_mode`.
+	/// Returns `None` if there is no mode with that index.
+	pub fn mapping_info<'a>(&'a self, mode_index :usize) -> Option<MappingInfo<'a>> {
+		self.modes.get(mode_index)
+			.and_then(|mode| self.mapping(mode.mode_mapping as usize))
+	}
+	/// Returns whether any of the mappings couples channels
+	///
+	/// If this returns `false`, the inverse coupling step
+	/// of audio packet decoding is skipped.
+	pub fn uses_coupling(&self) -> bool {
+		self.mappings.iter().any(|m| !m.mapping_magnitudes.is_empty())
+	}
+	/// Returns the total number of coupling steps over all mappings
+	pub fn coupling_step_count(&self) -> usize {
+		self.mappings.iter().map(|m| m.mapping_magnitudes.len()).sum()
+	}
+	/// Returns whether the mappings are consistent with
+	/// the channel count declared by the ident header
+	///
+	/// This is the case if each mapping assigns a submap to every
+	/// channel, and only couples channels that exist.
+	/// Setup headers returned by `read_header_setup` are always
+	/// consistent with the channel count passed to it, so this is
+	/// mainly useful to check whether an ident header and a setup header
+	/// obtained from different sources fit together.
+	pub fn declared_channels_consistent(&self, ident :&IdentHeader) -> bool {
+		self.channels_consistent(ident.audio_channels)
+	}
+	fn channels_consistent(&self, audio_channels :u8) -> bool {
+		self.mappings.iter().all(|m| {
+			let submaps = m.mapping_submap_floors.len();
+			m.mapping_mux.len() == audio_channels as usize &&
+				m.mapping_mux.iter().all(|&sm| (sm as usize) < submaps) &&
+				m.mapping_magnitudes.iter().chain(m.mapping_angles.iter())
+					.all(|&ch| ch < audio_channels)
+		})
+	}
+	/// Returns a fingerprint of the setup header
+	///
+	/// The fingerprint is a hash over the structure of the codebooks,
+	/// floors, residues, mappings and modes. Setup headers that decode
+	/// to the same structure have the same fingerprint, so it can be
+	/// used as key to cache state derived from the setup header,
+	/// e.g. across files produced by the same encoder.
+	///
+	/// The fingerprint doesn't depend on the platform,
+	/// but it may change between releases of this crate,
+	/// so it shouldn't be persisted.
+	/// As with any hash, different setup headers may
+	/// have the same fingerprint, albeit it is very unlikely.
+	pub fn fingerprint(&self) -> u64 {
+		let mut h = Fingerprinter::new();
+		h.write_len(self.codebooks.len());
+		for cb in self.codebooks.iter() {
+			h.write_u32(cb.codebook_dimensions as u32);
+			match cb.codebook_vq_lookup_vec {
+				Some(ref v) => {
+					h.write_u8(1);
+					h.write_len(v.len());
+					for f in v.iter() {
+						h.write_u32(f.to_bits());
+					}
+				},
+				None => h.write_u8(0),
+			}
+			let desc_prog = cb.codebook_huffman_tree.desc_prog();
+			h.write_len(desc_prog.len());
+			for d in desc_prog.iter() {
+				h.write_u32(*d);
+			}
+		}
+		h.write_len(self.floors.len());
+		for floor in self.floors.iter() {
+			match *floor {
+				Floor::TypeZero(ref fl) => {
+					h.write_u8(0);
+					h.write_u8(fl.floor0_order);
+					h.write_u8(fl.floor0_amplitude_bits);
+					h.write_u8(fl.floor0_amplitude_offset);
+					h.write_u8(fl.floor0_number_of_books);
+					h.write_bytes(&fl.floor0_book_list);
+				},
+				Floor::TypeOne(ref fl) => {
+					h.write_u8(1);
+					h.write_u8(fl.floor1_multiplier);
+					h.write_bytes(&fl.floor1_partition_class);
+					h.write_bytes(&fl.floor1_class_dimensions);
+					h.write_bytes(&fl.floor1_class_subclasses);
+					h.write_len(fl.floor1_subclass_books.len());
+					for books in fl.floor1_subclass_books.iter() {
+						h.write_len(books.len());
+						for b in books.iter() {
+							h.write_u32(*b as u32);
+						}
+					}
+					h.write_bytes(&fl.floor1_class_masterbooks);
+					h.write_len(fl.floor1_x_list.len());
+					for x in fl.floor1_x_list.iter() {
+						h.write_u32(*x);
+					}
+				},
+			}
+		}
+		h.write_len(self.residues.len());
+		for res in self.residues.iter() {
+			h.write_u8(res.residue_type);
+			h.write_u32(res.residue_begin);
+			h.write_u32(res.residue_end);
+			h.write_u32(res.residue_partition_size);
+			h.write_u8(res.residue_classifications);
+			h.write_u8(res.residue_classbook);
+			h.write_len(res.residue_books.len());
+			for book in res.residue_books.iter() {
+				h.write_u8(book.vals_used);
+				h.write_bytes(&book.val_i);
+			}
+		}
+		h.write_len(self.mappings.len());
+		for mapping in self.mappings.iter() {
+			h.write_bytes(&mapping.mapping_magnitudes);
+			h.write_bytes(&mapping.mapping_angles);
+			h.write_bytes(&mapping.mapping_mux);
+			h.write_bytes(&mapping.mapping_submap_floors);
+			h.write_bytes(&mapping.mapping_submap_residues);
+		}
+		h.write_len(self.modes.len());
+		for mode in self.modes.iter() {
+			h.write_u8(mode.mode_blockflag as u8);
+			h.write_u8(mode.mode_mapping);
+		}
+		return h.finish();
+	}
+}
+
+/// Platform independent FNV-1a hasher for `SetupHeader::fingerprint`
+///
+/// We can't use the hashers of std, as their output
+/// isn't guaranteed to be stable.
+struct Fingerprinter {
+	state :u64,
+}
+
+impl Fingerprinter {
+	fn new() -> Self {
+		Fingerprinter { state : 0xcbf29ce484222325 }
+	}
+	fn write_u8(&mut self, v :u8) {
+		self.state ^= v as u64;
+		self.state = self.state.wrapping_mul(0x100000001b3);
+	}
+	fn write_u32(&mut self, v :u32) {
+		for b in v.to_le_bytes().iter() {
+			self.write_u8(*b);
+		}
+	}
+	fn write_len(&mut self, len :usize) {
+		self.write_u32(len as u32);
+	}
+	/// Writes the slice, prefixed by its length
+	fn write_bytes(&mut self, v :&[u8]) {
+		self.write_len(v.len());
+		for b in v.iter() {
+			self.write_u8(*b);
+		}
+	}
+	fn finish(&self) -> u64 {
+		self.state
+	}
+}
+
+/**
+Read-only view on a mapping of the setup header
+
+A mapping describes which channels are coupled with each other,
+and which submap each channel is assigned to. Each submap in turn
+specifies the floor and the residue used to decode its channels.
+
+Obtained via the `SetupHeader::mapping` function.
+*/
+#[derive(Clone, Copy)]
+pub struct MappingInfo<'a> {
+	mapping :&'a Mapping,
+}
+
+impl<'a> MappingInfo<'a> {
+	/// Returns the number of submaps of the mapping
+	pub fn submap_count(&self) -> usize {
+		self.mapping.mapping_submap_floors.len()
+	}
+	/// Returns the submap the given channel is assigned to
+	///
+	/// Returns `None` if the channel doesn't exist.
+	pub fn channel_to_submap(&self, channel :usize) -> Option<u8> {
+		self.mapping.mapping_mux.get(channel).cloned()
+	}
+	/// Returns the index of the floor the given submap uses
+	///
+	/// Returns `None` if the submap doesn't exist.
+	pub fn submap_floor(&self, submap :usize) -> Option<u8> {
+		self.mapping.mapping_submap_floors.get(submap).cloned()
+	}
+	/// Returns the index of the residue the given submap uses
+	///
+	/// Returns `None` if the submap doesn't exist.
+	pub fn submap_residue(&self, submap :usize) -> Option<u8> {
+		self.mapping.mapping_submap_residues.get(submap).cloned()
+	}
+	/// Returns the number of channel coupling steps of the mapping
+	///
+	/// If this is zero, the channels are not coupled.
+	pub fn coupling_steps(&self) -> usize {
+		self.mapping.mapping_magnitudes.len()
+	}
+	/// Returns the coupled channels as (magnitude, angle) pairs,
+	/// in the order of the coupling steps
+	pub fn coupling_pairs(&self) -> impl Iterator<Item=(u8, u8)> + 'a {
+		self.mapping.mapping_magnitudes.iter().cloned()
+			.zip(self.mapping.mapping_angles.iter().cloned())
+	}
+	/// Returns the submap each channel is assigned to, in channel order
+	pub fn mux(&self) -> &'a [u8] {
+		&self.mapping.mapping_mux
+	}
+}
+
 struct CodebookVqLookup {
 	codebook_lookup_type :u8,
 	codebook_minimum_value :f32,
@@ -538,9 +1738,15 @@ fn lookup_vec_val_decode(lup :&CodebookVqLookup, codebook_entries :u32, codebook
 /// the decoder might have to reject packages with the
 /// NoVqLookupForCodebook variant, but have to treat EndOfPacket
 /// as normal occurence.
+#[derive(Debug, PartialEq)]
 pub(crate) enum HuffmanVqReadErr {
 	EndOfPacket,
 	NoVqLookupForCodebook,
+	/// The codebook has no used entries
+	EmptyCodebook,
+	/// The lookup vector is too short for the
+	/// decoded entry and the codebook dimensions
+	LookupOutOfBounds,
 }
 
 impl <'a> BitpackCursor <'a> {
@@ -549,17 +1755,46 @@ impl <'a> BitpackCursor <'a> {
 
 		let idx = match self.read_huffman(&b.codebook_huffman_tree) {
 			Ok(v) => v as usize,
-			Err(_) => return Err(HuffmanVqReadErr::EndOfPacket),
+			Err(HuffmanReadErr::EndOfPacket) => return Err(HuffmanVqReadErr::EndOfPacket),
+			Err(HuffmanReadErr::EmptyTree) => return Err(HuffmanVqReadErr::EmptyCodebook),
 		};
 		let codebook_vq_lookup_vec :&[f32] = match b.codebook_vq_lookup_vec.as_ref() {
 			Some(ref v) => v,
 			None => return Err(HuffmanVqReadErr::NoVqLookupForCodebook),
 		};
 		let dim = b.codebook_dimensions as usize;
-		return Ok(&codebook_vq_lookup_vec[idx * dim .. (idx + 1) * dim]);
+		// As the lookup vector is computed from the codebook dimensions,
+		// this should never be out of bounds, but better be safe.
+		return match codebook_vq_lookup_vec.get(idx * dim .. (idx + 1) * dim) {
+			Some(v) => Ok(v),
+			None => Err(HuffmanVqReadErr::LookupOutOfBounds),
+		};
 	}
 }
 
+#[test]
+fn test_read_huffman_vq_single_entry() {
+	// Only entry 1 is used, so every read must return it,
+	// consuming a single bit, no matter its value
+	let codebook = Codebook {
+		codebook_dimensions : 2,
+		codebook_vq_lookup_vec : Some(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+		codebook_huffman_tree : VorbisHuffmanTree::load_from_array(&[0, 1, 0]).unwrap(),
+	};
+	let data = &[0b10];
+	let mut rdr = BitpackCursor::new(data);
+	assert_eq!(rdr.read_huffman_vq(&codebook), Ok(&[3.0, 4.0][..]));
+	assert_eq!(rdr.read_huffman_vq(&codebook), Ok(&[3.0, 4.0][..]));
+
+	// Inconsistent dimensions must not make us panic
+	let codebook = Codebook {
+		codebook_dimensions : 4,
+		.. codebook
+	};
+	let mut rdr = BitpackCursor::new(data);
+	assert_eq!(rdr.read_huffman_vq(&codebook), Err(HuffmanVqReadErr::LookupOutOfBounds));
+}
+
 static MAX_BASES_WITHOUT_OVERFLOW : &[u32] = &[
 	0xffffffff, 0xffffffff, 0x0000ffff, 0x00000659,
 	0x000000ff, 0x00000054, 0x00000028, 0x00000017,
@@ -776,15 +2011,9 @@ fn read_floor(rdr :&mut BitpackCursor, codebook_cnt :u16, blocksizes :(u8, u8))
 			let floor0_order = try!(rdr.read_u8());
 			let floor0_rate = try!(rdr.read_u16());
 			let floor0_bark_map_size = try!(rdr.read_u16());
+			// Being a six bit field, this is at most 63,
+			// so the amplitudes fit into 64 bits.
 			let floor0_amplitude_bits = try!(rdr.read_u6());
-			if floor0_amplitude_bits > 64 {
-				// Unfortunately the audio decoder part
-				// doesn't support values > 64 because rust has no
-				// 128 bit integers yet.
-				// TODO when support is added, remove this
-				// check.
-				try!(Err(HeaderReadError::HeaderBadFormat));
-			}
 			let floor0_amplitude_offset = try!(rdr.read_u8());
 			let floor0_number_of_books = try!(rdr.read_u4()) + 1;
 			let mut floor0_book_list = Vec::with_capacity(
@@ -898,6 +2127,7 @@ fn read_floor(rdr :&mut BitpackCursor, codebook_cnt :u16, blocksizes :(u8, u8))
 				}
 				last = el.1;
 			}
+			let floor1_neighbors = compute_floor1_neighbors(&floor1_x_list);
 
 			// Only now return the result
 			Ok(Floor::TypeOne(FloorTypeOne {
@@ -909,7 +2139,7 @@ fn read_floor(rdr :&mut BitpackCursor, codebook_cnt :u16, blocksizes :(u8, u8))
 				floor1_class_masterbooks,
 				floor1_x_list,
 				floor1_x_list_sorted,
-
+				floor1_neighbors,
 			}))
 		},
 		// Type greater than 1 is error condition per spec
@@ -1081,8 +2311,71 @@ fn read_mode_info(rdr :&mut BitpackCursor, mapping_count :u8) -> Result<ModeInfo
 /// can be obtained from the ident header.
 pub fn read_header_setup(packet :&[u8], audio_channels :u8, blocksizes :(u8, u8)) ->
 		Result<SetupHeader, HeaderReadError> {
+	read_header_setup_with_progress(packet, audio_channels, blocksizes, |_| ())
+}
+
+/// The part of the setup header that is being read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupStage {
+	Codebooks,
+	Floors,
+	Residues,
+	Mappings,
+	Modes,
+}
+
+/// Progress report of `read_header_setup_with_progress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetupProgress {
+	/// The part of the header the report is about
+	pub stage :SetupStage,
+	/// How many items of that part have been read so far
+	pub done :usize,
+	/// How many items the part has in total
+	pub total :usize,
+}
+
+/// Reading the setup header, reporting the progress
+///
+/// Like `read_header_setup`, but calls the passed closure each
+/// time a codebook, floor, residue, mapping or mode has been read.
+/// Setup headers can be several hundred kilobytes large,
+/// and most of the time is spent in building the codebooks,
+/// so this allows UIs to show progress during stream startup.
+pub fn read_header_setup_with_progress<F :FnMut(SetupProgress)>(packet :&[u8],
+		audio_channels :u8, blocksizes :(u8, u8), mut progress :F) ->
+		Result<SetupHeader, HeaderReadError> {
 	let mut rdr = BitpackCursor::new(packet);
-	let hd_id = try!(read_header_begin(&mut rdr));
+	read_header_setup_rdr(&mut rdr, audio_channels, blocksizes, &mut progress)
+}
+
+/// Reading the setup header, reporting the position of errors
+///
+/// Like `read_header_setup`, but on failure, the position inside the
+/// packet where the error occured is returned along with the error.
+pub fn read_header_setup_with_position(packet :&[u8], audio_channels :u8,
+		blocksizes :(u8, u8)) -> Result<SetupHeader, WithPosition<HeaderReadError>> {
+	let mut rdr = BitpackCursor::new(packet);
+	read_header_setup_rdr(&mut rdr, audio_channels, blocksizes, &mut |_| ())
+		.map_err(|error| {
+			let (byte_offset, bit_offset) = rdr.position();
+			WithPosition { error, byte_offset, bit_offset }
+		})
+}
+
+fn read_header_setup_rdr<F :FnMut(SetupProgress)>(rdr :&mut BitpackCursor,
+		audio_channels :u8, blocksizes :(u8, u8), progress :&mut F) ->
+		Result<SetupHeader, HeaderReadError> {
+	macro_rules! report {
+		($stage:ident, $done:expr, $total:expr) => {
+			progress(SetupProgress {
+				stage : SetupStage::$stage,
+				done : $done,
+				total : $total as usize,
+			});
+		}
+	}
+	let hd_id = try!(read_header_begin(rdr));
 	if hd_id != 5 {
 		try!(Err(HeaderReadError::HeaderBadType(hd_id)));
 	}
@@ -1095,8 +2388,9 @@ pub fn read_header_setup(packet :&[u8], audio_channels :u8, blocksizes :(u8, u8)
 	// 1. Read the codebooks
 	let vorbis_codebook_count :u16 = try!(rdr.read_u8()) as u16 + 1;
 	let mut codebooks = Vec::with_capacity(vorbis_codebook_count as usize);
-	for _ in 0 .. vorbis_codebook_count {
-		codebooks.push(try!(read_codebook(&mut rdr)));
+	for i in 0 .. vorbis_codebook_count {
+		codebooks.push(try!(read_codebook(rdr)));
+		report!(Codebooks, i as usize + 1, vorbis_codebook_count);
 	}
 
 	// 2. Read the time domain transforms
@@ -1110,31 +2404,35 @@ pub fn read_header_setup(packet :&[u8], audio_channels :u8, blocksizes :(u8, u8)
 	// 3. Read the floor values
 	let vorbis_floor_count :u8 = try!(rdr.read_u6()) + 1;
 	let mut floors = Vec::with_capacity(vorbis_floor_count as usize);
-	for _ in 0 .. vorbis_floor_count {
-		floors.push(try!(read_floor(&mut rdr, vorbis_codebook_count, blocksizes)));
+	for i in 0 .. vorbis_floor_count {
+		floors.push(try!(read_floor(rdr, vorbis_codebook_count, blocksizes)));
+		report!(Floors, i as usize + 1, vorbis_floor_count);
 	}
 
 	// 4. Read the residue values
 	let vorbis_residue_count :u8 = try!(rdr.read_u6()) + 1;
 	let mut residues = Vec::with_capacity(vorbis_residue_count as usize);
-	for _ in 0 .. vorbis_residue_count {
-		residues.push(try!(read_residue(&mut rdr, &codebooks)));
+	for i in 0 .. vorbis_residue_count {
+		residues.push(try!(read_residue(rdr, &codebooks)));
+		report!(Residues, i as usize + 1, vorbis_residue_count);
 	}
 
 	// 5. Read the mappings
 	let vorbis_mapping_count :u8 = try!(rdr.read_u6()) + 1;
 	let mut mappings = Vec::with_capacity(vorbis_mapping_count as usize);
-	for _ in 0 .. vorbis_mapping_count {
-		mappings.push(try!(read_mapping(& mut rdr,
+	for i in 0 .. vorbis_mapping_count {
+		mappings.push(try!(read_mapping(rdr,
 			audio_chan_ilog, audio_channels,
 			vorbis_floor_count, vorbis_residue_count)));
+		report!(Mappings, i as usize + 1, vorbis_mapping_count);
 	}
 
 	// 6. Read the modes
 	let vorbis_mode_count :u8 = try!(rdr.read_u6()) + 1;
 	let mut modes = Vec::with_capacity(vorbis_mode_count as usize);
-	for _ in 0 .. vorbis_mode_count {
-		modes.push(try!(read_mode_info(& mut rdr, vorbis_mapping_count)));
+	for i in 0 .. vorbis_mode_count {
+		modes.push(try!(read_mode_info(rdr, vorbis_mapping_count)));
+		report!(Modes, i as usize + 1, vorbis_mode_count);
 	}
 
 	// Now we only have to make sure the framing bit is set,
@@ -1144,11 +2442,324 @@ pub fn read_header_setup(packet :&[u8], audio_channels :u8, blocksizes :(u8, u8)
 		try!(Err(HeaderReadError::HeaderBadFormat));
 	}
 
-	return Ok(SetupHeader {
+	let setup = SetupHeader {
 		codebooks,
 		floors,
 		residues,
 		mappings,
 		modes,
+	};
+	// The mappings have been checked against the channel count
+	// while reading them already. Checking again makes sure that
+	// a mismatch can't surface as a decode error deep inside the
+	// audio decoding code if the checks above ever miss a case.
+	if !setup.channels_consistent(audio_channels) {
+		try!(Err(HeaderReadError::HeaderBadFormat));
+	}
+	return Ok(setup);
+}
+
+fn read_xiph_lacing(arr :&mut &[u8]) -> Option<usize> {
+	let mut r = 0;
+	loop {
+		let (&v, rest) = arr.split_first()?;
+		*arr = rest;
+		r += v as usize;
+		if v < 255 {
+			return Some(r);
+		}
+	}
+}
+
+/// Splits a Xiph laced extradata blob into the three headers
+///
+/// This is the layout Matroska and WebM use for the CodecPrivate
+/// element of Vorbis tracks: a byte with the number of headers minus
+/// one, which must be 2, the Xiph lacing encoded lengths of the first
+/// two headers, and then the three headers themselves. The setup
+/// header takes up the rest of the blob.
+///
+/// All lengths are checked against the size of the blob,
+/// so malformed blobs yield an error.
+pub fn split_xiph_extradata(extradata :&[u8]) ->
+		Result<[&[u8]; 3], HeaderReadError> {
+	let mut rest = match extradata.split_first() {
+		Some((&2, rest)) => rest,
+		_ => try!(Err(HeaderReadError::HeaderBadFormat)),
+	};
+	let lens = read_xiph_lacing(&mut rest)
+		.and_then(|l| read_xiph_lacing(&mut rest).map(|m| (l, m)));
+	let (ident_len, comment_len) = match lens {
+		Some(lens) => lens,
+		None => try!(Err(HeaderReadError::HeaderBadFormat)),
+	};
+	if rest.len() < ident_len || rest.len() - ident_len < comment_len {
+		try!(Err(HeaderReadError::HeaderBadFormat));
+	}
+	let (ident, rest) = rest.split_at(ident_len);
+	let (comment, setup) = rest.split_at(comment_len);
+	return Ok([ident, comment, setup]);
+}
+
+/// Reads the three headers from a Xiph laced extradata blob
+///
+/// See `split_xiph_extradata` for the layout. This allows to pass
+/// the CodecPrivate element of Matroska or WebM files directly.
+pub fn read_headers_from_xiph_extradata(extradata :&[u8]) ->
+		Result<HeaderSet, HeaderReadError> {
+	let hdrs = try!(split_xiph_extradata(extradata));
+	return read_headers_from_split(hdrs);
+}
+
+/// Splits an FFmpeg style extradata blob into the three headers
+///
+/// FFmpeg (and gstreamer's libav elements) hand out the Vorbis
+/// headers as one "extradata" blob in one of two layouts:
+///
+/// * Each header prefixed by its length as a 16 bit big endian integer.
+///   This is the layout FFmpeg itself produces, e.g. when
+///   demuxing from Ogg or when encoding with libvorbis.
+/// * The Xiph lacing layout also used by Matroska: a byte with
+///   value 2, the lacing encoded lengths of the first two headers,
+///   and then the three headers themselves.
+///
+/// Both are accepted, distinguished the same way as FFmpeg does it:
+/// the ident header is always 30 bytes long, so the 16 bit layout
+/// always starts with the bytes `0x00, 0x1e`.
+pub fn split_ffmpeg_extradata(extradata :&[u8]) ->
+		Result<[&[u8]; 3], HeaderReadError> {
+	let mut headers :[&[u8]; 3] = [&[]; 3];
+	let mut rest = extradata;
+	if rest.len() >= 2 && rest[0] == 0 && rest[1] == 30 {
+		for hdr in headers.iter_mut() {
+			if rest.len() < 2 {
+				try!(Err(HeaderReadError::HeaderBadFormat));
+			}
+			let len = ((rest[0] as usize) << 8) | rest[1] as usize;
+			rest = &rest[2..];
+			if rest.len() < len {
+				try!(Err(HeaderReadError::HeaderBadFormat));
+			}
+			*hdr = &rest[..len];
+			rest = &rest[len..];
+		}
+	} else if rest.first() == Some(&2) {
+		headers = try!(split_xiph_extradata(rest));
+	} else {
+		try!(Err(HeaderReadError::HeaderBadFormat));
+	}
+	return Ok(headers);
+}
+
+/// Reads the three headers from an FFmpeg style extradata blob
+///
+/// See `split_ffmpeg_extradata` for the accepted layouts.
+pub fn read_headers_from_ffmpeg_extradata(extradata :&[u8]) ->
+		Result<HeaderSet, HeaderReadError> {
+	let hdrs = try!(split_ffmpeg_extradata(extradata));
+	return read_headers_from_split(hdrs);
+}
+
+fn read_headers_from_split(hdrs :[&[u8]; 3]) -> Result<HeaderSet, HeaderReadError> {
+	let [ident_pck, comment_pck, setup_pck] = hdrs;
+	let ident = try!(read_header_ident(ident_pck));
+	let comment = try!(read_header_comment(comment_pck));
+	let setup = try!(read_header_setup(setup_pck, ident.audio_channels,
+		(ident.blocksize_0, ident.blocksize_1)));
+	return Ok((ident, comment, setup));
+}
+
+#[test]
+fn test_split_ffmpeg_extradata() {
+	// 16 bit big endian lengths
+	let mut blob = vec![0, 30];
+	blob.extend_from_slice(&[1; 30]);
+	blob.extend_from_slice(&[0, 3, 2, 2, 2]);
+	blob.extend_from_slice(&[1, 4]);
+	blob.extend_from_slice(&[5; 260]);
+	let hdrs = split_ffmpeg_extradata(&blob).unwrap();
+	assert_eq!(hdrs[0], &[1; 30][..]);
+	assert_eq!(hdrs[1], &[2; 3][..]);
+	assert_eq!(hdrs[2], &[5; 260][..]);
+	// Truncated
+	assert_eq!(split_ffmpeg_extradata(&blob[..100]),
+		Err(HeaderReadError::HeaderBadFormat));
+
+	// Xiph lacing
+	let mut blob = vec![2, 30, 255, 1];
+	blob.extend_from_slice(&[1; 30]);
+	blob.extend_from_slice(&[3; 256]);
+	blob.extend_from_slice(&[5; 7]);
+	let hdrs = split_ffmpeg_extradata(&blob).unwrap();
+	assert_eq!(hdrs[0], &[1; 30][..]);
+	assert_eq!(hdrs[1], &[3; 256][..]);
+	assert_eq!(hdrs[2], &[5; 7][..]);
+	assert_eq!(split_ffmpeg_extradata(&blob[..40]),
+		Err(HeaderReadError::HeaderBadFormat));
+
+	// Neither layout
+	assert_eq!(split_ffmpeg_extradata(&[1, 0x76]),
+		Err(HeaderReadError::HeaderBadFormat));
+	assert_eq!(split_ffmpeg_extradata(&[]),
+		Err(HeaderReadError::HeaderBadFormat));
+}
+
+#[test]
+fn test_split_xiph_extradata() {
+	let mut blob = vec![2, 30, 255, 1];
+	blob.extend_from_slice(&[1; 30]);
+	blob.extend_from_slice(&[3; 256]);
+	blob.extend_from_slice(&[5; 7]);
+	let hdrs = split_xiph_extradata(&blob).unwrap();
+	assert_eq!(hdrs[0], &[1; 30][..]);
+	assert_eq!(hdrs[1], &[3; 256][..]);
+	assert_eq!(hdrs[2], &[5; 7][..]);
+	// An empty setup header is up to the setup header parser to reject
+	assert_eq!(split_xiph_extradata(&blob[..290]).unwrap()[2], &[][..]);
+
+	// Malformed blobs
+	for blob in [&blob[..40], &blob[..2], &[2, 255][..], &[][..],
+			&[1, 30, 0][..], &[3, 30, 0, 0][..]].iter() {
+		assert_eq!(split_xiph_extradata(blob),
+			Err(HeaderReadError::HeaderBadFormat));
+	}
+	// Lengths larger than the blob
+	let mut blob = vec![2, 255, 255, 255, 10, 0];
+	blob.extend_from_slice(&[0; 100]);
+	assert_eq!(split_xiph_extradata(&blob),
+		Err(HeaderReadError::HeaderBadFormat));
+}
+
+#[test]
+fn test_declared_channels_consistent() {
+	let ident = test_ident_with_channels(2);
+	let setup_with_mapping = |mapping :Mapping| SetupHeader {
+		codebooks : Vec::new(),
+		floors : Vec::new(),
+		residues : Vec::new(),
+		mappings : vec![mapping],
+		modes : Vec::new(),
+	};
+	let mapping = Mapping {
+		mapping_magnitudes : vec![0],
+		mapping_angles : vec![1],
+		mapping_mux : vec![0, 1],
+		mapping_submap_floors : vec![0, 0],
+		mapping_submap_residues : vec![0, 0],
+	};
+	assert!(setup_with_mapping(mapping.clone()).declared_channels_consistent(&ident));
+
+	// The mux only covers one channel
+	let setup = setup_with_mapping(Mapping {
+		mapping_mux : vec![0],
+		.. mapping.clone()
+	});
+	assert!(!setup.declared_channels_consistent(&ident));
+	assert_eq!(format!("{:?}", setup), "SetupHeader { codebooks: 0, \
+		floors: 0, residues: 0, mappings: 1, modes: 0 }");
+
+	// The mux references a nonexistent submap
+	let setup = setup_with_mapping(Mapping {
+		mapping_mux : vec![0, 2],
+		.. mapping.clone()
+	});
+	assert!(!setup.declared_channels_consistent(&ident));
+
+	// Coupling references a nonexistent channel
+	let setup = setup_with_mapping(Mapping {
+		mapping_angles : vec![2],
+		.. mapping.clone()
+	});
+	assert!(!setup.declared_channels_consistent(&ident));
+}
+
+#[test]
+fn test_mapping_info() {
+	let mapping = Mapping {
+		mapping_magnitudes : vec![0],
+		mapping_angles : vec![1],
+		mapping_mux : vec![0, 1],
+		mapping_submap_floors : vec![0, 0],
+		mapping_submap_residues : vec![0, 0],
+	};
+	let setup = SetupHeader {
+		codebooks : Vec::new(),
+		floors : Vec::new(),
+		residues : Vec::new(),
+		modes : vec![ModeInfo { mode_blockflag : false, mode_mapping : 0 },
+			ModeInfo { mode_blockflag : true, mode_mapping : 1 }],
+		mappings : vec![mapping.clone(), Mapping {
+			mapping_magnitudes : Vec::new(),
+			mapping_angles : Vec::new(),
+			.. mapping
+		}],
+	};
+	assert_eq!(setup.mode_count(), 2);
+	let info = setup.mapping_info(0).unwrap();
+	assert_eq!(info.coupling_pairs().collect::<Vec<_>>(), [(0, 1)]);
+	assert_eq!(info.mux(), [0, 1]);
+	assert_eq!(info.submap_floor(0), Some(0));
+	assert_eq!(info.submap_residue(0), Some(0));
+	assert_eq!(setup.mapping_info(1).unwrap().coupling_pairs().count(), 0);
+	assert!(setup.mapping_info(2).is_none());
+}
+
+#[test]
+fn test_uses_coupling() {
+	// Stereo, with both channels in the same submap
+	let coupled = Mapping {
+		mapping_magnitudes : vec![0],
+		mapping_angles : vec![1],
+		mapping_mux : vec![0, 0],
+		mapping_submap_floors : vec![0],
+		mapping_submap_residues : vec![0],
+	};
+	let uncoupled = Mapping {
+		mapping_magnitudes : Vec::new(),
+		mapping_angles : Vec::new(),
+		.. coupled.clone()
+	};
+	let setup_with_mappings = |mappings :Vec<Mapping>| SetupHeader {
+		codebooks : Vec::new(),
+		floors : Vec::new(),
+		residues : Vec::new(),
+		mappings,
+		modes : Vec::new(),
+	};
+	let setup = setup_with_mappings(vec![uncoupled.clone()]);
+	assert!(!setup.uses_coupling());
+	assert_eq!(setup.coupling_step_count(), 0);
+
+	// Stereo, with each channel in its own submap
+	let setup = setup_with_mappings(vec![Mapping {
+		mapping_mux : vec![0, 1],
+		mapping_submap_floors : vec![0, 1],
+		mapping_submap_residues : vec![0, 1],
+		.. uncoupled.clone()
+	}]);
+	assert!(!setup.uses_coupling());
+	assert_eq!(setup.coupling_step_count(), 0);
+
+	// Only one of the mappings couples
+	let setup = setup_with_mappings(vec![uncoupled.clone(), coupled.clone()]);
+	assert!(setup.uses_coupling());
+	assert_eq!(setup.coupling_step_count(), 1);
+	let setup = setup_with_mappings(vec![coupled.clone(), coupled.clone()]);
+	assert_eq!(setup.coupling_step_count(), 2);
+}
+
+#[test]
+fn test_read_header_setup_with_position() {
+	// One codebook, with a broken sync pattern
+	let test_arr = &[0x05, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73,
+		0x00, 0x42, 0x43, 0x57, 0x01];
+	assert_eq!(read_header_setup(test_arr, 2, (8, 11)).err().unwrap(),
+		HeaderReadError::HeaderBadFormat);
+	let err = read_header_setup_with_position(test_arr, 2, (8, 11)).err().unwrap();
+	assert_eq!(err, WithPosition {
+		error : HeaderReadError::HeaderBadFormat,
+		byte_offset : 11,
+		bit_offset : 0,
 	});
+	assert_eq!(err.to_string(), "Invalid header (at byte 11, bit 0)");
 }
@@ -16,6 +16,8 @@ that's used later in the decode process.
 The caching is done to speed up decoding.
 */
 
+use std::sync::{Arc, Mutex, MutexGuard};
+
 #[derive(Clone)]
 pub struct TwiddleFactors {
 	pub a :Vec<f32>,
@@ -28,6 +30,9 @@ pub struct CachedBlocksizeDerived {
 	pub twiddle_factors : TwiddleFactors,
 	pub window_slope : Vec<f32>,
 	pub bitrev : Vec<u32>,
+	/// Cosine table of the f64 inverse MDCT,
+	/// empty unless `f64_intermediate` is enabled
+	pub mdct_cos_f64 : Vec<f64>,
 }
 
 impl CachedBlocksizeDerived {
@@ -36,10 +41,55 @@ impl CachedBlocksizeDerived {
 			window_slope : generate_window((1 << (bs as u16)) >> 1),
 			twiddle_factors : compute_twiddle_factors(bs),
 			bitrev : compute_bitreverse(bs),
+			mdct_cos_f64 : if cfg!(feature = "f64_intermediate") {
+				compute_mdct_cos_f64(bs)
+			} else {
+				Vec::new()
+			},
 		}
 	}
 }
 
+/// The `CachedBlocksizeDerived` of the halved block sizes
+///
+/// They are only needed by half rate decoding, so they
+/// are computed on first use, instead of with the header.
+#[derive(Default)]
+pub struct HalfBlocksizeDerived {
+	cached :Mutex<Option<Arc<[CachedBlocksizeDerived; 2]>>>,
+}
+
+impl Clone for HalfBlocksizeDerived {
+	fn clone(&self) -> Self {
+		HalfBlocksizeDerived {
+			cached : Mutex::new(self.lock().clone()),
+		}
+	}
+}
+
+impl HalfBlocksizeDerived {
+	fn lock(&self) -> MutexGuard<'_, Option<Arc<[CachedBlocksizeDerived; 2]>>> {
+		// The data can't be left in an inconsistent state
+		self.cached.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Returns the data for the halves of the given block sizes
+	///
+	/// The block sizes must be the same for all calls.
+	pub fn get(&self, blocksize_0 :u8, blocksize_1 :u8) -> Arc<[CachedBlocksizeDerived; 2]> {
+		self.lock().get_or_insert_with(|| Arc::new([
+			CachedBlocksizeDerived::from_blocksize(blocksize_0 - 1),
+			CachedBlocksizeDerived::from_blocksize(blocksize_1 - 1),
+		])).clone()
+	}
+
+	/// Returns whether the data has been computed already
+	#[cfg(all(test, feature = "ogg"))]
+	pub fn is_computed(&self) -> bool {
+		self.lock().is_some()
+	}
+}
+
 fn win_slope(x :u16, n :u16) -> f32 {
 	// please note that there might be a MISTAKE
 	// in how the spec specifies the right window slope
@@ -109,6 +159,17 @@ fn compute_bitreverse(blocksize :u8) -> Vec<u32> {
 	return rev;
 }
 
+/// Computes the cosines the f64 inverse MDCT uses
+///
+/// For block size n, the table holds cos(pi/4 * i / (n/2)) for i
+/// in 0 .. 4 * n, which is one period of the cosine.
+pub fn compute_mdct_cos_f64(blocksize :u8) -> Vec<f64> {
+	let n2 = (1 << blocksize) >> 1;
+	(0 .. 8 * n2)
+		.map(|i| (std::f64::consts::FRAC_PI_4 * (i as f64) / (n2 as f64)).cos())
+		.collect()
+}
+
 #[test]
 fn test_compute_bitreverse() {
 	let br = compute_bitreverse(8);
@@ -156,3 +217,19 @@ pub fn compute_bark_map_cos_omega(n :u16, floor0_rate :u16,
 	}
 	return res;
 }
+
+/// Precomputes the neighbors of the floor type 1 x list entries
+///
+/// Entry `i - 2` of the returned Vec contains the indices of
+/// `low_neighbor` and `high_neighbor` for index `i` of the list.
+/// The first two entries of the list have no neighbors.
+///
+/// The list must be free of duplicates, start with 0, and its
+/// second entry must be larger than all others, which is
+/// ensured by the setup header parsing code.
+pub fn compute_floor1_neighbors(floor1_x_list :&[u32]) -> Vec<(usize, usize)> {
+	(2 .. floor1_x_list.len())
+		.map(|i| (::audio::low_neighbor(floor1_x_list, i).0,
+			::audio::high_neighbor(floor1_x_list, i).0))
+		.collect()
+}
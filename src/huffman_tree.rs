@@ -123,6 +123,7 @@ impl HuffTree {
 	}
 }
 
+/// Error type for `VorbisHuffmanTree::load_from_array`
 #[derive(Debug)]
 pub enum HuffmanError {
 	Overspecified,
@@ -307,8 +308,23 @@ impl VorbisHuffmanTree {
 		});
 	}
 
+	/// Returns whether the tree is empty
+	///
+	/// This is the case for codebooks without any used entries.
+	pub fn is_empty(&self) -> bool {
+		self.desc_prog.len() < 2
+	}
+
+	/// Returns the internal representation of the tree
+	///
+	/// Trees built from the same codeword lengths
+	/// have the same representation.
+	pub(crate) fn desc_prog(&self) -> &[u32] {
+		&self.desc_prog
+	}
+
 	/// Returns an iterator over this tree.
-	pub fn iter<'l>(&'l self) -> VorbisHuffmanIter<'l> {
+	pub(crate) fn iter<'l>(&'l self) -> VorbisHuffmanIter<'l> {
 		return VorbisHuffmanIter { desc_prog :&self.desc_prog, pos :0 };
 	}
 
@@ -318,7 +334,7 @@ impl VorbisHuffmanTree {
 	/// tree element, or whether only an iterator that's progressed by
 	/// a given amount can be returned. Also, info is returned about how
 	/// far the reader can be advanced.
-	pub fn lookup_peeked_data<'l>(&'l self, bit_count :u8, peeked_data :u32)
+	pub(crate) fn lookup_peeked_data<'l>(&'l self, bit_count :u8, peeked_data :u32)
 			-> PeekedDataLookupResult<'l> {
 		if bit_count > 8 {
 			panic!("Bit count {} larger than allowed 8", bit_count);
@@ -356,9 +372,15 @@ impl<'a> VorbisHuffmanIter<'a> {
 	///
 	/// # Panics
 	///
-	/// Panics if the vorbis huffman treee is empty. It has to be found out
-	/// what to do if the huffman tree is empty, whether to reject the stream,
-	/// or whether to do sth else. Finding this out is a TODO.
+	/// Panics if the vorbis huffman tree is empty.
+	///
+	/// Empty trees are created for codebooks without any used entries.
+	/// We can't reject those when reading the setup header, as the
+	/// codebook might never be used. Instead, following how section 3.3
+	/// of the spec treats codebooks without VQ lookup, only packets
+	/// that attempt to read from them are considered undecodable.
+	/// For this, callers should check `VorbisHuffmanTree::is_empty`
+	/// first, like `BitpackCursor::read_huffman` does.
 	pub fn next(&mut self, bit :bool) -> Option<u32> {
 		// Assertion test for the paranoid and testing, comment out if you are:
 		/*let cur_entry = self.desc_prog[self.pos as usize];
@@ -417,7 +439,10 @@ fn test_huffman_tree() {
 fn test_issue_8() {
 	// regression test for issue 8
 	// make sure that it doesn't panic.
-	let _ = VorbisHuffmanTree::load_from_array(&[0; 625]);
+	let tree = VorbisHuffmanTree::load_from_array(&[0; 625]).unwrap();
+	assert!(tree.is_empty());
+	let tree = VorbisHuffmanTree::load_from_array(&[1]).unwrap();
+	assert!(!tree.is_empty());
 }
 
 #[test]
@@ -289,17 +289,34 @@ fn imdct_step3_inner_s_loop_ld654(n :usize, e :&mut [f32], i_off :usize,
 
 #[allow(dead_code)]
 pub fn inverse_mdct(cached_bd :&CachedBlocksizeDerived, buffer :&mut [f32], bs :u8) {
+	inverse_mdct_with_buf(cached_bd, buffer, bs, &mut Vec::new());
+}
+
+/// Like `inverse_mdct`, but uses the passed Vec as temporary
+/// storage, so that its allocation can be reused between calls
+pub fn inverse_mdct_with_buf(cached_bd :&CachedBlocksizeDerived, buffer :&mut [f32], bs :u8,
+		buf2 :&mut Vec<f32>) {
 	let n = buffer.len();
 	// Pre-condition.
 	assert_eq!(n, 1 << bs);
 
+	// The optimized step 3 below does its first two and its last three
+	// iterations separately, which only works for at least five
+	// iterations, meaning block sizes of 256 and up. Vorbis allows
+	// block sizes of 64 and 128 as well, and half rate decoding
+	// uses even smaller ones, so use the naive version for them.
+	if bs < 8 {
+		inverse_mdct_naive(cached_bd, buffer);
+		return;
+	}
+
 	let n2 = n >> 1;
 	let n4 = n >> 2;
 	let n8 = n >> 3;
 
-	// TODO later on we might want to do Vec::with_capacity here,
-	// and use buf2.push everywhere...
-	let mut buf2 :Vec<f32> = vec![0.0; n2];
+	buf2.clear();
+	buf2.resize(n2, 0.0);
+	let buf2 = &mut buf2[..];
 
 	let ctf = &cached_bd.twiddle_factors;
 	let a :&[f32] = &ctf.a;
@@ -658,7 +675,6 @@ pub fn inverse_mdct(cached_bd :&CachedBlocksizeDerived, buffer :&mut [f32], bs :
 	}
 }
 
-#[allow(dead_code)]
 pub fn inverse_mdct_naive(cached_bd :&CachedBlocksizeDerived, buffer :&mut[f32]) {
 	let n = buffer.len();
 	let n2 = n >> 1;
@@ -666,10 +682,10 @@ pub fn inverse_mdct_naive(cached_bd :&CachedBlocksizeDerived, buffer :&mut[f32])
 	let n8 = n >> 3;
 	let n3_4 = n - n4;
 
-	let mut u = [0.0; 1 << 13];
-	let mut xa = [0.0; 1 << 13];
-	let mut v = [0.0; 1 << 13];
-	let mut w = [0.0; 1 << 13];
+	let mut u = vec![0.0; n];
+	let mut xa = vec![0.0; n];
+	let mut v = vec![0.0; n];
+	let mut w = vec![0.0; n];
 
 	// retrieve the cached twiddle factors
 	let ctf = &cached_bd.twiddle_factors;
@@ -845,3 +861,29 @@ fn test_imdct() {
 			mismatches, mismatches_limit);
 	}
 }
+
+#[cfg(test)]
+#[test]
+fn test_imdct_sizes() {
+	// Compare with the definition of the inverse MDCT,
+	// for all block sizes that vorbis allows, as well as
+	// the ones half rate decoding uses
+	for bs in 5 .. 12 {
+		let n = 1 << bs;
+		let input = (0 .. n / 2)
+			.map(|i| ((i * 7 + 3) % 11) as f32 / 11.0 - 0.5)
+			.collect::<Vec<f32>>();
+		let mut arr = input.clone();
+		arr.resize(n, 0.0);
+		let cbd = CachedBlocksizeDerived::from_blocksize(bs);
+		inverse_mdct(&cbd, &mut arr, bs);
+		for (k, v) in arr.iter().enumerate() {
+			let expected = input.iter().enumerate()
+				.map(|(j, x)| *x as f64 * (::std::f64::consts::PI * 2.0 / n as f64
+					* (k as f64 + 0.5 + n as f64 / 4.0) * (j as f64 + 0.5)).cos())
+				.sum::<f64>();
+			assert!((expected - *v as f64).abs() < 1e-4,
+				"bs={} k={}: {} != {}", bs, k, v, expected);
+		}
+	}
+}
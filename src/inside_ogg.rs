@@ -13,14 +13,84 @@ This module provides higher level access to the library functionality,
 and useful helper methods for the Ogg `PacketReader` struct.
 */
 
-use ogg::{PacketReader, Packet};
-use std::io::{Read, Seek};
+use ogg::{PacketReader, Packet, OggReadError};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::cmp::{min, max, Ordering};
+use std::ops::Range;
+use std::marker::PhantomData;
+use std::iter::FusedIterator;
+use std::collections::VecDeque;
+use std::time::Duration;
 use header::*;
-use VorbisError;
-use audio::{PreviousWindowRight, read_audio_packet,
-	get_decoded_sample_count, read_audio_packet_generic};
+use {VorbisError, samples_to_duration};
+use audio::{AudioReadError, PreviousWindowRight, read_audio_packet,
+	get_decoded_sample_count, get_packet_blockflag, get_packet_mode, read_audio_packet_generic,
+	read_audio_packet_with_spectra, read_audio_packet_with_floor0_amps, BlockTransition, StageTimes};
 use header::HeaderSet;
-use samples::{Samples, InterleavedSamples};
+use samples::{Sample, Samples, InterleavedSamples, PcmByteFormat, interleave_fixed,
+	interleave_slices, Dither, DitherState, convert_to_i16_dithered};
+
+/// Reads the ident header, detecting streams of other codecs
+///
+/// If the packet is not a vorbis header, but the first
+/// packet of an Opus or FLAC stream, `VorbisError::WrongCodec`
+/// is returned instead of the generic header error.
+fn read_header_ident_checked(packet :&[u8]) -> Result<IdentHeader, VorbisError> {
+	match read_header_ident(packet) {
+		Ok(hdr) => Ok(hdr),
+		Err(e @ HeaderReadError::NotVorbisHeader) |
+		Err(e @ HeaderReadError::HeaderIsAudio) => {
+			if packet.starts_with(b"OpusHead") {
+				Err(VorbisError::WrongCodec("Opus"))
+			} else if packet.starts_with(b"\x7fFLAC") || packet.starts_with(b"fLaC") {
+				Err(VorbisError::WrongCodec("FLAC"))
+			} else {
+				Err(VorbisError::from(e))
+			}
+		},
+		Err(e) => Err(VorbisError::from(e)),
+	}
+}
+
+#[test]
+fn test_read_header_ident_checked() {
+	// Start of an OpusHead packet
+	let opus = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+	match read_header_ident_checked(opus) {
+		Err(VorbisError::WrongCodec("Opus")) => (),
+		_ => panic!("Expected Opus to be detected"),
+	}
+	// Start of the first packet of FLAC-in-Ogg
+	let flac_ogg = b"\x7fFLAC\x01\x00\x00\x01fLaC\x00\x00\x00\x22";
+	match read_header_ident_checked(flac_ogg) {
+		Err(VorbisError::WrongCodec("FLAC")) => (),
+		_ => panic!("Expected FLAC to be detected"),
+	}
+	// Native FLAC stream marker
+	match read_header_ident_checked(b"fLaC\x00\x00\x00\x22") {
+		Err(VorbisError::WrongCodec("FLAC")) => (),
+		_ => panic!("Expected FLAC to be detected"),
+	}
+	match read_header_ident_checked(b"\x01somethingelse") {
+		Err(VorbisError::BadHeader(HeaderReadError::NotVorbisHeader)) => (),
+		_ => panic!("Expected NotVorbisHeader error"),
+	}
+}
+
+/// Reads the ident header from an ogg stream as well as stream serial information
+///
+/// Exactly one packet is read from the stream,
+/// which is enough to find out e.g. the sample rate or channel count.
+///
+/// Note that as `PacketReader` requires it, the stream has
+/// to implement `Seek` as well, despite it not being used.
+pub fn read_ident_header_from<T: Read + Seek>(rdr: &mut PacketReader<T>) ->
+		Result<(IdentHeader, u32), VorbisError> {
+	let pck :Packet = try!(rdr.read_packet_expected());
+	let ident_hdr = try!(read_header_ident_checked(&pck.data));
+	return Ok((ident_hdr, pck.stream_serial()));
+}
 
 /// Reads the three vorbis headers from an ogg stream as well as stream serial information
 ///
@@ -29,27 +99,115 @@ use samples::{Samples, InterleavedSamples};
 /// and use the `HeadersReader` struct instead.
 pub fn read_headers<'a, T: Read + Seek + 'a>(rdr: &mut PacketReader<T>) ->
 		Result<(HeaderSet, u32), VorbisError> {
+	read_headers_with_config(rdr, StrictnessConfig::default())
+}
+
+/// Reads the three vorbis headers from an ogg stream, with the given strictness settings
+///
+/// Like `read_headers`, but deviations from the spec are
+/// only tolerated if the passed configuration allows it.
+pub fn read_headers_with_config<'a, T: Read + Seek + 'a>(rdr: &mut PacketReader<T>,
+		config :StrictnessConfig) -> Result<(HeaderSet, u32), VorbisError> {
 	let pck :Packet = try!(rdr.read_packet_expected());
-	let ident_hdr = try!(read_header_ident(&pck.data));
+	return read_headers_after_ident(rdr, pck, config);
+}
+
+/// Reads the comment and setup headers from an ogg stream,
+/// after the packet with the ident header has been read already
+fn read_headers_after_ident<T: Read + Seek>(rdr: &mut PacketReader<T>, pck :Packet,
+		config :StrictnessConfig) -> Result<(HeaderSet, u32), VorbisError> {
+	let ident_hdr = try!(read_header_ident_checked(&pck.data));
 	let stream_serial = pck.stream_serial();
 
 	let mut pck :Packet = try!(rdr.read_packet_expected());
 	while pck.stream_serial() != stream_serial {
 		pck = try!(rdr.read_packet_expected());
 	}
-	let comment_hdr = try!(read_header_comment(&pck.data));
+	let comment_hdr = try!(read_header_comment_with_config(&pck.data, config));
 
+	// Setup headers with large codebooks can span multiple pages.
+	// The ogg reader reassembles such packets, so we always
+	// receive the full setup header here.
 	let mut pck :Packet = try!(rdr.read_packet_expected());
 	while pck.stream_serial() != stream_serial {
 		pck = try!(rdr.read_packet_expected());
 	}
 	let setup_hdr = try!(read_header_setup(&pck.data, ident_hdr.audio_channels,
 		(ident_hdr.blocksize_0, ident_hdr.blocksize_1)));
+	try!(check_setup_ends_page(&pck, config));
 
 	rdr.delete_unread_packets();
 	return Ok(((ident_hdr, comment_hdr, setup_hdr), pck.stream_serial()));
 }
 
+/// If configured to, errors if the setup header packet doesn't end its page
+///
+/// The spec requires that the first audio packet begins a fresh page.
+/// However, like libvorbis, we ignore any packets that
+/// follow the setup header on the same page by default.
+fn check_setup_ends_page(pck :&Packet, config :StrictnessConfig) -> Result<(), VorbisError> {
+	if !config.tolerate_packets_after_setup && !pck.last_in_page() {
+		try!(Err(HeaderReadError::HeaderBadFormat));
+	}
+	return Ok(());
+}
+
+/// A decoded packet together with its frequency domain data
+///
+/// Obtained via `OggStreamReader::read_dec_packet_full`.
+pub struct DecodedPacket {
+	/// The decoded samples, one `Vec` per channel
+	pub samples :Vec<Vec<f32>>,
+	/// The spectra of the packet prior to the inverse MDCT
+	///
+	/// There is one `Vec` per channel, each containing `n/2` bins,
+	/// `n` being the block size of the packet. Please see the
+	/// `audio::read_audio_packet_with_spectra` function for details.
+	pub spectra :Vec<Vec<f32>>,
+}
+
+/// Running counters of the decoding done by an `OggStreamReader`
+///
+/// Obtained via `OggStreamReader::stats`. The counters are
+/// accumulated since the reader has been created, over all links
+/// of chained files and over seeks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+	/// Number of audio packets that have been decoded and returned
+	pub packets :u64,
+	/// Number of samples (per channel) that have been returned
+	pub samples :u64,
+	/// Total size of the decoded packets in compressed form, in bytes
+	pub compressed_bytes :u64,
+	/// Number of decoding errors that the reader has recovered from
+	pub recovered_errors :u64,
+}
+
+/// A decoded packet together with information about its compressed form
+///
+/// Obtained via `OggStreamReader::read_dec_packet_detailed`.
+pub struct DetailedPacket {
+	/// The decoded samples, one `Vec` per channel
+	pub samples :Vec<Vec<f32>>,
+	/// The length of the packet in compressed form, in bytes
+	pub compressed_len :usize,
+	/// The offset of the first sample of the packet
+	///
+	/// This is the number of samples (per channel) that the reader
+	/// has returned before this packet, counted from the start of the
+	/// current logical stream, or from the last seek.
+	pub start_sample :u64,
+	/// The number of the mode the packet uses
+	pub mode :u8,
+	/// Whether the packet uses the long block size
+	pub long_block :bool,
+}
+
+/// A decoded packet together with the amplitudes of its floor0 floors
+///
+/// Obtained via `OggStreamReader::read_dec_packet_with_floor0_amp`.
+pub type PacketWithFloor0Amps = (Vec<Vec<f32>>, Vec<Option<u64>>);
+
 /**
 Reading ogg/vorbis files or streams
 
@@ -62,6 +220,11 @@ in the case of ogv, is not supported.
 
 If you need support for this, you need to use the lower level methods
 instead.
+
+Chained files are decoded in their entirety, the decoding functions
+continue with the next logical stream once a stream ends. For
+handling each stream separately, you can obtain an `OggStreamReader`
+for the next stream via the `into_next_stream` function, if any.
 */
 pub struct OggStreamReader<T: Read + Seek> {
 	rdr :PacketReader<T>,
@@ -74,6 +237,88 @@ pub struct OggStreamReader<T: Read + Seek> {
 	pub setup_hdr :SetupHeader,
 
 	cur_absgp :Option<u64>,
+	/// Absgp of the page of the last read audio packet,
+	/// if it was the last packet of that page
+	#[cfg(debug_assertions)]
+	last_page_absgp :Option<u64>,
+	/// Number of samples to drop from the start of the next decoded packets
+	skip_count :u64,
+	/// Number of samples the last decoded packet got truncated by
+	last_truncation :usize,
+	/// Number of samples dropped from the start of the last decoded packet
+	last_trimmed_start :usize,
+	/// Whether the last audio packet was zero bytes long
+	last_packet_empty :bool,
+	/// Total number of samples dropped from the start
+	total_trimmed_start :u64,
+	/// Total number of samples truncated from the end
+	total_trimmed_end :u64,
+	pad_final_packet :bool,
+	/// Peak threshold below which leading packets are dropped
+	silence_threshold :Option<f32>,
+	/// Whether to skip header packets, set after seeking
+	/// as the seek might land on the header pages
+	skip_header_packets :bool,
+
+	strictness :StrictnessConfig,
+
+	/// Whether the last packet of the logical stream has been read
+	stream_ended :bool,
+	/// Number of samples returned since the start of the
+	/// logical stream or the last seek
+	returned_samples :u64,
+	/// Blockflag of the last decoded packet
+	prev_blockflag :Option<bool>,
+	/// Whether `read_dec_packet_lossy` reset the decoder after an
+	/// undecodable packet, and it hasn't been warmed up again yet
+	lossy_reset :bool,
+	last_block_transition :Option<BlockTransition>,
+
+	/// Cached results of the scan through the entire stream
+	stream_scan :Option<StreamScan>,
+	/// Byte position from which on the first stream with
+	/// our serial is the current link, as concatenated
+	/// streams may reuse the serial of earlier ones
+	link_start :u64,
+	/// Byte position of the end of the current logical stream,
+	/// once its last packet has been read
+	link_end :Option<u64>,
+	/// Whether the last read packet of the stream didn't end its page
+	mid_page :bool,
+	/// Packets read ahead by `save_position`, to be returned
+	/// before any further packets from the ogg reader
+	pending_packets :VecDeque<Packet>,
+
+	stats :DecodeStats,
+	/// State of the dither noise, kept across packets
+	dither_state :DitherState,
+}
+
+// Manual impl as the reader doesn't need to implement Debug
+impl<T: Read + Seek> fmt::Debug for OggStreamReader<T> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		fmt.debug_struct("OggStreamReader")
+			.field("stream_serial", &self.stream_serial)
+			.field("ident_hdr", &self.ident_hdr)
+			.field("comment_hdr", &self.comment_hdr)
+			.field("setup_hdr", &self.setup_hdr)
+			.field("cur_absgp", &self.cur_absgp)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Information obtained by scanning through the entire stream
+#[derive(Clone, Copy)]
+struct StreamScan {
+	packet_count :u64,
+	/// Byte range of the logical stream inside the file
+	byte_range :(u64, u64),
+	/// Absgp of the last page of the logical stream
+	end_absgp :u64,
+	/// Absgp of the first sample of the logical stream
+	start_absgp :u64,
+	/// Byte position of the end of the page with the setup header
+	audio_start :u64,
 }
 
 impl<T: Read + Seek> OggStreamReader<T> {
@@ -94,11 +339,65 @@ impl<T: Read + Seek> OggStreamReader<T> {
 	/// Please note that this function doesn't work well with async
 	/// I/O. In order to support this use case, enable the `async_ogg` feature,
 	/// and use the `HeadersReader` struct instead.
-	pub fn from_ogg_reader(mut rdr :PacketReader<T>) ->
+	pub fn from_ogg_reader(rdr :PacketReader<T>) ->
+			Result<Self, VorbisError> {
+		OggStreamReader::from_ogg_reader_with_config(rdr, StrictnessConfig::default())
+	}
+	/// Constructs a new OggStreamReader with the given strictness settings
+	///
+	/// Like `new`, but deviations from the spec are only
+	/// tolerated if the passed configuration allows it.
+	/// The configuration also applies to the headers
+	/// of any chained streams that follow.
+	pub fn new_with_config(rdr :T, config :StrictnessConfig) ->
 			Result<Self, VorbisError> {
-		let ((ident_hdr, comment_hdr, setup_hdr), stream_serial) =
-			try!(read_headers(&mut rdr));
-		return Ok(OggStreamReader {
+		OggStreamReader::from_ogg_reader_with_config(PacketReader::new(rdr), config)
+	}
+	/// Constructs a new OggStreamReader from a given Ogg PacketReader,
+	/// with the given strictness settings.
+	pub fn from_ogg_reader_with_config(mut rdr :PacketReader<T>,
+			config :StrictnessConfig) -> Result<Self, VorbisError> {
+		// The first page of the stream only contains the ident header,
+		// so asking for the position doesn't lose any of its packets.
+		let link_start = try!(rdr.seek_bytes(SeekFrom::Current(0))
+			.map_err(OggReadError::ReadError));
+		let (hdrs, stream_serial) = try!(read_headers_with_config(&mut rdr, config));
+		let mut rdr = OggStreamReader::from_headers_with_config(rdr, hdrs, stream_serial, config);
+		rdr.link_start = link_start;
+		return Ok(rdr);
+	}
+	/// Constructs a new OggStreamReader from already read headers
+	///
+	/// This avoids reading the headers a second time if they have
+	/// been obtained already, e.g. via the async `HeadersReader`, or
+	/// from a container. The passed reader should be positioned after
+	/// the pages with the headers of the stream with the given serial.
+	/// If it is positioned before them, the header packets get skipped.
+	///
+	/// Like with `new`, the first audio packet only
+	/// warms up the decoder and yields no samples.
+	///
+	/// Returns `HeaderReadError::HeaderBadFormat` if the setup
+	/// header is inconsistent with the ident header.
+	pub fn from_headers(mut rdr :PacketReader<T>, headers :HeaderSet,
+			stream_serial :u32) -> Result<Self, VorbisError> {
+		if !headers.2.declared_channels_consistent(&headers.0) {
+			try!(Err(HeaderReadError::HeaderBadFormat));
+		}
+		// The reader might not have seen the first page of the stream,
+		// so make the ogg layer accept starting in the middle of it.
+		// The page of the setup header mustn't contain audio packets,
+		// so no unread packets are lost by this.
+		try!(rdr.seek_bytes(SeekFrom::Current(0)).map_err(OggReadError::ReadError));
+		let mut rdr = OggStreamReader::from_headers_with_config(rdr, headers,
+			stream_serial, StrictnessConfig::default());
+		rdr.skip_header_packets = true;
+		return Ok(rdr);
+	}
+	fn from_headers_with_config(rdr :PacketReader<T>, hdrs :HeaderSet, stream_serial :u32,
+			config :StrictnessConfig) -> Self {
+		let (ident_hdr, comment_hdr, setup_hdr) = hdrs;
+		return OggStreamReader {
 			rdr,
 			pwr : PreviousWindowRight::new(),
 			ident_hdr,
@@ -106,29 +405,122 @@ impl<T: Read + Seek> OggStreamReader<T> {
 			setup_hdr,
 			stream_serial,
 			cur_absgp : None,
-		});
+			#[cfg(debug_assertions)]
+			last_page_absgp : None,
+			skip_count : 0,
+			last_truncation : 0,
+			last_trimmed_start : 0,
+			last_packet_empty : false,
+			total_trimmed_start : 0,
+			total_trimmed_end : 0,
+			pad_final_packet : false,
+			silence_threshold : None,
+			skip_header_packets : false,
+			strictness : config,
+			stream_ended : false,
+			returned_samples : 0,
+			prev_blockflag : None,
+			lossy_reset : false,
+			last_block_transition : None,
+			stream_scan : None,
+			link_start : 0,
+			link_end : None,
+			mid_page : false,
+			pending_packets : VecDeque::new(),
+			stats : DecodeStats::default(),
+			dither_state : DitherState::new(),
+		};
 	}
 	pub fn into_inner(self) -> PacketReader<T> {
 		self.rdr
 	}
-	fn read_next_audio_packet(&mut self) -> Result<Option<Packet>, VorbisError> {
+	/// Returns a reader over the decoded samples, as bytes
+	///
+	/// See `PcmReader` for details.
+	pub fn into_pcm_reader(self) -> PcmReader<T> {
+		PcmReader {
+			rdr : self,
+			buffer : Vec::new(),
+			pos : 0,
+		}
+	}
+	/// Returns a reader for the logical stream that follows the current one
+	///
+	/// The remaining packets of the current logical stream are
+	/// skipped, and the headers of the next stream are read.
+	/// Returns `None` if there is no further stream.
+	///
+	/// Note that the decoding functions already continue with the next
+	/// stream of chained files on their own. This function allows
+	/// to handle each stream separately, e.g. to skip a stream, or to
+	/// reset settings like `pad_final_packet` for each of them.
+	/// The new reader starts out with the default settings,
+	/// apart from the strictness configuration, which is kept.
+	pub fn into_next_stream(mut self) -> Result<Option<OggStreamReader<T>>, VorbisError> {
 		loop {
 			let pck = match try!(self.rdr.read_packet()) {
 				Some(p) => p,
 				None => return Ok(None),
 			};
-			if pck.stream_serial() != self.stream_serial {
+			if pck.stream_serial() != self.stream_serial || self.stream_ended {
+				if pck.first_in_stream() {
+					let (hdrs, stream_serial) = try!(read_headers_after_ident(&mut self.rdr,
+						pck, self.strictness));
+					return Ok(Some(OggStreamReader::from_headers_with_config(self.rdr, hdrs,
+						stream_serial, self.strictness)));
+				}
+			} else if pck.last_in_stream() {
+				self.stream_ended = true;
+				// Make the ogg reader forget about the stream,
+				// in case the next one has the same serial.
+				// Please see read_next_audio_packet for details.
+				try!(self.rdr.seek_bytes(SeekFrom::Current(0))
+					.map_err(OggReadError::ReadError));
+			}
+		}
+	}
+	/// Returns a mutable reference to the underlying ogg reader
+	///
+	/// This allows inspecting the raw packets of the stream,
+	/// e.g. those of other logical streams multiplexed with the
+	/// vorbis stream, without giving up the `OggStreamReader`.
+	///
+	/// Beware: reading packets of the vorbis stream via this reference,
+	/// or seeking, desyncs the internal state of the reader, like the
+	/// absgp returned by `get_last_absgp`. Further decoding may then fail
+	/// or yield wrong samples. Use `set_position` or `seek_absgp_pg`
+	/// to bring the state back in sync.
+	pub fn as_packet_reader_mut(&mut self) -> &mut PacketReader<T> {
+		&mut self.rdr
+	}
+	fn read_next_audio_packet(&mut self) -> Result<Option<Packet>, VorbisError> {
+		loop {
+			let pck = match self.pending_packets.pop_front() {
+				Some(p) => p,
+				None => match try!(self.rdr.read_packet()) {
+					Some(p) => p,
+					None => return Ok(None),
+				},
+			};
+			let new_stream = pck.stream_serial() != self.stream_serial || self.stream_ended;
+			if !new_stream {
+				self.mid_page = !pck.last_in_page();
+			}
+			if new_stream {
 				if pck.first_in_stream() {
-					// We have a chained ogg file. This means we need to
+					// We have a chained ogg file, or multiple ogg files
+					// concatenated to each other. This means we need to
 					// re-initialize the internal context.
-					let ident_hdr = try!(read_header_ident(&pck.data));
+					let ident_hdr = try!(read_header_ident_checked(&pck.data));
 
 					let pck :Packet = try!(self.rdr.read_packet_expected());
-					let comment_hdr = try!(read_header_comment(&pck.data));
+					let comment_hdr = try!(read_header_comment_with_config(&pck.data,
+						self.strictness));
 
 					let pck :Packet = try!(self.rdr.read_packet_expected());
 					let setup_hdr = try!(read_header_setup(&pck.data, ident_hdr.audio_channels,
 						(ident_hdr.blocksize_0, ident_hdr.blocksize_1)));
+					try!(check_setup_ends_page(&pck, self.strictness));
 
 					// Update the context
 					self.pwr = PreviousWindowRight::new();
@@ -137,6 +529,17 @@ impl<T: Read + Seek> OggStreamReader<T> {
 					self.setup_hdr = setup_hdr;
 					self.stream_serial = pck.stream_serial();
 					self.cur_absgp = None;
+					self.skip_count = 0;
+					self.stream_ended = false;
+					self.returned_samples = 0;
+					self.stream_scan = None;
+					if let Some(end) = self.link_end.take() {
+						self.link_start = end;
+					}
+					#[cfg(debug_assertions)]
+					{
+						self.last_page_absgp = None;
+					}
 
 					// Now, read the first audio packet to prime the pwr
 					// and discard the packet.
@@ -146,13 +549,46 @@ impl<T: Read + Seek> OggStreamReader<T> {
 					};
 					let _decoded_pck = try!(read_audio_packet(&self.ident_hdr,
 						&self.setup_hdr, &pck.data, &mut self.pwr));
-					self.cur_absgp = Some(pck.absgp_page());
+					// The absgp of the page only tells the position
+					// after the packet if it is the last one of the page.
+					if pck.last_in_page() {
+						self.cur_absgp = Some(pck.absgp_page());
+					}
+					self.prev_blockflag = get_packet_blockflag(&self.setup_hdr, &pck.data).ok();
+					self.lossy_reset = false;
+					self.last_block_transition = None;
 
-					return Ok(try!(self.rdr.read_packet()));
+					let pck = try!(self.rdr.read_packet());
+					self.mid_page = pck.as_ref().map_or(false, |p| !p.last_in_page());
+					return Ok(pck);
 				} else {
 					// Ignore every packet that has a mismatching stream serial
 				}
+			} else if self.skip_header_packets && pck.data.first().map_or(false, |b| b & 1 == 1) {
+				// Header packets have the first bit set,
+				// audio packets have it unset.
 			} else {
+				self.skip_header_packets = false;
+				if pck.last_in_stream() {
+					self.stream_ended = true;
+					// When ogg files get concatenated, the stream that follows
+					// may have the same serial as this one. The ogg reader
+					// would reject its first page, as it still knows about
+					// this stream, so make it forget about it. The last page
+					// of the stream has been read entirely, so no packets
+					// of it are lost.
+					let end = try!(self.rdr.seek_bytes(SeekFrom::Current(0))
+						.map_err(OggReadError::ReadError));
+					self.link_end = Some(end);
+				}
+				#[cfg(debug_assertions)]
+				{
+					self.last_page_absgp = if pck.last_in_page() {
+						Some(pck.absgp_page())
+					} else {
+						None
+					};
+				}
 				return Ok(Some(pck));
 			}
 		}
@@ -189,128 +625,2084 @@ impl<T: Read + Seek> OggStreamReader<T> {
 		return Ok(Some(decoded_pck.samples));
 	}
 
-	/// Reads and decompresses an audio packet from the stream (generic).
+	/// Reads and decompresses an audio packet from the stream (interleaved),
+	/// dithering the samples when converting them to `i16`.
 	///
-	/// On read errors, it returns Err(e) with the error.
+	/// Like `read_dec_packet_itl`, but with the given dither applied.
+	/// The state of the dither noise is kept by the reader, so it
+	/// continues over packet boundaries. `read_dec_packet_itl` stays
+	/// undithered, so that its output can be compared to libvorbis.
+	pub fn read_dec_packet_itl_dithered(&mut self, dither :Dither) ->
+			Result<Option<Vec<i16>>, VorbisError> {
+		let decoded_pck :InterleavedSamples<f32> = match try!(self.read_dec_packet_generic()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		let mut out = Vec::new();
+		convert_to_i16_dithered(&decoded_pck.samples, &mut out,
+			dither, &mut self.dither_state);
+		return Ok(Some(out));
+	}
+
+	/// Reads and decompresses an audio packet from the stream
+	/// with a channel count known at compile time (interleaved).
 	///
-	/// On success, it either returns None, when the end of the
-	/// stream has been reached, or Some(packet_data),
-	/// with the data of the decompressed packet.
-	pub fn read_dec_packet_generic<S :Samples>(&mut self) ->
-			Result<Option<S>, VorbisError> {
-		let pck = match try!(self.read_next_audio_packet()) {
+	/// Like `read_dec_packet_itl`, but the channel count `CH` is a
+	/// constant, which allows the compiler to optimize the
+	/// interleaving for it, e.g. for stereo.
+	///
+	/// Returns `VorbisError::ChannelCountMismatch` if `CH` doesn't
+	/// match the channel count of the stream.
+	/// In that case, no packet is read.
+	pub fn read_dec_packet_itl_const<const CH :usize>(&mut self) ->
+			Result<Option<Vec<i16>>, VorbisError> {
+		let channels = self.ident_hdr.audio_channels;
+		if CH != channels as usize {
+			try!(Err(VorbisError::ChannelCountMismatch(channels)));
+		}
+		let decoded_pck :Vec<Vec<f32>> = match try!(self.read_dec_packet_generic()) {
 			Some(p) => p,
 			None => return Ok(None),
 		};
-		self.dec_packet_generic(pck).map(Option::Some)
+		// In chained files, the channel count can
+		// change with the packet we just read.
+		if CH != decoded_pck.len() {
+			try!(Err(VorbisError::ChannelCountMismatch(decoded_pck.len() as u8)));
+		}
+		let mut channels :[&[f32]; CH] = [&[]; CH];
+		for (slot, ch) in channels.iter_mut().zip(decoded_pck.iter()) {
+			*slot = ch;
+		}
+		let mut samples = Vec::new();
+		interleave_fixed(&channels, &mut samples);
+		return Ok(Some(samples));
 	}
 
-	#[inline]
-	pub fn dec_packet_generic<S :Samples>(&mut self, pck :Packet) ->
-			Result<S, VorbisError> {
-		let mut decoded_pck :S = try!(read_audio_packet_generic(&self.ident_hdr,
-			&self.setup_hdr, &pck.data, &mut self.pwr));
+	/// Reads and decompresses an audio packet from the stream,
+	/// returning only the samples of the given channel.
+	///
+	/// All channels are still decoded, as the channels can depend
+	/// on each other via channel coupling, but only the samples of
+	/// channel `ch` are returned. No mixing is done, so this can be used
+	/// to obtain mono output from files with identical channels,
+	/// without the coloring that downmixing might introduce.
+	///
+	/// Returns `VorbisError::ChannelCountMismatch` if `ch` is not
+	/// smaller than the channel count of the stream.
+	/// In that case, no packet is read.
+	pub fn read_dec_packet_pick_channel(&mut self, ch :usize) ->
+			Result<Option<Vec<i16>>, VorbisError> {
+		let channels = self.ident_hdr.audio_channels;
+		if ch >= channels as usize {
+			try!(Err(VorbisError::ChannelCountMismatch(channels)));
+		}
+		let decoded_pck :Vec<Vec<i16>> = match try!(self.read_dec_packet_generic()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		// In chained files, the channel count can
+		// change with the packet we just read.
+		let channels = decoded_pck.len();
+		match decoded_pck.into_iter().nth(ch) {
+			Some(samples) => return Ok(Some(samples)),
+			None => try!(Err(VorbisError::ChannelCountMismatch(channels as u8))),
+		}
+	}
 
-		// If this is the last packet in the logical bitstream,
-		// we need to truncate it so that its ending matches
-		// the absgp of the current page.
-		// This is what the spec mandates and also the behaviour
-		// of libvorbis.
-		if let (Some(absgp), true) = (self.cur_absgp, pck.last_in_stream()) {
-			let target_length = pck.absgp_page().saturating_sub(absgp) as usize;
-			decoded_pck.truncate(target_length);
+	/// Reads and decompresses an audio packet from the stream,
+	/// applying the given gain to each channel (interleaved).
+	///
+	/// Like `read_dec_packet_itl`, but the samples of each channel
+	/// are multiplied by the respective entry of `gains` prior to
+	/// the conversion to `i16`. Samples exceeding the range of `i16`
+	/// after the multiplication are clamped.
+	///
+	/// Returns `VorbisError::ChannelCountMismatch` if the number of
+	/// gains doesn't match the channel count of the stream.
+	/// In that case, no packet is read.
+	pub fn read_dec_packet_itl_with_gains(&mut self, gains :&[f32]) ->
+			Result<Option<Vec<i16>>, VorbisError> {
+		let channels = self.ident_hdr.audio_channels;
+		if gains.len() != channels as usize {
+			try!(Err(VorbisError::ChannelCountMismatch(channels)));
 		}
-		if pck.last_in_page() {
-			self.cur_absgp = Some(pck.absgp_page());
-		} else if let &mut Some(ref mut absgp) = &mut self.cur_absgp {
-			*absgp += decoded_pck.num_samples() as u64;
+		let mut decoded_pck :Vec<Vec<f32>> = match try!(self.read_dec_packet_generic()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		// In chained files, the channel count can
+		// change with the packet we just read.
+		if gains.len() != decoded_pck.len() {
+			try!(Err(VorbisError::ChannelCountMismatch(decoded_pck.len() as u8)));
+		}
+		for (ch, gain) in decoded_pck.iter_mut().zip(gains.iter()) {
+			for s in ch.iter_mut() {
+				*s *= *gain;
+			}
 		}
-		return Ok(decoded_pck);
+		let decoded_pck :InterleavedSamples<i16> = Samples::from_floats(decoded_pck);
+		return Ok(Some(decoded_pck.samples));
 	}
-	/// Skips the given number of samples
+
+	/// Decodes the remaining packets of the stream, passing
+	/// the interleaved samples of each to the given closure.
 	///
-	/// Skips multiple packets without decoding any but the last two, so that
-	/// the leftover number of samples to skip is lower than the length of the
-	/// returned packet.
+	/// All packets are interleaved into the same buffer, so its
+	/// allocation is reused, and only needs to grow until it has the
+	/// size of the largest packet. Packets without any samples,
+	/// like the first packet of a stream, are not passed to `f`.
 	///
-	/// The function runs a linear skip algorithm which means that instead of
-	/// logarithmic *seeking*, it inspects each packet for its length, and
-	/// subtracts the length from the length to skip. This function does no
-	/// packet decoding until it arrives at the destination, which makes it
-	/// way cheaper than just decoding all packets.
+	/// On errors, decoding stops and the error is returned.
+	/// Chained files are decoded in their entirety, so the channel
+	/// count in `ident_hdr` might change between calls of `f`.
+	pub fn for_each_packet_itl<F :FnMut(&[i16])>(&mut self, mut f :F) ->
+			Result<(), VorbisError> {
+		let mut buffer = Vec::new();
+		while let Some(decoded_pck) = try!(self.read_dec_packet_generic::<Vec<Vec<f32>>>()) {
+			interleave_slices(&decoded_pck, &mut buffer);
+			if !buffer.is_empty() {
+				f(&buffer);
+			}
+		}
+		return Ok(());
+	}
+
+	/// Decodes the entire stream, returning the interleaved samples
+	/// together with the sample rate and the channel count.
 	///
-	/// The absolute granule position is always increased in whole-package
-	/// increments.
-	pub fn skip_samples_linear<S :Samples>(&mut self, to_skip :usize) -> Result<(Option<S>, usize), VorbisError> {
-		let mut to_skip = to_skip;
-		let mut last_pck :Option<Packet> = None;
-		let mut next_pck;
+	/// This is meant for short files like sound effects, where
+	/// all samples are needed at once. The stream is scanned first
+	/// to determine its length, so that the output can be allocated
+	/// in one go. Decoding starts at the position of the reader,
+	/// so after a seek, only the remaining samples are returned.
+	///
+	/// Chained files are decoded in their entirety. The sample rate
+	/// and the channel count are those of the first stream. If the
+	/// channel count changes, `VorbisError::ChannelCountMismatch`
+	/// is returned with the channel count of the first stream.
+	pub fn decode_all_itl(&mut self) -> Result<(Vec<i16>, u32, u8), VorbisError> {
+		self.decode_all()
+	}
 
-		loop {
-			if let Some(p) = try!(self.read_next_audio_packet()) {
-				next_pck = p;
-			} else {
-				return Ok((None, to_skip));
-			}
-			let mut sample_cnt = try!(get_decoded_sample_count(&self.ident_hdr, &self.setup_hdr, &next_pck.data));
-			// If this is the last packet in the logical bitstream,
-			// we need to truncate it so that its ending matches
-			// the absgp of the current page.
-			// This is what the spec mandates and also the behaviour
-			// of libvorbis.
-			if let (Some(absgp), true) = (self.cur_absgp, next_pck.last_in_stream()) {
-				last_pck = None;
-				let target_length = next_pck.absgp_page().saturating_sub(absgp) as usize;
-				sample_cnt = sample_cnt.min(target_length);
+	/// Decodes the entire stream, returning the interleaved samples
+	/// together with the sample rate and the channel count (generic).
+	///
+	/// Like `decode_all_itl`, but for any sample type.
+	pub fn decode_all<S :Sample>(&mut self) -> Result<(Vec<S>, u32, u8), VorbisError> {
+		let sample_rate = self.ident_hdr.audio_sample_rate;
+		let channels = self.ident_hdr.audio_channels;
+		// Each packet yields at most half a long block, which limits
+		// the allocation if the absgp of the last page is bogus
+		let mut remaining = min(try!(self.total_samples()),
+			try!(self.packet_count()) * self.info().max_block_samples as u64);
+		if let Some(absgp) = self.cur_absgp {
+			remaining = min(remaining, try!(self.end_absgp()).saturating_sub(absgp));
+		}
+		let mut samples = Vec::with_capacity(remaining as usize * channels as usize);
+		while let Some(mut decoded_pck) = try!(self.read_dec_packet_generic::<InterleavedSamples<S>>()) {
+			if self.ident_hdr.audio_channels != channels {
+				try!(Err(VorbisError::ChannelCountMismatch(channels)));
 			}
-			if to_skip < sample_cnt {
-				// We reached the end of our search.
-				if let Some(last_pck) = last_pck {
-					self.pwr = PreviousWindowRight::new();
-					let _decoded_pck :S = try!(read_audio_packet_generic(&self.ident_hdr,
-						&self.setup_hdr, &last_pck.data, &mut self.pwr));
-				}
-				let decoded_pck = try!(self.dec_packet_generic(next_pck));
-				return Ok((Some(decoded_pck), to_skip));
-			} else {
-				to_skip -= sample_cnt;
+			samples.append(&mut decoded_pck.samples);
+		}
+		return Ok((samples, sample_rate, channels));
+	}
+
+	/// Reads and decompresses an audio packet from the stream,
+	/// returning the interleaved samples serialized as bytes.
+	///
+	/// On read errors, it returns Err(e) with the error.
+	///
+	/// On success, it either returns None, when the end of the
+	/// stream has been reached, or Some(packet_data),
+	/// with the samples of the decompressed packet in
+	/// the given format, ready to be sent over the network.
+	/// The conversion and serialization happen in a single pass.
+	pub fn read_dec_packet_bytes(&mut self, format :PcmByteFormat) ->
+			Result<Option<Vec<u8>>, VorbisError> {
+		let decoded_pck :Vec<Vec<f32>> = match try!(self.read_dec_packet_generic()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		let channels = decoded_pck.iter().map(|ch| &ch[..]).collect::<Vec<_>>();
+		let mut bytes = Vec::new();
+		format.write_interleaved(&channels, &mut bytes);
+		return Ok(Some(bytes));
+	}
+
+	/// Reads and decompresses audio packets until a budget is exhausted,
+	/// returning the interleaved samples.
+	///
+	/// Decoding stops when `max_packets` packets have been read,
+	/// or when at least `max_samples` samples (per channel) have been
+	/// decoded, whichever comes first. As packets are decoded
+	/// as a whole, the last packet may make the returned amount
+	/// of samples exceed `max_samples`, by at most one packet's length.
+	/// After this function returns, the reader is positioned
+	/// to continue decoding with the next packet.
+	///
+	/// If the end of the stream is reached, the samples decoded
+	/// so far are returned, which means that an empty `Vec`
+	/// is returned if the end had already been reached before the call.
+	///
+	/// On read errors, it returns Err(e) with the error.
+	pub fn read_budgeted(&mut self, max_packets :usize, max_samples :u64) ->
+			Result<Vec<i16>, VorbisError> {
+		let mut samples = Vec::new();
+		let mut decoded_count :u64 = 0;
+		for _ in 0 .. max_packets {
+			if decoded_count >= max_samples {
+				break;
 			}
-			if let &mut Some(ref mut absgp) = &mut self.cur_absgp {
-				*absgp += sample_cnt as u64;
+			let decoded_pck :InterleavedSamples<i16> = match try!(self.read_dec_packet_generic()) {
+				Some(p) => p,
+				None => break,
+			};
+			decoded_count += decoded_pck.num_samples() as u64;
+			samples.extend_from_slice(&decoded_pck.samples);
+		}
+		return Ok(samples);
+	}
+
+	/// Reads and decompresses an audio packet from the stream (generic).
+	///
+	/// On read errors, it returns Err(e) with the error.
+	///
+	/// On success, it either returns None, when the end of the
+	/// stream has been reached, or Some(packet_data),
+	/// with the data of the decompressed packet.
+	/// Zero length packets yield Some, with zero samples,
+	/// see `last_packet_empty`.
+	pub fn read_dec_packet_generic<S :Samples>(&mut self) ->
+			Result<Option<S>, VorbisError> {
+		loop {
+			let pck = match try!(self.read_next_audio_packet()) {
+				Some(p) => p,
+				None => return Ok(None),
+			};
+			let trimming = self.silence_threshold.is_some();
+			let decoded_pck :S = try!(self.dec_packet_generic(pck));
+			if trimming && decoded_pck.num_samples() == 0 {
+				// Don't emit packets dropped by silence trimming
+				continue;
 			}
-			last_pck = Some(next_pck);
+			return Ok(Some(decoded_pck));
 		}
 	}
 
-	/// Returns the stream serial of the current stream
+	/// Returns an iterator over the decoded packets of the stream
 	///
-	/// The stream serial can change in chained ogg files.
-	pub fn stream_serial(&self) -> u32 {
-		self.stream_serial
+	/// Each item is the result of a `read_dec_packet` call,
+	/// and the iterator ends where `read_dec_packet`
+	/// would return `Ok(None)`. Errors are passed on as items,
+	/// so that they don't get lost. After an error, the iterator
+	/// ends as well.
+	///
+	/// The iterator borrows the reader mutably, so the reader
+	/// can't be used otherwise while the iterator is alive.
+	/// Once it is dropped, the reader can be used again,
+	/// e.g. to seek and iterate over the packets once more.
+	pub fn packets(&mut self) -> Packets<'_, T> {
+		Packets {
+			inner : self.packets_generic(),
+		}
 	}
 
-	/// Returns the absolute granule position of the last read page.
+	/// Returns an iterator over the decoded packets of the stream (interleaved)
 	///
-	/// In the case of ogg/vorbis, the absolute granule position is given
-	/// as number of PCM samples, on a per channel basis.
-	pub fn get_last_absgp(&self) -> Option<u64> {
-		self.cur_absgp
+	/// Like `packets`, but the items are the results of
+	/// `read_dec_packet_itl` calls.
+	pub fn packets_itl(&mut self) -> PacketsItl<'_, T> {
+		PacketsItl {
+			inner : self.packets_generic(),
+		}
 	}
 
-	/// Seeks to the specified absolute granule position, with a page granularity.
+	/// Returns an iterator over the decoded packets of the stream (generic)
 	///
-	/// The granularity is per-page, and the obtained position is
-	/// then <= the seeked absgp.
+	/// Like `packets`, but the items are the results of
+	/// `read_dec_packet_generic` calls.
+	pub fn packets_generic<S :Samples>(&mut self) -> PacketsGeneric<'_, T, S> {
+		PacketsGeneric {
+			rdr : self,
+			ended : false,
+			phantom : PhantomData,
+		}
+	}
+
+	/// Reads and decompresses an audio packet from the stream,
+	/// also returning its spectra.
 	///
-	/// In the case of ogg/vorbis, the absolute granule position is given
-	/// as number of PCM samples, on a per channel basis.
-	pub fn seek_absgp_pg(&mut self, absgp :u64) -> Result<(), VorbisError> {
-		try!(self.rdr.seek_absgp(None, absgp));
-		// Reset the internal state after the seek
-		self.cur_absgp = None;
-		self.pwr = PreviousWindowRight::new();
-		Ok(())
+	/// On read errors, it returns Err(e) with the error.
+	///
+	/// On success, it either returns None, when the end of the
+	/// stream has been reached, or Some(packet),
+	/// with the data of the decompressed packet, as well as
+	/// the frequency domain data it was computed from.
+	///
+	/// This avoids decoding the packet twice if both
+	/// the samples and the spectra are needed.
+	pub fn read_dec_packet_full(&mut self) ->
+			Result<Option<DecodedPacket>, VorbisError> {
+		let pck = match try!(self.read_next_audio_packet()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		if self.skip_empty_packet(&pck) {
+			return Ok(Some(DecodedPacket {
+				samples : self.empty_packet(),
+				spectra : self.empty_packet(),
+			}));
+		}
+		let (mut samples, spectra) = try!(read_audio_packet_with_spectra(&self.ident_hdr,
+			&self.setup_hdr, &pck.data, &mut self.pwr));
+		self.update_after_decode(&pck, &mut samples);
+		return Ok(Some(DecodedPacket {
+			samples,
+			spectra,
+		}));
+	}
+
+	/// Reads and decompresses an audio packet from the stream,
+	/// also returning the amplitudes of its floor0 floors.
+	///
+	/// The amplitudes are returned per channel, and are `None`
+	/// for channels whose floor is unused or of type 1.
+	/// See `audio::read_audio_packet_with_floor0_amps` for details.
+	///
+	/// This is meant as a diagnostic tool.
+	pub fn read_dec_packet_with_floor0_amp(&mut self) ->
+			Result<Option<PacketWithFloor0Amps>, VorbisError> {
+		let pck = match try!(self.read_next_audio_packet()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		if self.skip_empty_packet(&pck) {
+			let amplitudes = vec![None; self.ident_hdr.audio_channels as usize];
+			return Ok(Some((self.empty_packet(), amplitudes)));
+		}
+		let (mut samples, amplitudes) = try!(read_audio_packet_with_floor0_amps(&self.ident_hdr,
+			&self.setup_hdr, &pck.data, &mut self.pwr));
+		self.update_after_decode(&pck, &mut samples);
+		return Ok(Some((samples, amplitudes)));
+	}
+
+	/// Reads and decompresses an audio packet from the stream,
+	/// also returning information about the compressed packet.
+	///
+	/// On read errors, it returns Err(e) with the error.
+	///
+	/// On success, it either returns None, when the end of the
+	/// stream has been reached, or Some(packet), with the data of
+	/// the decompressed packet, its length in compressed form,
+	/// its position, and the mode it uses.
+	/// Zero length packets have no mode, they are reported
+	/// with mode 0 and `long_block` set to false.
+	/// This is meant for tools analyzing the packet structure of streams.
+	pub fn read_dec_packet_detailed(&mut self) ->
+			Result<Option<DetailedPacket>, VorbisError> {
+		let pck = match try!(self.read_next_audio_packet()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		if self.skip_empty_packet(&pck) {
+			return Ok(Some(DetailedPacket {
+				samples : self.empty_packet(),
+				compressed_len : 0,
+				start_sample : self.returned_samples,
+				mode : 0,
+				long_block : false,
+			}));
+		}
+		let mode = try!(get_packet_mode(&self.setup_hdr, &pck.data));
+		let long_block = self.setup_hdr.modes[mode as usize].mode_blockflag;
+		let start_sample = self.returned_samples;
+		let mut samples :Vec<Vec<f32>> = try!(read_audio_packet_generic(&self.ident_hdr,
+			&self.setup_hdr, &pck.data, &mut self.pwr));
+		self.update_after_decode(&pck, &mut samples);
+		return Ok(Some(DetailedPacket {
+			samples,
+			compressed_len : pck.data.len(),
+			start_sample,
+			mode,
+			long_block,
+		}));
+	}
+
+	/// Reads the next audio packet from the stream, without decoding it.
+	///
+	/// On success, it either returns None, when the end of the
+	/// stream has been reached, or Some((data, last_in_page)), with the
+	/// raw data of the packet and whether it is the last packet of
+	/// its page. This is meant for remuxing the packets into
+	/// another container.
+	///
+	/// The absgp reported by `get_last_absgp` is still advanced,
+	/// by the number of samples the packet would decode to.
+	/// If decoding functions are called after this function, the
+	/// first decoded packet is only used to warm up the decoder and
+	/// yields no samples, just like after a seek.
+	pub fn read_raw_audio_packet(&mut self) -> Result<Option<(Vec<u8>, bool)>, VorbisError> {
+		let pck = match try!(self.read_next_audio_packet()) {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+		let blockflag = try!(get_packet_blockflag(&self.setup_hdr, &pck.data));
+		// The first packet of a stream doesn't yield any samples
+		let sample_cnt = if self.prev_blockflag.is_some() {
+			try!(get_decoded_sample_count(&self.ident_hdr, &self.setup_hdr, &pck.data))
+		} else {
+			0
+		};
+		if pck.last_in_page() {
+			self.cur_absgp = Some(pck.absgp_page());
+		} else if let &mut Some(ref mut absgp) = &mut self.cur_absgp {
+			*absgp += sample_cnt as u64;
+		}
+		self.pwr = PreviousWindowRight::new();
+		self.prev_blockflag = Some(blockflag);
+		self.last_block_transition = None;
+		let last_in_page = pck.last_in_page();
+		return Ok(Some((pck.data, last_in_page)));
+	}
+
+	/// Checks whether the packet is a zero length audio packet
+	///
+	/// If it is, the bookkeeping for the packet is done, and
+	/// true is returned. The packet must not be decoded then.
+	fn skip_empty_packet(&mut self, pck :&Packet) -> bool {
+		self.last_packet_empty = pck.data.is_empty();
+		if !self.last_packet_empty {
+			return false;
+		}
+		// The spec says that zero length audio packets must not
+		// be treated as errors, but instead be skipped over.
+		// Thus they don't affect the decoder state.
+		if pck.last_in_page() {
+			self.cur_absgp = Some(pck.absgp_page());
+		}
+		self.last_truncation = 0;
+		self.last_trimmed_start = 0;
+		self.stats.packets += 1;
+		true
+	}
+
+	/// Returns the samples of a zero length audio packet
+	fn empty_packet(&self) -> Vec<Vec<f32>> {
+		vec![Vec::new(); self.ident_hdr.audio_channels as usize]
+	}
+
+	#[inline]
+	pub fn dec_packet_generic<S :Samples>(&mut self, pck :Packet) ->
+			Result<S, VorbisError> {
+		if self.skip_empty_packet(&pck) {
+			return Ok(S::from_floats(self.empty_packet()));
+		}
+		let mut decoded_pck :Vec<Vec<f32>> = try!(read_audio_packet_generic(&self.ident_hdr,
+			&self.setup_hdr, &pck.data, &mut self.pwr));
+		self.update_after_decode(&pck, &mut decoded_pck);
+		return Ok(S::from_floats(decoded_pck));
+	}
+
+	/// Reads and decompresses an audio packet from the stream,
+	/// replacing undecodable packets by silence.
+	///
+	/// Like `read_dec_packet`, but if an audio packet fails to decode,
+	/// the decoder state is reset, and a silent packet is returned
+	/// in its place, with the length the packet would have had.
+	/// As the decoder needs to warm up again after the reset, the
+	/// packet following the undecodable one is replaced by silence
+	/// as well, so that the stream keeps its length.
+	/// Errors of the ogg layer are still returned, as well as
+	/// `AudioReadError::BufferNotAddressable`.
+	///
+	/// This is useful for playback of partially corrupt files,
+	/// like interrupted downloads.
+	/// The recovered errors are counted in the `recovered_errors`
+	/// field of the `stats`.
+	pub fn read_dec_packet_lossy(&mut self) ->
+			Result<Option<Vec<Vec<i16>>>, VorbisError> {
+		loop {
+			let pck = match try!(self.read_next_audio_packet()) {
+				Some(p) => p,
+				None => return Ok(None),
+			};
+			let trimming = self.silence_threshold.is_some();
+			if pck.data.is_empty() {
+				let decoded_pck :Vec<Vec<i16>> = try!(self.dec_packet_generic(pck));
+				if trimming {
+					continue;
+				}
+				return Ok(Some(decoded_pck));
+			}
+			// Packets after a reset have a length too,
+			// unlike the first packet of the stream.
+			let has_len = !self.pwr.is_empty() || self.lossy_reset;
+			// Compute the length before decoding, as the decoder state
+			// is lost if decoding fails half way through. The window
+			// flags of a corrupt packet can't be trusted, so use the
+			// size of the previous block if it is known.
+			let blockflag = get_packet_blockflag(&self.setup_hdr, &pck.data);
+			let expected_len = match (self.prev_blockflag, blockflag) {
+				(Some(prev), Ok(cur)) => {
+					let block_size = |long :bool| if long {
+						1 << self.ident_hdr.blocksize_1
+					} else {
+						1 << self.ident_hdr.blocksize_0
+					};
+					Ok((block_size(prev) + block_size(cur)) / 4)
+				},
+				_ => get_decoded_sample_count(&self.ident_hdr,
+					&self.setup_hdr, &pck.data),
+			};
+			let decoded_pck = match read_audio_packet_generic::<Vec<Vec<f32>>>(
+					&self.ident_hdr, &self.setup_hdr, &pck.data, &mut self.pwr) {
+				Ok(mut decoded_pck) => {
+					if self.lossy_reset {
+						// The packet only warmed up the decoder
+						let len = expected_len.unwrap_or(0);
+						for ch in decoded_pck.iter_mut() {
+							ch.resize(len, 0.0);
+						}
+						self.lossy_reset = false;
+					}
+					self.update_after_decode(&pck, &mut decoded_pck);
+					decoded_pck
+				},
+				Err(AudioReadError::BufferNotAddressable) =>
+					try!(Err(AudioReadError::BufferNotAddressable)),
+				Err(_) => {
+					self.pwr = PreviousWindowRight::new();
+					self.lossy_reset = true;
+					let len = match expected_len {
+						Ok(len) if has_len => len,
+						_ => 0,
+					};
+					let channels = self.ident_hdr.audio_channels as usize;
+					let mut silent_pck = vec![vec![0.0; len]; channels];
+					self.update_after_decode(&pck, &mut silent_pck);
+					self.stats.recovered_errors += 1;
+					silent_pck
+				},
+			};
+			if trimming && decoded_pck.num_samples() == 0 {
+				// Don't emit packets dropped by silence trimming
+				continue;
+			}
+			return Ok(Some(Samples::from_floats(decoded_pck)));
+		}
+	}
+
+	/// Truncates the decoded packet if needed, updates the absgp
+	/// and drops samples that are requested to be skipped
+	fn update_after_decode(&mut self, pck :&Packet, decoded_pck :&mut Vec<Vec<f32>>) {
+		// If this is the last packet in the logical bitstream,
+		// we need to truncate it so that its ending matches
+		// the absgp of the current page.
+		// This is what the spec mandates and also the behaviour
+		// of libvorbis.
+		let blockflag = get_packet_blockflag(&self.setup_hdr, &pck.data).ok();
+		self.last_block_transition = match (self.prev_blockflag, blockflag) {
+			(Some(prev), Some(cur)) => Some(BlockTransition::from_blockflags(prev, cur)),
+			_ => None,
+		};
+		self.prev_blockflag = blockflag;
+		self.last_truncation = 0;
+		self.last_trimmed_start = 0;
+		self.last_packet_empty = false;
+		if let (Some(absgp), true) = (self.cur_absgp, pck.last_in_stream()) {
+			let target_length = pck.absgp_page().saturating_sub(absgp) as usize;
+			let length = decoded_pck.num_samples();
+			if target_length < length {
+				self.last_truncation = length - target_length;
+				self.total_trimmed_end += self.last_truncation as u64;
+				if self.pad_final_packet {
+					for ch in decoded_pck.iter_mut() {
+						for s in ch[target_length ..].iter_mut() {
+							*s = 0.0;
+						}
+					}
+				} else {
+					Samples::truncate(decoded_pck, target_length);
+				}
+			}
+		}
+		if pck.last_in_page() {
+			self.cur_absgp = Some(pck.absgp_page());
+		} else if let &mut Some(ref mut absgp) = &mut self.cur_absgp {
+			*absgp += decoded_pck.num_samples() as u64;
+		}
+		if self.skip_count > 0 {
+			let to_skip = min(self.skip_count, decoded_pck.num_samples() as u64);
+			for ch in decoded_pck.iter_mut() {
+				ch.drain(.. to_skip as usize);
+			}
+			self.skip_count -= to_skip;
+			self.last_trimmed_start = to_skip as usize;
+			self.total_trimmed_start += to_skip;
+		}
+		if let Some(threshold) = self.silence_threshold {
+			let peak = decoded_pck.iter()
+				.flat_map(|ch| ch.iter())
+				.fold(0.0f32, |peak, s| peak.max(s.abs()));
+			if peak < threshold {
+				for ch in decoded_pck.iter_mut() {
+					ch.clear();
+				}
+			} else {
+				self.silence_threshold = None;
+			}
+		}
+		self.returned_samples += decoded_pck.num_samples() as u64;
+		self.stats.packets += 1;
+		self.stats.samples += decoded_pck.num_samples() as u64;
+		self.stats.compressed_bytes += pck.data.len() as u64;
+	}
+	/// Skips the given number of samples
+	///
+	/// Skips multiple packets without decoding any but the last two, so that
+	/// the leftover number of samples to skip is lower than the length of the
+	/// returned packet.
+	///
+	/// The function runs a linear skip algorithm which means that instead of
+	/// logarithmic *seeking*, it inspects each packet for its length, and
+	/// subtracts the length from the length to skip. This function does no
+	/// packet decoding until it arrives at the destination, which makes it
+	/// way cheaper than just decoding all packets.
+	///
+	/// The absolute granule position is always increased in whole-package
+	/// increments.
+	pub fn skip_samples_linear<S :Samples>(&mut self, to_skip :usize) -> Result<(Option<S>, usize), VorbisError> {
+		let mut to_skip = to_skip;
+		let mut last_pck :Option<Packet> = None;
+		let mut next_pck;
+
+		loop {
+			if let Some(p) = try!(self.read_next_audio_packet()) {
+				next_pck = p;
+			} else {
+				return Ok((None, to_skip));
+			}
+			let mut sample_cnt = try!(get_decoded_sample_count(&self.ident_hdr, &self.setup_hdr, &next_pck.data));
+			// If this is the last packet in the logical bitstream,
+			// we need to truncate it so that its ending matches
+			// the absgp of the current page.
+			// This is what the spec mandates and also the behaviour
+			// of libvorbis.
+			if let (Some(absgp), true) = (self.cur_absgp, next_pck.last_in_stream()) {
+				last_pck = None;
+				let target_length = next_pck.absgp_page().saturating_sub(absgp) as usize;
+				sample_cnt = sample_cnt.min(target_length);
+			}
+			if to_skip < sample_cnt {
+				// We reached the end of our search.
+				if let Some(last_pck) = last_pck {
+					self.pwr = PreviousWindowRight::new();
+					let _decoded_pck :S = try!(read_audio_packet_generic(&self.ident_hdr,
+						&self.setup_hdr, &last_pck.data, &mut self.pwr));
+					self.prev_blockflag = get_packet_blockflag(&self.setup_hdr,
+						&last_pck.data).ok();
+				}
+				let decoded_pck = try!(self.dec_packet_generic(next_pck));
+				return Ok((Some(decoded_pck), to_skip));
+			} else {
+				to_skip -= sample_cnt;
+			}
+			if let &mut Some(ref mut absgp) = &mut self.cur_absgp {
+				*absgp += sample_cnt as u64;
+			}
+			last_pck = Some(next_pck);
+		}
+	}
+
+	/// Returns the stream serial of the current stream
+	///
+	/// The stream serial can change in chained ogg files.
+	pub fn stream_serial(&self) -> u32 {
+		self.stream_serial
+	}
+
+	/// Returns a summary of the properties of the current stream
+	///
+	/// The properties can change in chained ogg files.
+	pub fn info(&self) -> VorbisInfo {
+		VorbisInfo::from_ident(&self.ident_hdr)
+	}
+
+	/// Returns the absolute granule position of the last read page.
+	///
+	/// In the case of ogg/vorbis, the absolute granule position is given
+	/// as number of PCM samples, on a per channel basis.
+	pub fn get_last_absgp(&self) -> Option<u64> {
+		self.cur_absgp
+	}
+
+	/// Counts the short and long blocks of the remaining packets
+	///
+	/// Returns `(short_count, long_count)`. Only the mode of each
+	/// packet is inspected, so this is much cheaper than decoding.
+	/// Only the packets of the current logical stream are counted.
+	///
+	/// Afterwards, the reader is at the same position as before,
+	/// and decoding continues where it left off.
+	pub fn block_size_histogram(&mut self) -> Result<(u64, u64), VorbisError> {
+		let pos = try!(self.save_position());
+		let res = self.count_block_sizes();
+		try!(self.restore_position(pos));
+		return res;
+	}
+
+	fn count_block_sizes(&mut self) -> Result<(u64, u64), VorbisError> {
+		let mut short_count = 0;
+		let mut long_count = 0;
+		let mut stream_ended = self.stream_ended;
+		let mut pending = self.pending_packets.iter();
+		while !stream_ended {
+			let read_pck;
+			let pck = match pending.next() {
+				Some(p) => p,
+				None => match try!(self.rdr.read_packet()) {
+					Some(p) => {
+						read_pck = p;
+						&read_pck
+					},
+					None => break,
+				},
+			};
+			if pck.stream_serial() != self.stream_serial {
+				continue;
+			}
+			stream_ended = pck.last_in_stream();
+			// Header packets have the first bit set,
+			// audio packets have it unset.
+			if pck.data.first().map_or(false, |b| b & 1 == 1) {
+				continue;
+			}
+			if try!(get_packet_blockflag(&self.setup_hdr, &pck.data)) {
+				long_count += 1;
+			} else {
+				short_count += 1;
+			}
+		}
+		return Ok((short_count, long_count));
+	}
+
+	/// Returns the byte position of the underlying reader,
+	/// for `restore_position` to go back to it
+	///
+	/// The ogg reader drops the unread packets of the current page
+	/// when asked for its position, so if the last read packet didn't
+	/// end its page, the remaining packets of the page are read ahead
+	/// first. They get returned by `read_next_audio_packet` later on.
+	fn save_position(&mut self) -> Result<u64, VorbisError> {
+		while self.mid_page {
+			match try!(self.rdr.read_packet()) {
+				Some(pck) => {
+					if pck.stream_serial() == self.stream_serial {
+						self.mid_page = !pck.last_in_page();
+					}
+					self.pending_packets.push_back(pck);
+				},
+				None => self.mid_page = false,
+			}
+		}
+		let pos = try!(self.rdr.seek_bytes(SeekFrom::Current(0))
+			.map_err(OggReadError::ReadError));
+		return Ok(pos);
+	}
+
+	/// Seeks the underlying reader back to a position
+	/// obtained from `save_position`
+	///
+	/// Unlike the seek functions, this keeps the decoder state,
+	/// as reading continues with the same packets as before.
+	fn restore_position(&mut self, pos :u64) -> Result<(), VorbisError> {
+		try!(self.rdr.seek_bytes(SeekFrom::Start(pos))
+			.map_err(OggReadError::ReadError));
+		return Ok(());
+	}
+
+	/// Returns the number of audio packets in the stream
+	///
+	/// The three header packets are not counted.
+	/// This equals the number of times that `read_dec_packet`
+	/// returns `Some` when reading the stream from its start.
+	///
+	/// The first call scans through the entire logical stream, and
+	/// returns the reader to its previous position afterwards, so
+	/// decoding continues where it left off. The result is cached,
+	/// so later calls return immediately.
+	pub fn packet_count(&mut self) -> Result<u64, VorbisError> {
+		let scan = try!(self.scan_stream());
+		return Ok(scan.packet_count);
+	}
+
+	/// Returns the range of bytes the current logical stream
+	/// occupies inside the file
+	///
+	/// The range starts with the first page of the stream and ends
+	/// after its last page. For chained files, this is the range of the
+	/// link that is currently being read, and it changes when the reader
+	/// advances to the next link. Data after the end of the last page,
+	/// like trailing junk, is not included.
+	///
+	/// Like `packet_count`, the first call scans through the stream,
+	/// without changing the position of the reader.
+	pub fn current_stream_byte_range(&mut self) -> Result<Range<u64>, VorbisError> {
+		let scan = try!(self.scan_stream());
+		let (start, end) = scan.byte_range;
+		return Ok(start .. end);
+	}
+
+	fn scan_stream(&mut self) -> Result<StreamScan, VorbisError> {
+		if let Some(scan) = self.stream_scan {
+			return Ok(scan);
+		}
+		// Return to the current position afterwards,
+		// even if the scan failed
+		let pos = try!(self.save_position());
+		let res = self.scan_link();
+		try!(self.restore_position(pos));
+		let scan = try!(res);
+		self.stream_scan = Some(scan);
+		return Ok(scan);
+	}
+
+	/// Scans through the current link, starting at `link_start`
+	fn scan_link(&mut self) -> Result<StreamScan, VorbisError> {
+		macro_rules! tell {
+			() => {
+				try!(self.rdr.seek_bytes(SeekFrom::Current(0))
+					.map_err(OggReadError::ReadError))
+			};
+		}
+		try!(self.rdr.seek_bytes(SeekFrom::Start(self.link_start))
+			.map_err(OggReadError::ReadError));
+		let mut packet_count = 0;
+		// End of the last logical stream before ours
+		let mut start = self.link_start;
+		let mut found_stream = false;
+		let mut end_absgp = 0;
+		let mut start_absgp = None;
+		// Number of samples before the end of the first audio page.
+		// The first audio packet doesn't yield any samples.
+		let mut start_samples = None;
+		let mut header_count = 0;
+		let mut audio_start = None;
+		let end = loop {
+			let pck = match try!(self.rdr.read_packet()) {
+				Some(p) => p,
+				None => break tell!(),
+			};
+			if pck.stream_serial() != self.stream_serial {
+				if pck.last_in_stream() && !found_stream {
+					// Once a page ended a stream, reading the next page
+					// hasn't started yet, so asking for the position
+					// doesn't lose any packets.
+					start = tell!();
+				}
+				continue;
+			}
+			found_stream = true;
+			if pck.last_in_page() {
+				end_absgp = pck.absgp_page();
+			}
+			// Header packets have the first bit set,
+			// audio packets have it unset.
+			let is_audio = pck.data.first().map_or(true, |b| b & 1 == 0);
+			if !is_audio {
+				header_count += 1;
+			}
+			if header_count >= 3 && audio_start.is_none() && pck.last_in_page() {
+				audio_start = Some(tell!());
+			}
+			if is_audio && start_absgp.is_none() {
+				start_samples = Some(match start_samples {
+					None => 0,
+					Some(n) => n + try!(get_decoded_sample_count(&self.ident_hdr,
+						&self.setup_hdr, &pck.data)) as u64,
+				});
+				if pck.last_in_page() {
+					start_absgp = Some(pck.absgp_page()
+						.saturating_sub(start_samples.unwrap_or(0)));
+				}
+			}
+			if pck.last_in_stream() {
+				let end = tell!();
+				if is_audio {
+					packet_count += 1;
+				}
+				break end;
+			}
+			if !is_audio {
+				continue;
+			}
+			packet_count += 1;
+		};
+		return Ok(StreamScan {
+			packet_count,
+			byte_range : (start, end),
+			end_absgp,
+			start_absgp : start_absgp.unwrap_or(0),
+			audio_start : audio_start.unwrap_or(end),
+		});
+	}
+
+	/// Returns the number of bytes of the current logical stream
+	/// that come after the headers
+	///
+	/// This is the size of the range of bytes between the end of the
+	/// page with the setup header and the end of the stream, so it
+	/// includes the page headers of the audio pages. Together with
+	/// the duration of the stream, this can be used to compute
+	/// its bitrate without counting the headers, which can be large.
+	///
+	/// Like `packet_count`, the first call scans through the stream,
+	/// without changing the position of the reader.
+	pub fn audio_byte_len(&mut self) -> Result<u64, VorbisError> {
+		let scan = try!(self.scan_stream());
+		return Ok(scan.byte_range.1 - scan.audio_start);
+	}
+
+	/// Returns the absgp of the last page of the current logical stream
+	///
+	/// Like `packet_count`, the first call scans through the stream,
+	/// without changing the position of the reader.
+	pub(crate) fn end_absgp(&mut self) -> Result<u64, VorbisError> {
+		let scan = try!(self.scan_stream());
+		return Ok(scan.end_absgp);
+	}
+
+	/// Returns the total number of samples (per channel)
+	/// of the current logical stream
+	///
+	/// This is the difference between the granule position of the
+	/// last page and that of the first sample, so it equals the
+	/// number of samples the decoding functions return when reading
+	/// the stream from its start. Streams with at most one audio
+	/// packet don't contain any samples, and 0 is returned for them.
+	///
+	/// Like `packet_count`, the first call scans through the stream,
+	/// without changing the position of the reader. The result
+	/// is cached, so later calls don't scan the stream again.
+	pub fn total_samples(&mut self) -> Result<u64, VorbisError> {
+		let scan = try!(self.scan_stream());
+		if scan.packet_count <= 1 {
+			return Ok(0);
+		}
+		return Ok(scan.end_absgp.saturating_sub(scan.start_absgp));
+	}
+
+	/// Returns the total duration of the current logical stream
+	///
+	/// This is `total_samples` divided by the sample rate,
+	/// e.g. for progress bars of players.
+	pub fn total_duration(&mut self) -> Result<Duration, VorbisError> {
+		let samples = try!(self.total_samples());
+		let duration = samples_to_duration(samples, self.ident_hdr.audio_sample_rate);
+		return Ok(duration.unwrap_or_default());
+	}
+
+	/// Returns the absolute granule position of the sample
+	/// with the given index
+	///
+	/// The index counts the samples (per channel) returned by the
+	/// decoding functions, starting at the beginning of the current
+	/// logical stream, so the first audio packet, which doesn't yield
+	/// any samples, is not counted. The granule position is obtained by
+	/// adding the index to the granule position of the first sample.
+	/// The latter is zero for most files, but can be larger for
+	/// streams whose recording started in the middle, like
+	/// captures of live streams. In those cases, the granule position
+	/// is larger than the sample index, and `get_last_absgp` reports
+	/// positions on the granule position scale as well.
+	///
+	/// The positions obtained can be passed to `seek_absgp_pg` or
+	/// `decode_region`, and used to write external seek tables.
+	///
+	/// Like `packet_count`, the first call scans through the stream,
+	/// without changing the position of the reader.
+	pub fn granule_for_sample(&mut self, sample_index :u64) -> Result<u64, VorbisError> {
+		let scan = try!(self.scan_stream());
+		return Ok(scan.start_absgp.saturating_add(sample_index));
+	}
+
+	/// Returns the time the stages of decoding
+	/// the last packet have taken
+	///
+	/// The times are only measured if the `profiling`
+	/// feature is enabled. Otherwise, they are all zero.
+	pub fn last_stage_times(&self) -> StageTimes {
+		self.pwr.last_stage_times()
+	}
+
+	/// Returns whether the floors of all channels
+	/// were unused in the last decoded packet
+	///
+	/// This is a cheap indicator of silence, as in that case, the
+	/// spectrum of the packet is entirely zero. However, the samples
+	/// of such a packet can still be nonzero, due to the overlap
+	/// with the previous packet.
+	/// Please see `PreviousWindowRight::last_packet_was_silent`.
+	pub fn last_packet_was_silent(&self) -> bool {
+		self.pwr.last_packet_was_silent()
+	}
+
+	/// Returns whether the last audio packet was zero bytes long
+	///
+	/// Such packets are allowed by the spec, and decode to zero
+	/// samples, without affecting the decoding of the packets
+	/// around them. With this function, they can be told apart
+	/// from packets that yield no samples for other reasons.
+	/// Note that the end of the stream is only indicated by `None`.
+	pub fn last_packet_empty(&self) -> bool {
+		self.last_packet_empty
+	}
+
+	/// Returns the block size transition between the last
+	/// decoded packet and the packet before it
+	///
+	/// The transition determines the shape of the window
+	/// that the two packets got overlapped with.
+	///
+	/// Returns `None` if no packet has been decoded yet,
+	/// or if the last decoded packet is the first one
+	/// at the start of a stream or after a seek.
+	pub fn last_block_transition(&self) -> Option<BlockTransition> {
+		self.last_block_transition
+	}
+
+	/// Sets whether to zero pad the last packet of the stream
+	///
+	/// Normally, the last packet of a stream gets truncated so
+	/// that the stream ends at the position its last page specifies.
+	/// If padding is enabled, the truncated samples are instead set
+	/// to zero, so that the packet keeps its nominal length.
+	/// The number of padding samples can be obtained
+	/// via `last_truncation`.
+	///
+	/// Padding is disabled by default.
+	pub fn pad_final_packet(&mut self, pad :bool) -> &mut Self {
+		self.pad_final_packet = pad;
+		self
+	}
+
+	/// Enables trimming of silence at the start of the stream
+	///
+	/// Packets are dropped until the first packet is encountered whose
+	/// peak absolute sample value reaches `threshold`, on the scale of
+	/// the `f32` output where full scale is `1.0`. From that packet on,
+	/// all packets are emitted. The trimming is done on a per-packet
+	/// basis, so some silence might remain at the start of the first
+	/// emitted packet.
+	///
+	/// Dropped packets still advance the absgp.
+	/// Trimming silence at the end of the stream is not supported,
+	/// as it requires knowing where the stream ends in advance.
+	///
+	/// Trimming is disabled by default.
+	pub fn trim_silence(&mut self, threshold :f32) -> &mut Self {
+		self.silence_threshold = Some(threshold);
+		self
+	}
+
+	/// Returns the number of samples the last decoded packet
+	/// has been truncated by
+	///
+	/// This is only nonzero for the last packet of a stream.
+	/// If padding is enabled via `pad_final_packet`, this is the
+	/// number of zero samples at the end of the packet instead.
+	pub fn last_truncation(&self) -> usize {
+		self.last_truncation
+	}
+
+	/// Returns the number of samples that have been dropped
+	/// from the start of the last decoded packet
+	///
+	/// This is nonzero for packets that samples have been skipped
+	/// from, e.g. after `set_position` or `seek_absgp`.
+	/// Samples dropped via `trim_silence` are not counted.
+	pub fn last_packet_trimmed_start(&self) -> usize {
+		self.last_trimmed_start
+	}
+
+	/// Returns the number of samples that have been truncated
+	/// from the end of the last decoded packet
+	///
+	/// This is the same as `last_truncation`, and only nonzero
+	/// for the last packet of a stream, whose end is given by the
+	/// absgp of the final page.
+	pub fn last_packet_trimmed_end(&self) -> usize {
+		self.last_truncation
+	}
+
+	/// Returns counters of the decoding done since
+	/// the reader has been created
+	pub fn stats(&self) -> DecodeStats {
+		self.stats
+	}
+
+	/// Returns the comment header of the current logical stream
+	pub fn comment_hdr(&self) -> &CommentHeader {
+		&self.comment_hdr
+	}
+
+	/// Returns the total number of samples that have been dropped
+	/// from the start of decoded packets
+	///
+	/// Samples get dropped at the start when a skip count is set via
+	/// `set_position`, or by `seek_absgp`. Samples dropped via
+	/// `trim_silence` are not counted.
+	///
+	/// The count is accumulated since the reader has been created,
+	/// over all links of chained files and over seeks.
+	pub fn total_trimmed_start(&self) -> u64 {
+		self.total_trimmed_start
+	}
+
+	/// Returns the total number of samples that the last packets
+	/// of streams have been truncated by
+	///
+	/// This is the sum of all nonzero values of `last_truncation`.
+	/// Like `total_trimmed_start`, the count is accumulated since
+	/// the reader has been created.
+	pub fn total_trimmed_end(&self) -> u64 {
+		self.total_trimmed_end
+	}
+
+	/// Sets the internal position bookkeeping of the reader
+	///
+	/// This is an advanced function, meant for custom seek
+	/// implementations that reposition the underlying stream themselves,
+	/// e.g. via the `PacketReader` obtained from `into_inner`.
+	/// The reader will assume that `cur_absgp` is the absolute granule
+	/// position directly before the next packet to be read,
+	/// and drop the first `skip_count` samples it decodes afterwards.
+	///
+	/// No checks are performed on the passed values: if they don't
+	/// match the actual position in the stream, the absgp reported by
+	/// `get_last_absgp` will be wrong and the last packet of
+	/// the stream might not get truncated at the correct position.
+	pub fn set_position(&mut self, cur_absgp :u64, skip_count :u64) {
+		self.cur_absgp = Some(cur_absgp);
+		self.skip_count = skip_count;
+	}
+
+	/// Seeks to the specified absolute granule position, with a page granularity.
+	///
+	/// The granularity is per-page, and the obtained position is
+	/// then <= the seeked absgp.
+	///
+	/// In the case of ogg/vorbis, the absolute granule position is given
+	/// as number of PCM samples, on a per channel basis.
+	pub fn seek_absgp_pg(&mut self, absgp :u64) -> Result<(), VorbisError> {
+		try!(self.rdr.seek_absgp(None, absgp));
+		self.reset_after_seek();
+		Ok(())
+	}
+
+	/// Seeks to the specified absolute granule position, with sample accuracy.
+	///
+	/// Unlike `seek_absgp_pg`, the next decoded samples start exactly
+	/// at the given position, and match the ones obtained when decoding
+	/// the entire stream. For this, the reader seeks to a page before the
+	/// position and decodes packets until it knows the position of the
+	/// decoded samples. The remaining distance to the seeked position
+	/// is then dropped from the next packets via the skip count,
+	/// the same way as it is done by `set_position`.
+	///
+	/// If the position is beyond the end of the stream,
+	/// no samples are returned afterwards.
+	///
+	/// The packets decoded to find the position are not returned,
+	/// so they are not counted in the `stats` nor in the totals of
+	/// trimmed samples, and they don't end silence trimming.
+	pub fn seek_absgp(&mut self, absgp :u64) -> Result<(), VorbisError> {
+		let stats = self.stats;
+		let total_trimmed_start = self.total_trimmed_start;
+		let total_trimmed_end = self.total_trimmed_end;
+		let silence_threshold = self.silence_threshold.take();
+		let res = self.seek_absgp_inner(absgp);
+		self.stats = stats;
+		self.total_trimmed_start = total_trimmed_start;
+		self.total_trimmed_end = total_trimmed_end;
+		self.silence_threshold = silence_threshold;
+		res
+	}
+
+	fn seek_absgp_inner(&mut self, absgp :u64) -> Result<(), VorbisError> {
+		let mut margin :u64 = 0;
+		loop {
+			let seek_target = absgp.saturating_sub(margin);
+			try!(self.seek_absgp_pg(seek_target));
+			// Decode until the absgp becomes known,
+			// which tells us the position of the decoded samples.
+			let mut buffered_len = 0;
+			let mut ends_stream = false;
+			while self.cur_absgp.is_none() {
+				let pck = match try!(self.read_next_audio_packet()) {
+					Some(p) => p,
+					None => break,
+				};
+				ends_stream = pck.last_in_stream();
+				let decoded_pck :Vec<Vec<f32>> = try!(self.dec_packet_generic(pck));
+				buffered_len += decoded_pck.num_samples() as u64;
+			}
+			let end = match self.cur_absgp {
+				Some(absgp) => absgp,
+				None => return Ok(()),
+			};
+			// If the absgp is from the last page, the last packet couldn't
+			// be truncated as the position was unknown, so we can't
+			// use the absgp to compute the position either.
+			if end <= absgp && !ends_stream {
+				self.skip_count = absgp - end;
+				self.returned_samples = 0;
+				return Ok(());
+			}
+			if seek_target > 0 {
+				// The seek went past the position,
+				// retry with a larger margin.
+				margin = max(margin * 2, 1 << self.ident_hdr.blocksize_1);
+				continue;
+			}
+			// The position lies inside the first page. Decode it again, and
+			// skip the samples before. The decoded samples started at
+			// end - buffered_len, which might be negative.
+			try!(self.seek_absgp_pg(0));
+			self.skip_count = (absgp + buffered_len).saturating_sub(end);
+			return Ok(());
+		}
+	}
+
+	/// Seeks to the specified time, in seconds, with sample accuracy.
+	///
+	/// The time gets multiplied with the sample rate and rounded to the
+	/// nearest sample, which is then passed to `seek_absgp`. Negative
+	/// or NaN times seek to the start of the stream.
+	pub fn seek_time(&mut self, seconds :f64) -> Result<(), VorbisError> {
+		let absgp = if seconds > 0.0 {
+			// Casts from float to int saturate
+			(seconds * self.ident_hdr.audio_sample_rate as f64).round() as u64
+		} else {
+			0
+		};
+		return self.seek_absgp(absgp);
+	}
+
+	/// Seeks to the given fraction of the stream, with sample accuracy.
+	///
+	/// A fraction of 0 is the start of the stream, and a fraction of 1
+	/// its end. Values outside of that range are clamped, and NaN
+	/// seeks to the start of the stream. The position is obtained
+	/// from `total_samples`, so the stream is scanned on the first call.
+	pub fn seek_fraction(&mut self, frac :f64) -> Result<(), VorbisError> {
+		let frac = if frac > 0.0 {
+			frac.min(1.0)
+		} else {
+			0.0
+		};
+		let total = try!(self.total_samples());
+		let absgp = try!(self.granule_for_sample((frac * total as f64).round() as u64));
+		return self.seek_absgp(absgp);
+	}
+
+	/// Resynchronizes the reader after an error of the ogg layer
+	///
+	/// When a corrupt page is encountered, reading packets returns
+	/// `VorbisError::OggError`. This function then skips forward
+	/// to the next valid page of the current logical stream
+	/// on which a packet ends, and resumes decoding after it,
+	/// as if the reader had seeked there. The absgp is taken
+	/// from the granule position of that page.
+	///
+	/// Returns `Ok(false)` if the end of the physical stream has been
+	/// reached without finding such a page. I/O errors are returned.
+	/// Pages of other logical streams are skipped, so in chained
+	/// files, this doesn't resynchronize to a following stream.
+	///
+	/// Successful resynchronizations are counted in the
+	/// `recovered_errors` field of the `stats`.
+	pub fn resync(&mut self) -> Result<bool, VorbisError> {
+		loop {
+			// Drop the packets of the corrupt page, and make the ogg
+			// reader tolerate the continuation of a lost packet
+			try!(self.rdr.seek_bytes(SeekFrom::Current(0))
+				.map_err(OggReadError::ReadError));
+			loop {
+				let pck = match self.rdr.read_packet() {
+					Ok(Some(pck)) => pck,
+					Ok(None) => return Ok(false),
+					Err(OggReadError::ReadError(ref e))
+							if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+					Err(e @ OggReadError::ReadError(_)) => try!(Err(e)),
+					// Another corrupt page, start over
+					Err(_) => break,
+				};
+				if pck.stream_serial() != self.stream_serial || !pck.last_in_page() {
+					continue;
+				}
+				self.reset_after_seek();
+				self.cur_absgp = Some(pck.absgp_page());
+				if pck.last_in_stream() {
+					self.stream_ended = true;
+					try!(self.rdr.seek_bytes(SeekFrom::Current(0))
+						.map_err(OggReadError::ReadError));
+				}
+				self.stats.recovered_errors += 1;
+				return Ok(true);
+			}
+		}
+	}
+
+	/// Resets the internal state after the underlying reader was seeked
+	fn reset_after_seek(&mut self) {
+		self.mid_page = false;
+		self.pending_packets.clear();
+		self.cur_absgp = None;
+		self.skip_count = 0;
+		self.skip_header_packets = true;
+		self.pwr = PreviousWindowRight::new();
+		self.stream_ended = false;
+		self.returned_samples = 0;
+		self.prev_blockflag = None;
+		self.lossy_reset = false;
+		self.last_block_transition = None;
+		#[cfg(debug_assertions)]
+		{
+			self.last_page_absgp = None;
+		}
+	}
+
+	/// Checks the position bookkeeping of the reader for consistency
+	///
+	/// This is a debugging aid for changes to the seeking
+	/// and truncation logic, meant to be called in tests
+	/// between decoding packets. It checks that:
+	///
+	/// * the current absgp lies within the stream's range,
+	///   if the stream has been scanned already,
+	/// * no samples are left to skip once samples have
+	///   been returned since the last seek,
+	/// * the current absgp matches the absgp of the page
+	///   the last packet came from, if it ended that page.
+	///
+	/// Returns a description of the first violated invariant.
+	/// Note that `set_position` can break the invariants,
+	/// as the values passed to it are not checked.
+	#[cfg(debug_assertions)]
+	pub fn check_invariants(&self) -> Result<(), String> {
+		if let (Some(absgp), Some(scan)) = (self.cur_absgp, self.stream_scan.as_ref()) {
+			if absgp < scan.start_absgp {
+				return Err(format!("cur_absgp {} lies before the stream start {}",
+					absgp, scan.start_absgp));
+			}
+			if absgp > scan.end_absgp {
+				return Err(format!("cur_absgp {} lies after the stream end {}",
+					absgp, scan.end_absgp));
+			}
+		}
+		if self.returned_samples > 0 && self.skip_count > 0 {
+			return Err(format!("skip_count is {} although {} samples were returned",
+				self.skip_count, self.returned_samples));
+		}
+		if let Some(page_absgp) = self.last_page_absgp {
+			if self.cur_absgp != Some(page_absgp) {
+				return Err(format!("cur_absgp {:?} doesn't match the page absgp {}",
+					self.cur_absgp, page_absgp));
+			}
+		}
+		return Ok(());
+	}
+
+	/// Seeks to the specified timestamp, given in a custom time base,
+	/// with sample accuracy.
+	///
+	/// The timestamp `ts` is in units of `1 / timebase_hz` seconds,
+	/// e.g. a `timebase_hz` of `90_000` is the MPEG time base.
+	/// It is converted to an absolute granule position by multiplying
+	/// it with the sample rate and dividing by `timebase_hz`,
+	/// rounding to the nearest sample, with halfway cases rounded up.
+	/// Then, `seek_absgp` is called with the resulting position.
+	///
+	/// If `timebase_hz` is zero, `VorbisError::ZeroTimeBase` is returned.
+	pub fn seek_timestamp(&mut self, ts :u64, timebase_hz :u64) -> Result<(), VorbisError> {
+		if timebase_hz == 0 {
+			return Err(VorbisError::ZeroTimeBase);
+		}
+		let rate = self.ident_hdr.audio_sample_rate as u128;
+		let timebase_hz = timebase_hz as u128;
+		let absgp = (ts as u128 * rate + timebase_hz / 2) / timebase_hz;
+		let absgp = min(absgp, u64::MAX as u128) as u64;
+		return self.seek_absgp(absgp);
+	}
+
+	/// Decodes a region of the stream, returning the interleaved samples.
+	///
+	/// Seeks to the given absolute granule position `start_sample`
+	/// via `seek_absgp` and returns exactly `len` samples (per channel)
+	/// starting from there, or fewer if the end of the stream comes
+	/// earlier. As the seek is sample accurate, the returned samples
+	/// match the ones obtained when decoding the entire stream, and
+	/// adjacent regions can be concatenated without gaps or clicks.
+	///
+	/// Afterwards, the reader is positioned somewhere after the region.
+	pub fn decode_region(&mut self, start_sample :u64, len :u64) -> Result<Vec<i16>, VorbisError> {
+		try!(self.seek_absgp(start_sample));
+		let channels = self.ident_hdr.audio_channels as u64;
+		let target_len = min(len.saturating_mul(channels), usize::MAX as u64) as usize;
+		let mut samples = Vec::new();
+		while samples.len() < target_len {
+			match try!(self.read_dec_packet_itl()) {
+				Some(pck) => samples.extend_from_slice(&pck),
+				None => break,
+			}
+		}
+		samples.truncate(target_len);
+		return Ok(samples);
+	}
+
+	/// Estimates the effective bit depth of the content
+	///
+	/// This is a heuristic meant for quality checks, like telling
+	/// apart real 16 bit masters from upsampled low resolution ones.
+	/// The result is approximate, and not comparable to the bit
+	/// depth of lossless formats.
+	///
+	/// The stream is sampled at 16 evenly spaced positions. At each,
+	/// a few packets are decoded and split into short windows. For each
+	/// window, the signal gets predicted from the two preceding samples
+	/// via least squares linear prediction, which cancels out tonal
+	/// content, and the remaining error is taken as the noise.
+	/// The median noise level over all windows is then compared to
+	/// the peak sample value, and converted into bits, the way it is
+	/// done for the quantization noise of uniform quantizers.
+	/// Windows of digital silence are ignored. If there is no noise
+	/// at all, or the stream is entirely silent, 32 or 0 is returned.
+	///
+	/// As this requires decoding, it is much more expensive than
+	/// the functions that only inspect the packet headers.
+	/// Afterwards, the reader is positioned at the start of the stream.
+	pub fn estimate_effective_bits(&mut self) -> Result<f32, VorbisError> {
+		const POSITIONS :u64 = 16;
+		const PACKETS_PER_POSITION :usize = 3;
+		const WINDOW_LEN :usize = 256;
+
+		let total_samples = try!(self.total_samples());
+		let mut peak = 0.0f32;
+		let mut noise_levels = Vec::new();
+		for i in 0 .. POSITIONS {
+			let absgp = try!(self.granule_for_sample(total_samples / POSITIONS * i));
+			try!(self.seek_absgp_pg(absgp));
+			for _ in 0 .. PACKETS_PER_POSITION {
+				let pck :Vec<Vec<f32>> = match try!(self.read_dec_packet_generic()) {
+					Some(p) => p,
+					None => break,
+				};
+				for ch in pck.iter() {
+					for window in ch.chunks(WINDOW_LEN) {
+						peak = window.iter().fold(peak, |peak, s| peak.max(s.abs()));
+						if window.len() < WINDOW_LEN || window.iter().all(|s| *s == 0.0) {
+							continue;
+						}
+						noise_levels.push(prediction_error_rms(window));
+					}
+				}
+			}
+		}
+		try!(self.seek_absgp_pg(0));
+
+		if noise_levels.is_empty() || peak == 0.0 {
+			return Ok(0.0);
+		}
+		noise_levels.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+		let noise = noise_levels[noise_levels.len() / 2];
+		if noise == 0.0 {
+			return Ok(32.0);
+		}
+		// With a step size of 2^(1 - bits) for a full scale of 1,
+		// the noise of a uniform quantizer is step / sqrt(12).
+		let bits = 1.0 + (peak as f64 / (noise * 12.0f64.sqrt())).log2();
+		return Ok(bits.clamp(0.0, 32.0) as f32);
+	}
+
+	/// Determines the peak sample value of the stream
+	///
+	/// The whole stream gets decoded, and the maximum absolute
+	/// value of all samples in floating point format is returned.
+	/// Unlike the `REPLAYGAIN_*_PEAK` comments, which may be absent
+	/// or outdated, this reflects what the decoder actually outputs,
+	/// so it is suitable for computing a normalization gain.
+	/// Values above 1.0 mean that integer output would clip.
+	///
+	/// Afterwards, the reader is positioned at the start of the stream.
+	pub fn scan_peak(&mut self) -> Result<f32, VorbisError> {
+		try!(self.seek_absgp_pg(0));
+		let mut peak = 0.0f32;
+		while let Some(pck) = try!(self.read_dec_packet_generic::<Vec<Vec<f32>>>()) {
+			for ch in pck.iter() {
+				peak = ch.iter().fold(peak, |peak, s| peak.max(s.abs()));
+			}
+		}
+		try!(self.seek_absgp_pg(0));
+		return Ok(peak);
+	}
+}
+
+/// Opens the stereo test file, see test-data/README.md
+#[cfg(test)]
+fn open_sine_stereo() -> OggStreamReader<io::Cursor<&'static [u8]>> {
+	let data :&'static [u8] = include_bytes!("../test-data/sine-stereo.ogg");
+	OggStreamReader::new(io::Cursor::new(data)).unwrap()
+}
+
+/// Writes the packets of the stereo test file into a new file
+///
+/// `f` is called with the index and the data of each packet, and
+/// returns the packets to write in its place. They are all written
+/// with the page end flags and the absgp of the original packet.
+#[cfg(test)]
+fn remux_sine_stereo<F :FnMut(usize, Vec<u8>) -> Vec<Vec<u8>>>(mut f :F) -> Vec<u8> {
+	use ogg::{PacketWriter, PacketWriteEndInfo};
+	let data :&'static [u8] = include_bytes!("../test-data/sine-stereo.ogg");
+	let mut rdr = PacketReader::new(io::Cursor::new(data));
+	let mut wtr = PacketWriter::new(Vec::new());
+	let mut idx = 0;
+	while let Some(pck) = rdr.read_packet().unwrap() {
+		let info = if pck.last_in_stream() {
+			PacketWriteEndInfo::EndStream
+		} else if pck.last_in_page() {
+			PacketWriteEndInfo::EndPage
+		} else {
+			PacketWriteEndInfo::NormalPacket
+		};
+		let (serial, absgp) = (pck.stream_serial(), pck.absgp_page());
+		for pck_data in f(idx, pck.data) {
+			wtr.write_packet(pck_data.into_boxed_slice(), serial, info, absgp).unwrap();
+		}
+		idx += 1;
+	}
+	wtr.into_inner()
+}
+
+#[test]
+fn test_block_size_histogram() {
+	let mut all = Vec::new();
+	let mut rdr = open_sine_stereo();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		all.push(pck);
+	}
+
+	let mut rdr = open_sine_stereo();
+	assert_eq!(rdr.block_size_histogram().unwrap(), (22, 41));
+	// Stop in the middle of the second page,
+	// which contains packets four to seven
+	let mut decoded = Vec::new();
+	for _ in 0 .. 5 {
+		decoded.push(rdr.read_dec_packet_itl().unwrap().unwrap());
+	}
+	let absgp = rdr.get_last_absgp();
+	let (short_count, long_count) = rdr.block_size_histogram().unwrap();
+	assert_eq!(short_count + long_count, 63 - 5);
+	assert_eq!(rdr.get_last_absgp(), absgp);
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		decoded.push(pck);
+	}
+	assert_eq!(decoded, all);
+	assert_eq!(rdr.block_size_histogram().unwrap(), (0, 0));
+}
+
+#[test]
+fn test_seek_timestamp() {
+	// 0.5 seconds in the MPEG time base
+	let mut rdr = open_sine_stereo();
+	rdr.seek_timestamp(45_000, 90_000).unwrap();
+	let mut after_ts = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		after_ts.extend_from_slice(&pck);
+	}
+	let mut rdr = open_sine_stereo();
+	rdr.seek_time(0.5).unwrap();
+	let mut after_time = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		after_time.extend_from_slice(&pck);
+	}
+	assert_eq!(after_ts.len(), (44100 - 22050) * 2);
+	assert_eq!(after_ts, after_time);
+
+	match rdr.seek_timestamp(1, 0) {
+		Err(VorbisError::ZeroTimeBase) => (),
+		_ => panic!("Expected an error for a zero time base"),
+	}
+}
+
+#[test]
+fn test_read_dec_packet_lossy() {
+	let (all, _, _) = open_sine_stereo().decode_all_itl().unwrap();
+
+	// Flip the previous window flag of a long block that follows
+	// a long one, which makes the packet undecodable.
+	let corrupt = remux_sine_stereo(|idx, mut pck| {
+		// Audio packet 5, after the three header packets
+		if idx == 8 {
+			pck[0] ^= 0b100;
+		}
+		vec![pck]
+	});
+
+	let mut rdr = OggStreamReader::new(io::Cursor::new(&corrupt[..])).unwrap();
+	assert!(rdr.decode_all_itl().is_err());
+	let mut rdr = OggStreamReader::new(io::Cursor::new(&corrupt[..])).unwrap();
+	let mut lossy = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_lossy().unwrap() {
+		for i in 0 .. pck[0].len() {
+			lossy.push(pck[0][i]);
+			lossy.push(pck[1][i]);
+		}
+	}
+	assert_eq!(rdr.stats().recovered_errors, 1);
+	// The bad packet and the one after it are silent,
+	// but the stream keeps its length.
+	assert_eq!(lossy.len(), all.len());
+	let silent = lossy.iter().zip(all.iter()).filter(|&(l, a)| l != a).all(|(l, _)| *l == 0);
+	assert!(silent);
+}
+
+#[test]
+fn test_zero_length_packets() {
+	let (all, _, _) = open_sine_stereo().decode_all_itl().unwrap();
+
+	// Add a zero length packet in the middle of a page, and one
+	// on a page of its own, after the page ending with packet 10
+	let data = remux_sine_stereo(|idx, pck| match idx {
+		9 | 10 => vec![pck, Vec::new()],
+		_ => vec![pck],
+	});
+	let mut rdr = OggStreamReader::new(io::Cursor::new(&data)).unwrap();
+	let mut empty_packets = 0;
+	let mut samples = Vec::new();
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		if rdr.last_packet_empty() {
+			assert!(pck.is_empty());
+			empty_packets += 1;
+		}
+		samples.extend_from_slice(&pck);
+	}
+	assert_eq!(empty_packets, 2);
+	// The end of the stream is reached, and the
+	// packets around the empty ones are unaffected
+	assert_eq!(rdr.get_last_absgp(), Some(44100));
+	assert_eq!(samples, all);
+
+	// The decoding functions returning additional
+	// information skip them the same way
+	fn read_all<F>(data :&[u8], mut read :F) -> Vec<f32>
+			where F :FnMut(&mut OggStreamReader<io::Cursor<&[u8]>>) -> Option<Vec<Vec<f32>>> {
+		let mut rdr = OggStreamReader::new(io::Cursor::new(data)).unwrap();
+		let mut empty_packets = 0;
+		let mut samples = Vec::new();
+		while let Some(pck) = read(&mut rdr) {
+			if rdr.last_packet_empty() {
+				assert!(pck.iter().all(|ch| ch.is_empty()));
+				empty_packets += 1;
+			}
+			for (l, r) in pck[0].iter().zip(pck[1].iter()) {
+				samples.push(*l);
+				samples.push(*r);
+			}
+		}
+		assert_eq!(empty_packets, 2);
+		assert_eq!(rdr.get_last_absgp(), Some(44100));
+		samples
+	}
+	let all = read_all(&data, |rdr| rdr.read_dec_packet_generic().unwrap());
+	let full = read_all(&data, |rdr| rdr.read_dec_packet_full().unwrap()
+		.map(|pck| pck.samples));
+	assert_eq!(full, all);
+	let floor0_amp = read_all(&data, |rdr| rdr.read_dec_packet_with_floor0_amp().unwrap()
+		.map(|(samples, _)| samples));
+	assert_eq!(floor0_amp, all);
+	let detailed = read_all(&data, |rdr| rdr.read_dec_packet_detailed().unwrap()
+		.map(|pck| pck.samples));
+	assert_eq!(detailed, all);
+}
+
+#[test]
+fn test_packets_end_after_error() {
+	// Packet 5 is replaced by a packet with the header flag set
+	let data = remux_sine_stereo(|idx, pck| match idx {
+		5 => vec![vec![1]],
+		_ => vec![pck],
+	});
+	let mut rdr = OggStreamReader::new(io::Cursor::new(data)).unwrap();
+	let mut packets = rdr.packets();
+	assert!(packets.next().unwrap().is_ok());
+	assert!(packets.next().unwrap().is_ok());
+	assert!(packets.next().unwrap().is_err());
+	assert!(packets.next().is_none());
+	assert!(packets.next().is_none());
+}
+
+#[test]
+fn test_resync() {
+	let (all, _, _) = open_sine_stereo().decode_all_itl().unwrap();
+	let data :&'static [u8] = include_bytes!("../test-data/sine-stereo.ogg");
+	let mut data = data.to_vec();
+	// Corrupt the last byte of the fifth page,
+	// so that its checksum doesn't match
+	let page_starts = (0 .. data.len() - 4)
+		.filter(|&i| &data[i .. i + 4] == b"OggS")
+		.collect::<Vec<_>>();
+	data[page_starts[5] - 1] ^= 0xff;
+
+	let mut rdr = OggStreamReader::new(io::Cursor::new(data)).unwrap();
+	let mut samples = Vec::new();
+	let mut before_error = None;
+	loop {
+		match rdr.read_dec_packet_itl() {
+			Ok(Some(pck)) => samples.extend_from_slice(&pck),
+			Ok(None) => break,
+			Err(VorbisError::OggError(_)) => {
+				assert_eq!(before_error, None);
+				before_error = Some(samples.len());
+				assert!(rdr.resync().unwrap());
+			},
+			Err(e) => panic!("{:?}", e),
+		}
+	}
+	assert_eq!(rdr.stats().recovered_errors, 1);
+	// The samples before the corrupt page are unaffected, and the
+	// ones after it match the end of the stream, minus the packet
+	// needed to warm up the decoder again.
+	let before = before_error.unwrap();
+	let after = samples.len() - before;
+	assert!(before > 0 && after > 0);
+	assert!(samples.len() < all.len());
+	assert_eq!(&samples[.. before], &all[.. before]);
+	assert_eq!(&samples[before ..], &all[all.len() - after ..]);
+	assert_eq!(rdr.get_last_absgp(), Some(44100));
+}
+
+#[test]
+fn test_last_packet_was_silent() {
+	// A single zero byte is an audio packet using mode 0,
+	// the short block mode, with the floors of both
+	// channels unused. Put a few of them before the audio.
+	let data = remux_sine_stereo(|idx, pck| match idx {
+		3 => vec![vec![0]; 5].into_iter().chain(Some(pck)).collect(),
+		_ => vec![pck],
+	});
+	let mut rdr = OggStreamReader::new(io::Cursor::new(data)).unwrap();
+	assert!(!rdr.last_packet_was_silent());
+	let mut silent_packets = 0;
+	let mut sound_started = false;
+	while let Some(pck) = rdr.read_dec_packet().unwrap() {
+		if rdr.last_packet_was_silent() {
+			assert!(!sound_started);
+			// There is no previous sound
+			// that could overlap into the packet.
+			assert!(pck.iter().all(|ch| ch.iter().all(|s| *s == 0)));
+			silent_packets += 1;
+		} else {
+			sound_started = true;
+		}
+	}
+	assert_eq!(silent_packets, 5);
+	assert!(sound_started);
+
+	let mut rdr = open_sine_stereo();
+	while let Some(_) = rdr.read_dec_packet().unwrap() {
+		assert!(!rdr.last_packet_was_silent());
+	}
+}
+
+#[test]
+fn test_decode_region() {
+	let (all, _, _) = open_sine_stereo().decode_all_itl().unwrap();
+	let mut rdr = open_sine_stereo();
+	// Adjacent regions concatenate to the full decode
+	for &(start, len) in [(0, 100), (100, 5000), (20_000, 3000), (30_000, 7000)].iter() {
+		let first = rdr.decode_region(start, len).unwrap();
+		let second = rdr.decode_region(start + len, len).unwrap();
+		let region = &all[start as usize * 2 .. (start + 2 * len) as usize * 2];
+		assert_eq!([first, second].concat(), region);
+	}
+	// At the end of the stream, fewer samples are returned
+	assert_eq!(rdr.decode_region(44090, 100).unwrap(), &all[44090 * 2 ..]);
+	assert!(rdr.decode_region(50_000, 100).unwrap().is_empty());
+}
+
+#[test]
+fn test_decode_all_after_seek() {
+	let (all, rate, channels) = open_sine_stereo().decode_all_itl().unwrap();
+	assert_eq!((all.len(), rate, channels), (44100 * 2, 44100, 2));
+	let mut rdr = open_sine_stereo();
+	rdr.seek_absgp(22050).unwrap();
+	let (rest, _, _) = rdr.decode_all_itl().unwrap();
+	assert_eq!(rest, &all[22050 * 2 ..]);
+	assert!(rest.capacity() <= all.len());
+}
+
+#[test]
+fn test_seek_keeps_stats() {
+	let mut rdr = open_sine_stereo();
+	for _ in 0 .. 3 {
+		rdr.read_dec_packet_itl().unwrap();
+	}
+	let stats = rdr.stats();
+	// The packets decoded to find the position aren't counted
+	for &pos in [0, 100, 22050, 44000, 50_000].iter() {
+		rdr.seek_absgp(pos).unwrap();
+		assert_eq!(rdr.stats(), stats);
+		assert_eq!(rdr.total_trimmed_end(), 0);
+	}
+	rdr.seek_absgp(22050).unwrap();
+	while let Some(_) = rdr.read_dec_packet_itl().unwrap() {}
+	assert_eq!(rdr.stats().samples, stats.samples + 22050);
+	assert!(rdr.total_trimmed_end() > 0);
+}
+
+#[test]
+fn test_scan_keeps_position() {
+	// Stop in the middle of a page, as the
+	// file has four audio packets per page
+	let mut rdr = open_sine_stereo();
+	let mut rdr_ref = open_sine_stereo();
+	for _ in 0 .. 6 {
+		rdr.read_dec_packet_itl().unwrap();
+		rdr_ref.read_dec_packet_itl().unwrap();
+	}
+	assert_eq!(rdr.total_samples().unwrap(), 44100);
+	assert_eq!(rdr.packet_count().unwrap(), 63);
+	loop {
+		let pck = rdr.read_dec_packet_itl().unwrap();
+		assert_eq!(pck, rdr_ref.read_dec_packet_itl().unwrap());
+		if pck.is_none() {
+			break;
+		}
+	}
+}
+
+#[test]
+fn test_scan_reused_serial() {
+	// Both links have the same serial, so the scan
+	// must only look at the link that is being read
+	let data :&'static [u8] = include_bytes!("../test-data/sine-stereo.ogg");
+	let len = data.len() as u64;
+	let mut twice = data.to_vec();
+	twice.extend_from_slice(data);
+	let mut rdr = OggStreamReader::new(io::Cursor::new(twice)).unwrap();
+	assert_eq!(rdr.current_stream_byte_range().unwrap(), 0 .. len);
+	let serial = rdr.stream_serial();
+	let mut first_link = 0;
+	while rdr.stream_scan.is_some() {
+		let pck = rdr.read_dec_packet_itl().unwrap().unwrap();
+		first_link += pck.len() / 2;
+	}
+	assert_eq!(rdr.stream_serial(), serial);
+	assert_eq!(rdr.current_stream_byte_range().unwrap(), len .. 2 * len);
+	assert_eq!(rdr.total_samples().unwrap(), 44100);
+	let mut second_link = 0;
+	while let Some(pck) = rdr.read_dec_packet_itl().unwrap() {
+		second_link += pck.len() / 2;
+	}
+	assert_eq!(first_link + second_link, 2 * 44100);
+}
+
+/// Iterator over the decoded packets of an `OggStreamReader`
+///
+/// Obtained via `OggStreamReader::packets_generic`.
+pub struct PacketsGeneric<'a, T :Read + Seek + 'a, S :Samples> {
+	rdr :&'a mut OggStreamReader<T>,
+	ended :bool,
+	phantom :PhantomData<S>,
+}
+
+impl<'a, T :Read + Seek + 'a, S :Samples> Iterator for PacketsGeneric<'a, T, S> {
+	type Item = Result<S, VorbisError>;
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.ended {
+			return None;
+		}
+		match self.rdr.read_dec_packet_generic() {
+			Ok(Some(pck)) => Some(Ok(pck)),
+			Ok(None) => {
+				self.ended = true;
+				None
+			},
+			Err(e) => {
+				self.ended = true;
+				Some(Err(e))
+			},
+		}
+	}
+}
+
+impl<'a, T :Read + Seek + 'a, S :Samples> FusedIterator for PacketsGeneric<'a, T, S> {}
+
+/// Iterator over the decoded packets of an `OggStreamReader`
+///
+/// Obtained via `OggStreamReader::packets`.
+pub struct Packets<'a, T :Read + Seek + 'a> {
+	inner :PacketsGeneric<'a, T, Vec<Vec<i16>>>,
+}
+
+impl<'a, T :Read + Seek + 'a> Iterator for Packets<'a, T> {
+	type Item = Result<Vec<Vec<i16>>, VorbisError>;
+	fn next(&mut self) -> Option<Self::Item> {
+		self.inner.next()
+	}
+}
+
+impl<'a, T :Read + Seek + 'a> FusedIterator for Packets<'a, T> {}
+
+/// Iterator over the interleaved decoded packets of an `OggStreamReader`
+///
+/// Obtained via `OggStreamReader::packets_itl`.
+pub struct PacketsItl<'a, T :Read + Seek + 'a> {
+	inner :PacketsGeneric<'a, T, InterleavedSamples<i16>>,
+}
+
+impl<'a, T :Read + Seek + 'a> Iterator for PacketsItl<'a, T> {
+	type Item = Result<Vec<i16>, VorbisError>;
+	fn next(&mut self) -> Option<Self::Item> {
+		self.inner.next().map(|r| r.map(|pck| pck.samples))
+	}
+}
+
+impl<'a, T :Read + Seek + 'a> FusedIterator for PacketsItl<'a, T> {}
+
+/// Reader over the decoded samples of an `OggStreamReader`, as bytes
+///
+/// Yields the interleaved samples as 16 bit little endian integers,
+/// decoding the packets as the bytes are requested. This allows to
+/// pass the samples to APIs that take `Read` implementations, e.g.
+/// to write them into a file via `std::io::copy`.
+///
+/// Decoding errors are returned as `io::Error`. Errors of the
+/// underlying reader are passed on as they are, all other errors
+/// have the `InvalidData` kind, and wrap the `VorbisError`.
+/// Chained files are decoded in their entirety, so the channel
+/// count might change in the middle of the bytes.
+///
+/// Obtained via `OggStreamReader::into_pcm_reader`.
+pub struct PcmReader<T :Read + Seek> {
+	rdr :OggStreamReader<T>,
+	/// The bytes of the last decoded packet
+	buffer :Vec<u8>,
+	/// Position of the next byte to return inside `buffer`
+	pos :usize,
+}
+
+impl<T :Read + Seek> PcmReader<T> {
+	/// Returns a reference to the underlying `OggStreamReader`
+	pub fn get_ref(&self) -> &OggStreamReader<T> {
+		&self.rdr
+	}
+	/// Returns the underlying `OggStreamReader`
+	///
+	/// Bytes that have been decoded, but not read yet, are lost.
+	pub fn into_inner(self) -> OggStreamReader<T> {
+		self.rdr
+	}
+}
+
+impl<T :Read + Seek> Read for PcmReader<T> {
+	fn read(&mut self, buf :&mut [u8]) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		while self.pos == self.buffer.len() {
+			self.buffer.clear();
+			self.pos = 0;
+			self.buffer = match self.rdr.read_dec_packet_bytes(PcmByteFormat::I16Le) {
+				Ok(Some(bytes)) => bytes,
+				Ok(None) => return Ok(0),
+				Err(VorbisError::OggError(OggReadError::ReadError(e))) => return Err(e),
+				Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+			};
+		}
+		let len = min(buf.len(), self.buffer.len() - self.pos);
+		buf[.. len].copy_from_slice(&self.buffer[self.pos ..][.. len]);
+		self.pos += len;
+		return Ok(len);
+	}
+}
+
+/// Returns the RMS of the error of a second order linear
+/// prediction of the given samples
+///
+/// The prediction coefficients are determined for the
+/// given samples via least squares.
+fn prediction_error_rms(samples :&[f32]) -> f64 {
+	let x = |i :usize| samples[i] as f64;
+	// Entries of the normal equations
+	let (mut r11, mut r12, mut r22, mut r01, mut r02) = (0.0, 0.0, 0.0, 0.0, 0.0);
+	for n in 2 .. samples.len() {
+		r11 += x(n - 1) * x(n - 1);
+		r12 += x(n - 1) * x(n - 2);
+		r22 += x(n - 2) * x(n - 2);
+		r01 += x(n) * x(n - 1);
+		r02 += x(n) * x(n - 2);
+	}
+	let det = r11 * r22 - r12 * r12;
+	// The determinant is zero if the samples are all zero,
+	// or if they are exactly geometric
+	let (a1, a2) = if det > 1e-12 * r11 * r22 {
+		((r01 * r22 - r02 * r12) / det, (r02 * r11 - r01 * r12) / det)
+	} else {
+		(0.0, 0.0)
+	};
+	let mut err_sum = 0.0;
+	for n in 2 .. samples.len() {
+		let err = x(n) - a1 * x(n - 1) - a2 * x(n - 2);
+		err_sum += err * err;
+	}
+	return (err_sum / (samples.len() - 2) as f64).sqrt();
+}
+
+#[test]
+fn test_prediction_error_rms() {
+	// Sines are predicted perfectly
+	let sine = (0 .. 256)
+		.map(|i| (i as f32 * 0.1).sin() * 0.8)
+		.collect::<Vec<f32>>();
+	assert!(prediction_error_rms(&sine) < 1e-6);
+	// Alternating noise is not
+	let noise = (0 .. 256)
+		.map(|i| ((i * 7919) % 263) as f32 / 263.0 - 0.5)
+		.collect::<Vec<f32>>();
+	assert!(prediction_error_rms(&noise) > 0.1);
+}
+
+#[test]
+fn test_estimate_effective_bits_start_absgp() {
+	use ogg::{PacketWriter, PacketWriteEndInfo};
+	let bits = open_sine_stereo().estimate_effective_bits().unwrap();
+
+	// Move the stream to a nonzero starting granule position,
+	// which must not change the positions that are looked at
+	let data :&'static [u8] = include_bytes!("../test-data/sine-stereo.ogg");
+	let mut rdr = PacketReader::new(io::Cursor::new(data));
+	let mut wtr = PacketWriter::new(Vec::new());
+	while let Some(pck) = rdr.read_packet().unwrap() {
+		let info = if pck.last_in_stream() {
+			PacketWriteEndInfo::EndStream
+		} else if pck.last_in_page() {
+			PacketWriteEndInfo::EndPage
+		} else {
+			PacketWriteEndInfo::NormalPacket
+		};
+		let absgp = match pck.absgp_page() {
+			0 => 0,
+			absgp => absgp + 10_000_000,
+		};
+		let serial = pck.stream_serial();
+		wtr.write_packet(pck.data.into_boxed_slice(), serial, info, absgp).unwrap();
 	}
+	let mut rdr = OggStreamReader::new(io::Cursor::new(wtr.into_inner())).unwrap();
+	assert_eq!(rdr.granule_for_sample(0).unwrap(), 10_000_000);
+	assert_eq!(rdr.estimate_effective_bits().unwrap(), bits);
 }
 
 #[cfg(feature = "async_ogg")]
@@ -374,7 +2766,7 @@ pub mod async_api {
 			}
 			if self.ident_hdr.is_none() {
 				let pck = rd_pck!();
-				self.ident_hdr = Some(try!(read_header_ident(&pck.data)));
+				self.ident_hdr = Some(try!(read_header_ident_checked(&pck.data)));
 			}
 			if self.comment_hdr.is_none() {
 				let pck = rd_pck!();
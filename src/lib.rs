@@ -29,6 +29,10 @@ extern crate ogg;
 extern crate futures;
 #[cfg(feature = "async_ogg")]
 extern crate tokio_io;
+#[cfg(feature = "rodio")]
+extern crate rodio;
+#[cfg(feature = "symphonia")]
+extern crate symphonia_core;
 
 macro_rules! try {
 	($expr:expr) => (match $expr {
@@ -100,31 +104,110 @@ mod imdct;
 #[cfg(test)]
 mod imdct_test;
 pub mod audio;
-mod bitpacking;
+pub mod bitpacking;
 #[cfg(feature = "ogg")]
 pub mod inside_ogg;
+#[cfg(feature = "ogg")]
+pub mod fuzz;
+#[cfg(feature = "ogg")]
+pub mod source;
+#[cfg(feature = "symphonia")]
+pub mod symphonia;
 pub mod samples;
 
 #[cfg(feature = "ogg")]
 #[doc(no_inline)]
 pub use ogg::OggReadError;
 
+pub use bitpacking::BitpackCursor;
+
 #[cfg(cargo_c)]
 mod capi;
 
 #[cfg(cargo_c)]
 pub use capi::*;
 
+/// The version of lewton, as specified in its `Cargo.toml`
+pub const VERSION :&str = env!("CARGO_PKG_VERSION");
+
+/// The optional cargo features lewton was compiled with
+///
+/// Obtained via the `features` function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+	/// Whether the `ogg` feature is enabled
+	pub ogg :bool,
+	/// Whether the `async_ogg` feature is enabled
+	pub async_ogg :bool,
+	/// Whether the `f64_intermediate` feature is enabled
+	pub f64_intermediate :bool,
+	/// Whether the `profiling` feature is enabled
+	pub profiling :bool,
+	/// Whether the `symphonia` feature is enabled
+	pub symphonia :bool,
+	/// Whether the `rodio` feature is enabled
+	pub rodio :bool,
+}
+
+/// Returns the optional cargo features lewton was compiled with
+///
+/// Useful to find out which build of lewton is being used.
+pub fn features() -> Features {
+	Features {
+		ogg : cfg!(feature = "ogg"),
+		async_ogg : cfg!(feature = "async_ogg"),
+		f64_intermediate : cfg!(feature = "f64_intermediate"),
+		profiling : cfg!(feature = "profiling"),
+		symphonia : cfg!(feature = "symphonia"),
+		rodio : cfg!(feature = "rodio"),
+	}
+}
+
+#[test]
+fn test_version() {
+	assert!(!VERSION.is_empty());
+	assert_eq!(VERSION, env!("CARGO_PKG_VERSION"));
+	assert_eq!(features().ogg, cfg!(feature = "ogg"));
+	assert_eq!(features().symphonia, cfg!(feature = "symphonia"));
+	assert_eq!(features().rodio, cfg!(feature = "rodio"));
+}
+
 /// Errors that can occur during decoding
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum VorbisError {
 	BadAudio(audio::AudioReadError),
 	BadHeader(header::HeaderReadError),
 	#[cfg(feature = "ogg")]
 	OggError(OggReadError),
+	/// The stream is not a vorbis stream, but of the codec with the given name
+	///
+	/// Returned when reading the headers of Ogg streams of other
+	/// commonly encountered codecs, like Opus or FLAC.
+	WrongCodec(&'static str),
+	/// The number of per-channel values passed to a function
+	/// doesn't match the channel count of the stream
+	///
+	/// Contains the channel count of the stream.
+	ChannelCountMismatch(u8),
+	/// A time base of zero has been passed to a function
+	/// taking a timestamp, like `seek_timestamp`
+	ZeroTimeBase,
 }
 
-impl std::error::Error for VorbisError {}
+impl std::error::Error for VorbisError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			VorbisError::BadAudio(err) => Some(err),
+			VorbisError::BadHeader(err) => Some(err),
+			#[cfg(feature = "ogg")]
+			VorbisError::OggError(err) => Some(err),
+			VorbisError::WrongCodec(_) => None,
+			VorbisError::ChannelCountMismatch(_) => None,
+			VorbisError::ZeroTimeBase => None,
+		}
+	}
+}
 
 impl std::fmt::Display for VorbisError {
 	fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
@@ -133,6 +216,11 @@ impl std::fmt::Display for VorbisError {
 			VorbisError::BadHeader(_) => "Vorbis bitstream header decode problem",
 			#[cfg(feature = "ogg")]
 			VorbisError::OggError(_) => "Ogg decode problem",
+			VorbisError::WrongCodec(codec) => return write!(fmt,
+				"Stream is not vorbis but {}", codec),
+			VorbisError::ChannelCountMismatch(channels) => return write!(fmt,
+				"Expected values for {} channels", channels),
+			VorbisError::ZeroTimeBase => "Time base must not be zero",
 		})
 	}
 }
@@ -156,6 +244,55 @@ impl From<OggReadError> for VorbisError {
 	}
 }
 
+#[test]
+fn test_vorbis_error_source() {
+	use std::error::Error;
+	let err = VorbisError::from(audio::AudioReadError::EndOfPacket);
+	assert_eq!(err.source().unwrap().to_string(),
+		audio::AudioReadError::EndOfPacket.to_string());
+	let err = VorbisError::from(header::HeaderReadError::NotVorbisHeader);
+	assert_eq!(err.source().unwrap().to_string(),
+		header::HeaderReadError::NotVorbisHeader.to_string());
+	#[cfg(feature = "ogg")]
+	{
+		let err = VorbisError::from(OggReadError::NoCapturePatternFound);
+		assert!(err.source().is_some());
+	}
+	assert!(VorbisError::WrongCodec("Opus").source().is_none());
+	assert!(VorbisError::ChannelCountMismatch(2).source().is_none());
+	assert!(VorbisError::ZeroTimeBase.source().is_none());
+}
+
+/// An error together with the position inside the packet where it occured
+///
+/// Returned by the `_with_position` variants of the decoding functions,
+/// to help pinpointing the field of a malformed packet that
+/// caused the error. There is no conversion into `VorbisError`,
+/// as it would lose the position. If the position isn't needed,
+/// the `error` field can be converted instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithPosition<E> {
+	/// The error that occured
+	pub error :E,
+	/// The offset of the byte inside the packet
+	pub byte_offset :usize,
+	/// The offset of the bit inside that byte
+	pub bit_offset :u8,
+}
+
+impl<E :std::error::Error + 'static> std::error::Error for WithPosition<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+impl<E :std::fmt::Display> std::fmt::Display for WithPosition<E> {
+	fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+		write!(fmt, "{} (at byte {}, bit {})",
+			self.error, self.byte_offset, self.bit_offset)
+	}
+}
+
 fn ilog(val :u64) -> u8 {
 	64 - val.leading_zeros() as u8
 }
@@ -171,6 +308,27 @@ fn test_ilog() {
 	assert_eq!(ilog(7), 3);
 }
 
+/// Converts a number of samples (per channel) into a duration
+///
+/// Returns `None` for a sample rate of zero.
+#[cfg(feature = "ogg")]
+fn samples_to_duration(samples :u64, sample_rate :u32) -> Option<std::time::Duration> {
+	if sample_rate == 0 {
+		return None;
+	}
+	let rate = sample_rate as u64;
+	let nanos = (samples % rate) * 1_000_000_000 / rate;
+	Some(std::time::Duration::new(samples / rate, nanos as u32))
+}
+
+#[cfg(feature = "ogg")]
+#[test]
+fn test_samples_to_duration() {
+	assert_eq!(samples_to_duration(44100, 44100), Some(std::time::Duration::from_secs(1)));
+	assert_eq!(samples_to_duration(66150, 44100), Some(std::time::Duration::from_millis(1500)));
+	assert_eq!(samples_to_duration(1, 0), None);
+}
+
 fn bit_reverse(n :u32) -> u32 {
 	n.reverse_bits()
 }
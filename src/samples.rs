@@ -10,6 +10,8 @@
 Traits for sample formats
 */
 
+use header::ChannelLayout;
+
 /// Trait for a packet of multiple samples
 pub trait Samples {
 	fn num_samples(&self) -> usize;
@@ -62,13 +64,8 @@ impl<S :Sample> Samples for InterleavedSamples<S> {
 			// Because decoded_pck[0] doesn't work...
 			<Vec<Vec<S>> as Samples>::from_floats(floats).into_iter().next().unwrap()
 		} else {
-			let len = floats[0].len();
-			let mut samples = Vec::with_capacity(len * channel_count);
-			for i in 0 .. len {
-				for ref chan in floats.iter() {
-					samples.push(S::from_float(chan[i]));
-				}
-			}
+			let mut samples = Vec::new();
+			interleave_slices(&floats, &mut samples);
 			samples
 		};
 		Self {
@@ -78,6 +75,503 @@ impl<S :Sample> Samples for InterleavedSamples<S> {
 	}
 }
 
+/// A packet of multi-channel samples, stored in boxed slices
+///
+/// Unlike a `Vec`, a boxed slice has no capacity,
+/// only a pointer and a length. This makes it easier to
+/// hand off ownership of the samples over FFI boundaries.
+pub struct BoxedSamples<S :Sample> {
+	/// The samples, one boxed slice per channel
+	pub channels :Vec<Box<[S]>>,
+}
+
+impl<S :Sample> BoxedSamples<S> {
+	/// Converts the channels into raw pointers and lengths
+	///
+	/// Ownership of the samples is passed to the caller: to free them,
+	/// reconstruct each boxed slice from its pointer and length,
+	/// e.g. via `Box::from_raw(slice::from_raw_parts_mut(ptr, len))`.
+	pub fn into_raw_parts(self) -> Vec<(*mut S, usize)> {
+		self.channels.into_iter()
+			.map(|ch| {
+				let len = ch.len();
+				(Box::into_raw(ch) as *mut S, len)
+			})
+			.collect()
+	}
+}
+
+impl<S :Sample> Samples for BoxedSamples<S> {
+	fn num_samples(&self) -> usize {
+		self.channels[0].len()
+	}
+	fn truncate(&mut self, limit :usize) {
+		for ch in self.channels.iter_mut() {
+			if limit < ch.len() {
+				let mut v = ::std::mem::replace(ch, Vec::new().into_boxed_slice()).into_vec();
+				v.truncate(limit);
+				*ch = v.into_boxed_slice();
+			}
+		}
+	}
+	fn from_floats(floats :Vec<Vec<f32>>) -> Self {
+		let channels = <Vec<Vec<S>> as Samples>::from_floats(floats).into_iter()
+			.map(|ch| ch.into_boxed_slice())
+			.collect();
+		BoxedSamples {
+			channels,
+		}
+	}
+}
+
+/// A packet that only records its sample count
+///
+/// Decoding to `NullSamples` runs the entire decoding
+/// pipeline, but discards the samples instead of
+/// converting them. This is useful to validate streams.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NullSamples {
+	pub num_samples :usize,
+}
+
+impl Samples for NullSamples {
+	fn num_samples(&self) -> usize {
+		self.num_samples
+	}
+	fn truncate(&mut self, limit :usize) {
+		self.num_samples = self.num_samples.min(limit);
+	}
+	fn from_floats(floats :Vec<Vec<f32>>) -> Self {
+		NullSamples {
+			num_samples : floats.first().map_or(0, |ch| ch.len()),
+		}
+	}
+}
+
+/// Interleaves the given planar channels into `out`
+///
+/// The channels can be slices, or the `Vec`s of a decoded packet.
+/// The previous contents of `out` are discarded,
+/// while its capacity gets reused.
+///
+/// Panics if the channels have different lengths.
+pub fn interleave_slices<S :Sample, C :AsRef<[f32]>>(channels :&[C], out :&mut Vec<S>) {
+	out.clear();
+	let len = match channels.first() {
+		Some(ch) => ch.as_ref().len(),
+		None => return,
+	};
+	assert!(channels.iter().all(|ch| ch.as_ref().len() == len),
+		"All channels must have the same length");
+	out.reserve(len * channels.len());
+	for i in 0 .. len {
+		for chan in channels.iter() {
+			out.push(S::from_float(chan.as_ref()[i]));
+		}
+	}
+}
+
+#[test]
+fn test_interleave_slices() {
+	let channels :&[&[f32]] = &[&[1.0, 2.0], &[3.0, 4.0], &[5.0, 6.0]];
+	let mut out :Vec<f32> = vec![42.0; 10];
+	interleave_slices(channels, &mut out);
+	assert_eq!(out, [1.0, 3.0, 5.0, 2.0, 4.0, 6.0]);
+
+	let mut out :Vec<i16> = Vec::new();
+	interleave_slices(&[&[0.5, -1.0][..]], &mut out);
+	assert_eq!(out, [16384, -32768]);
+
+	let channels = vec![vec![1.0, 2.0], vec![3.0, 4.0]];
+	let mut out :Vec<f32> = Vec::new();
+	interleave_slices(&channels, &mut out);
+	assert_eq!(out, [1.0, 3.0, 2.0, 4.0]);
+}
+
+/// Interleaves a compile-time known number of planar channels into `out`
+///
+/// Like `interleave_slices`, but as the channel count is known
+/// to the compiler, it can unroll the loop over the channels.
+///
+/// Panics if the channels have different lengths.
+pub fn interleave_fixed<S :Sample, const CH :usize>(channels :&[&[f32]; CH], out :&mut Vec<S>) {
+	out.clear();
+	let len = match channels.first() {
+		Some(ch) => ch.len(),
+		None => return,
+	};
+	assert!(channels.iter().all(|ch| ch.len() == len),
+		"All channels must have the same length");
+	out.reserve(len * CH);
+	for i in 0 .. len {
+		for chan in channels.iter() {
+			out.push(S::from_float(chan[i]));
+		}
+	}
+}
+
+#[test]
+fn test_interleave_fixed() {
+	let channels :[&[f32]; 3] = [&[1.0, 2.0], &[3.0, 4.0], &[5.0, 6.0]];
+	let mut out_fixed :Vec<i16> = Vec::new();
+	interleave_fixed(&channels, &mut out_fixed);
+	let mut out :Vec<i16> = Vec::new();
+	interleave_slices(&channels, &mut out);
+	assert_eq!(out_fixed, out);
+}
+
+/// Converts the given samples to `i16`, passing samples that
+/// would clip to the given callback first
+///
+/// For samples outside of `[-1.0, 1.0]`, `clip` is invoked, and its
+/// return value is converted instead. This allows implementing
+/// soft clipping or limiting, or just getting notified about clipping.
+/// Any values the callback returns that are still outside of the
+/// range are clamped, like the usual conversion does.
+/// With `hard_clip` as callback, the result is the same as with
+/// the usual conversion.
+///
+/// The previous contents of `out` are discarded,
+/// while its capacity gets reused.
+pub fn convert_to_i16_with_clip<F :FnMut(f32) -> f32>(samples :&[f32],
+		out :&mut Vec<i16>, mut clip :F) {
+	out.clear();
+	out.reserve(samples.len());
+	for s in samples.iter() {
+		let s = if *s < -1.0 || *s > 1.0 {
+			clip(*s)
+		} else {
+			*s
+		};
+		out.push(i16::from_float(s));
+	}
+}
+
+/// Clipping callback for `convert_to_i16_with_clip` that
+/// leaves the clamping to the conversion
+pub fn hard_clip(sample :f32) -> f32 {
+	sample
+}
+
+#[test]
+fn test_convert_to_i16_with_clip() {
+	let samples = [0.0, 0.5, -1.0, 1.5, -2.0, 1.0];
+	let mut out = Vec::new();
+	convert_to_i16_with_clip(&samples, &mut out, hard_clip);
+	let expected = samples.iter().map(|s| i16::from_float(*s)).collect::<Vec<_>>();
+	assert_eq!(out, expected);
+
+	// A limiter that halves the excess over full scale
+	let mut clipped = Vec::new();
+	convert_to_i16_with_clip(&samples, &mut out, |s| {
+		clipped.push(s);
+		s.signum() * (1.0 + (s.abs() - 1.0) / 2.0) / 1.5
+	});
+	assert_eq!(clipped, [1.5, -2.0]);
+	assert_eq!(&out[.. 3], &expected[.. 3]);
+	assert_eq!(out[3], i16::from_float(1.25 / 1.5));
+	assert_eq!(out[4], -32768);
+	assert_eq!(out[5], expected[5]);
+}
+
+/// Dither to apply when converting samples to `i16`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dither {
+	/// No dither, the samples are converted like `Sample::from_float` does it
+	None,
+	/// Triangular probability density function dither,
+	/// with a peak amplitude of one least significant bit,
+	/// added before rounding to the nearest value
+	Triangular,
+}
+
+/// State of the pseudo random number generator used for dithering
+///
+/// Keep one instance per stream, so that the noise
+/// continues over packet boundaries.
+#[derive(Debug, Clone)]
+pub struct DitherState {
+	// xorshift32 state, never zero
+	state :u32,
+}
+
+impl DitherState {
+	pub fn new() -> Self {
+		DitherState { state : 0x9e3779b9 }
+	}
+	/// Returns a uniformly distributed value in `[0.0, 1.0)`
+	fn next_uniform(&mut self) -> f32 {
+		let mut x = self.state;
+		x ^= x << 13;
+		x ^= x >> 17;
+		x ^= x << 5;
+		self.state = x;
+		(x >> 8) as f32 / (1u32 << 24) as f32
+	}
+}
+
+impl Default for DitherState {
+	fn default() -> Self {
+		DitherState::new()
+	}
+}
+
+/// Converts the given samples to `i16`, applying the given dither
+///
+/// With `Dither::None`, the result is the same as with the usual
+/// conversion, which is what libvorbis comparisons need.
+/// Dithering trades the distortion caused by the quantization to
+/// 16 bits for a low level of noise, which is preferable for quiet
+/// material.
+///
+/// The previous contents of `out` are discarded,
+/// while its capacity gets reused.
+pub fn convert_to_i16_dithered(samples :&[f32], out :&mut Vec<i16>,
+		dither :Dither, state :&mut DitherState) {
+	out.clear();
+	out.reserve(samples.len());
+	match dither {
+		Dither::None => out.extend(samples.iter().map(|s| i16::from_float(*s))),
+		Dither::Triangular => for s in samples.iter() {
+			let noise = state.next_uniform() - state.next_uniform();
+			let v = (*s * 32768.0 + noise + 0.5).floor();
+			out.push(if v > 32767. {
+				32767
+			} else if v < -32768. {
+				-32768
+			} else {
+				v as i16
+			});
+		},
+	}
+}
+
+#[test]
+fn test_convert_to_i16_dithered() {
+	let samples = (0 .. 4096)
+		.map(|i| (i as f32 * 0.01).sin() * 0.001)
+		.collect::<Vec<f32>>();
+	let mut out = Vec::new();
+	let mut state = DitherState::new();
+	convert_to_i16_dithered(&samples, &mut out, Dither::None, &mut state);
+	let expected = samples.iter().map(|s| i16::from_float(*s)).collect::<Vec<_>>();
+	assert_eq!(out, expected);
+
+	convert_to_i16_dithered(&samples, &mut out, Dither::Triangular, &mut state);
+	// The noise stays within one LSB of the rounded value
+	for (s, d) in samples.iter().zip(out.iter()) {
+		assert!((*s * 32768.0 - *d as f32).abs() <= 1.5);
+	}
+	assert_ne!(out, expected);
+	// The state continues instead of repeating the noise
+	let first = out.clone();
+	convert_to_i16_dithered(&samples, &mut out, Dither::Triangular, &mut state);
+	assert_ne!(out, first);
+	// Full scale doesn't overflow
+	convert_to_i16_dithered(&[1.0, -1.0, 2.0], &mut out, Dither::Triangular, &mut state);
+	assert!(out[0] >= 32766 && out[1] <= -32767);
+	assert_eq!(out[2], 32767);
+}
+
+/// Downmixes the channels of a stream to stereo
+///
+/// Uses the coefficients commonly used for this purpose:
+/// the front left and right channels are passed through,
+/// the center and the side and rear channels are mixed in at -3 dB,
+/// and a rear center channel is mixed into both sides at -6 dB.
+/// The LFE channel is dropped. Mono input is copied to both sides.
+///
+/// For `Unknown` layouts, the first two channels are used as
+/// left and right, and all other channels are dropped.
+///
+/// The gains are not normalized, so loud input
+/// may exceed full scale after being mixed.
+pub fn downmix_to_stereo(channels :&[Vec<f32>], layout :ChannelLayout)
+		-> (Vec<f32>, Vec<f32>) {
+	use header::ChannelPosition::*;
+	const MINUS_3DB :f32 = ::std::f32::consts::FRAC_1_SQRT_2;
+	const MINUS_6DB :f32 = 0.5;
+	let len = channels.first().map(|ch| ch.len()).unwrap_or(0);
+	let mut left = vec![0.0; len];
+	let mut right = vec![0.0; len];
+	let mut mix = |ch :&[f32], gain_l :f32, gain_r :f32| {
+		for ((l, r), s) in left.iter_mut().zip(right.iter_mut()).zip(ch.iter()) {
+			*l += gain_l * s;
+			*r += gain_r * s;
+		}
+	};
+	match layout.positions() {
+		Some(positions) => {
+			for (ch, pos) in channels.iter().zip(positions.iter()) {
+				let (gain_l, gain_r) = match *pos {
+					Mono => (1.0, 1.0),
+					FrontLeft => (1.0, 0.0),
+					FrontRight => (0.0, 1.0),
+					FrontCenter => (MINUS_3DB, MINUS_3DB),
+					SideLeft | RearLeft => (MINUS_3DB, 0.0),
+					SideRight | RearRight => (0.0, MINUS_3DB),
+					RearCenter => (MINUS_6DB, MINUS_6DB),
+					Lfe => continue,
+				};
+				mix(ch, gain_l, gain_r);
+			}
+		},
+		None => match channels.len() {
+			0 => (),
+			1 => mix(&channels[0], 1.0, 1.0),
+			_ => {
+				mix(&channels[0], 1.0, 0.0);
+				mix(&channels[1], 0.0, 1.0);
+			},
+		},
+	}
+	(left, right)
+}
+
+#[test]
+fn test_downmix_to_stereo() {
+	let mono = vec![vec![0.5, -0.25]];
+	let (l, r) = downmix_to_stereo(&mono, ChannelLayout::from_channel_count(1));
+	assert_eq!(l, mono[0]);
+	assert_eq!(r, mono[0]);
+
+	let stereo = vec![vec![0.5, 0.25], vec![-0.5, 0.125]];
+	let (l, r) = downmix_to_stereo(&stereo, ChannelLayout::from_channel_count(2));
+	assert_eq!(l, stereo[0]);
+	assert_eq!(r, stereo[1]);
+
+	// 5.1: FL, FC, FR, RL, RR, LFE
+	let sr = ::std::f32::consts::FRAC_1_SQRT_2;
+	let surround = vec![vec![0.5], vec![0.25], vec![-0.5], vec![0.125], vec![0.0625], vec![1.0]];
+	let (l, r) = downmix_to_stereo(&surround, ChannelLayout::from_channel_count(6));
+	assert!((l[0] - (0.5 + 0.25 * sr + 0.125 * sr)).abs() < 1e-6);
+	assert!((r[0] - (-0.5 + 0.25 * sr + 0.0625 * sr)).abs() < 1e-6);
+
+	// 6.1 has a rear center channel at index 5
+	let mut six_one = vec![vec![0.0]; 7];
+	six_one[5] = vec![0.5];
+	let (l, r) = downmix_to_stereo(&six_one, ChannelLayout::from_channel_count(7));
+	assert_eq!((l[0], r[0]), (0.25, 0.25));
+
+	let (l, r) = downmix_to_stereo(&vec![vec![0.5]; 10], ChannelLayout::from_channel_count(10));
+	assert_eq!((l[0], r[0]), (0.5, 0.5));
+
+	let (l, r) = downmix_to_stereo(&[], ChannelLayout::Unknown(0));
+	assert!(l.is_empty() && r.is_empty());
+}
+
+/// Byte level format of PCM samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmByteFormat {
+	/// Signed 16 bit integers, little endian
+	I16Le,
+	/// Signed 16 bit integers, big endian
+	I16Be,
+	/// Signed 32 bit integers, little endian
+	I32Le,
+	/// Signed 32 bit integers, big endian
+	I32Be,
+	/// 32 bit floats, little endian
+	F32Le,
+	/// 32 bit floats, big endian
+	F32Be,
+}
+
+impl PcmByteFormat {
+	/// Returns the number of bytes a single sample takes up
+	pub fn bytes_per_sample(&self) -> usize {
+		match *self {
+			PcmByteFormat::I16Le | PcmByteFormat::I16Be => 2,
+			PcmByteFormat::I32Le | PcmByteFormat::I32Be |
+			PcmByteFormat::F32Le | PcmByteFormat::F32Be => 4,
+		}
+	}
+	/// Interleaves the given planar channels and appends
+	/// the samples to `out`, serialized in this format
+	///
+	/// Panics if the channels have different lengths.
+	pub fn write_interleaved(&self, channels :&[&[f32]], out :&mut Vec<u8>) {
+		match *self {
+			PcmByteFormat::I16Le => write_interleaved_le::<i16>(channels, out),
+			PcmByteFormat::I16Be => write_interleaved_be::<i16>(channels, out),
+			PcmByteFormat::I32Le => write_interleaved_le::<i32>(channels, out),
+			PcmByteFormat::I32Be => write_interleaved_be::<i32>(channels, out),
+			PcmByteFormat::F32Le => write_interleaved_le::<f32>(channels, out),
+			PcmByteFormat::F32Be => write_interleaved_be::<f32>(channels, out),
+		}
+	}
+}
+
+/// Interleaves the given planar channels and appends
+/// the samples to `out`, as little endian bytes
+///
+/// Panics if the channels have different lengths.
+pub fn write_interleaved_le<S :PcmSample>(channels :&[&[f32]], out :&mut Vec<u8>) {
+	write_interleaved(channels, out, S::write_le)
+}
+
+/// Interleaves the given planar channels and appends
+/// the samples to `out`, as big endian bytes
+///
+/// Panics if the channels have different lengths.
+pub fn write_interleaved_be<S :PcmSample>(channels :&[&[f32]], out :&mut Vec<u8>) {
+	write_interleaved(channels, out, S::write_be)
+}
+
+fn write_interleaved<S :PcmSample>(channels :&[&[f32]], out :&mut Vec<u8>,
+		write :fn(S, &mut Vec<u8>)) {
+	let len = match channels.first() {
+		Some(ch) => ch.len(),
+		None => return,
+	};
+	assert!(channels.iter().all(|ch| ch.len() == len),
+		"All channels must have the same length");
+	out.reserve(len * channels.len() * S::BYTES);
+	for i in 0 .. len {
+		for chan in channels.iter() {
+			write(S::from_float(chan[i]), out);
+		}
+	}
+}
+
+#[test]
+fn test_write_interleaved() {
+	let channels :&[&[f32]] = &[&[0.5, -1.0], &[0.25, 2.0]];
+	let mut out = Vec::new();
+	write_interleaved_be::<i16>(channels, &mut out);
+	assert_eq!(out, [0x40, 0x00, 0x20, 0x00, 0x80, 0x00, 0x7f, 0xff]);
+
+	let mut out = vec![42];
+	PcmByteFormat::F32Le.write_interleaved(&channels[.. 1], &mut out);
+	assert_eq!(out, [42, 0, 0, 0, 0x3f, 0, 0, 0x80, 0xbf]);
+}
+
+/// Trait for samples that can be serialized to bytes
+pub trait PcmSample :Sample {
+	/// The number of bytes of the serialized sample
+	const BYTES :usize;
+	fn write_le(self, out :&mut Vec<u8>);
+	fn write_be(self, out :&mut Vec<u8>);
+}
+
+macro_rules! impl_pcm_sample {
+	($t:ty, $bytes:expr) => {
+		impl PcmSample for $t {
+			const BYTES :usize = $bytes;
+			fn write_le(self, out :&mut Vec<u8>) {
+				out.extend_from_slice(&self.to_le_bytes());
+			}
+			fn write_be(self, out :&mut Vec<u8>) {
+				out.extend_from_slice(&self.to_be_bytes());
+			}
+		}
+	};
+}
+
+impl_pcm_sample!(i16, 2);
+impl_pcm_sample!(i32, 4);
+impl_pcm_sample!(f32, 4);
+
 /// Trait representing a single sample
 pub trait Sample {
 	fn from_float(fl :f32) -> Self;
@@ -89,6 +583,28 @@ impl Sample for f32 {
 	}
 }
 
+/// Samples in double precision
+///
+/// Note that the decoder still outputs single precision floats,
+/// so this doesn't provide more precision than `f32` does,
+/// it only spares the conversion for code that works with `f64`.
+impl Sample for f64 {
+	fn from_float(fl :f32) -> Self {
+		fl as f64
+	}
+}
+
+#[test]
+fn test_f64_samples() {
+	let floats = vec![vec![0.5f32, -1.0, 0.1], vec![0.25, 2.0, -0.1]];
+	let chans :Vec<Vec<f64>> = Samples::from_floats(floats.clone());
+	assert_eq!(chans[0], [0.5, -1.0, 0.1f32 as f64]);
+	assert_eq!(chans[1], [0.25, 2.0, -0.1f32 as f64]);
+	let itl :InterleavedSamples<f64> = Samples::from_floats(floats);
+	assert_eq!(itl.channel_count, 2);
+	assert_eq!(itl.samples, [0.5, 0.25, -1.0, 2.0, 0.1f32 as f64, -0.1f32 as f64]);
+}
+
 impl Sample for i16 {
 	fn from_float(fl :f32) -> Self {
 		let fl = fl * 32768.0;
@@ -101,3 +617,131 @@ impl Sample for i16 {
 		}
 	}
 }
+
+impl Sample for i32 {
+	fn from_float(fl :f32) -> Self {
+		let fl = fl as f64 * 2147483648.0;
+		if fl > 2147483647. {
+			2147483647
+		} else if fl < -2147483648. {
+			-2147483648
+		} else {
+			fl as i32
+		}
+	}
+}
+
+impl Sample for i8 {
+	fn from_float(fl :f32) -> Self {
+		let fl = fl * 128.0;
+		if fl > 127. {
+			127
+		} else if fl < -128. {
+			-128
+		} else {
+			fl as i8
+		}
+	}
+}
+
+/// A signed 24 bit sample
+///
+/// The value is stored in an `i32`, and is always
+/// within the range of 24 bit integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct I24(i32);
+
+impl I24 {
+	pub const MIN :I24 = I24(-(1 << 23));
+	pub const MAX :I24 = I24((1 << 23) - 1);
+
+	/// Creates the sample from the given value, saturating
+	/// if it is outside of the range of 24 bit integers
+	pub fn new(v :i32) -> Self {
+		I24(v.clamp(I24::MIN.0, I24::MAX.0))
+	}
+	/// Returns the value of the sample
+	pub fn get(self) -> i32 {
+		self.0
+	}
+	/// Returns the three bytes of the sample in little endian order
+	pub fn to_le_bytes(self) -> [u8; 3] {
+		let b = self.0.to_le_bytes();
+		[b[0], b[1], b[2]]
+	}
+	/// Returns the three bytes of the sample in big endian order
+	pub fn to_be_bytes(self) -> [u8; 3] {
+		let b = self.0.to_be_bytes();
+		[b[1], b[2], b[3]]
+	}
+	/// Creates the sample from three bytes in little endian order
+	pub fn from_le_bytes(b :[u8; 3]) -> Self {
+		// Fill the upper byte with the sign
+		I24(i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8)
+	}
+}
+
+impl Sample for I24 {
+	fn from_float(fl :f32) -> Self {
+		let fl = fl as f64 * 8388608.0;
+		if fl > 8388607. {
+			I24::MAX
+		} else if fl < -8388608. {
+			I24::MIN
+		} else {
+			I24(fl as i32)
+		}
+	}
+}
+
+impl_pcm_sample!(I24, 3);
+
+#[test]
+fn test_i24() {
+	assert_eq!(I24::from_float(1.0), I24::MAX);
+	assert_eq!(I24::from_float(-1.0), I24::MIN);
+	assert_eq!(I24::from_float(2.0), I24::MAX);
+	assert_eq!(I24::from_float(-2.0), I24::MIN);
+	assert_eq!(I24::from_float(0.5).get(), 1 << 22);
+	assert_eq!(I24::MAX.get(), 8388607);
+	assert_eq!(I24::MIN.get(), -8388608);
+	assert_eq!(I24::new(1 << 24), I24::MAX);
+	assert_eq!(I24::new(-(1 << 24)), I24::MIN);
+
+	assert_eq!(I24::MAX.to_le_bytes(), [0xff, 0xff, 0x7f]);
+	assert_eq!(I24::MIN.to_le_bytes(), [0x00, 0x00, 0x80]);
+	assert_eq!(I24::new(-1).to_le_bytes(), [0xff, 0xff, 0xff]);
+	assert_eq!(I24::new(0x123456).to_be_bytes(), [0x12, 0x34, 0x56]);
+	for v in [0, 1, -1, 0x123456, -0x123456, I24::MAX.get(), I24::MIN.get()].iter() {
+		let v = I24::new(*v);
+		assert_eq!(I24::from_le_bytes(v.to_le_bytes()), v);
+	}
+
+	let itl :InterleavedSamples<I24> = Samples::from_floats(vec![vec![1.0], vec![-0.5]]);
+	assert_eq!(itl.samples, [I24::MAX, I24(-(1 << 22))]);
+	let mut out = Vec::new();
+	write_interleaved_le::<I24>(&[&[1.0], &[-0.5]], &mut out);
+	assert_eq!(out, [0xff, 0xff, 0x7f, 0x00, 0x00, 0xc0]);
+}
+
+#[test]
+fn test_integer_samples_full_scale() {
+	assert_eq!(i8::from_float(1.0), 127);
+	assert_eq!(i8::from_float(-1.0), -128);
+	assert_eq!(i8::from_float(0.5), 64);
+	assert_eq!(i8::from_float(3.0), 127);
+	assert_eq!(i8::from_float(-3.0), -128);
+	assert_eq!(i8::from_float(0.0), 0);
+
+	assert_eq!(i16::from_float(1.0), 32767);
+	assert_eq!(i16::from_float(-1.0), -32768);
+
+	assert_eq!(i32::from_float(1.0), i32::MAX);
+	assert_eq!(i32::from_float(-1.0), i32::MIN);
+	assert_eq!(i32::from_float(0.5), 1 << 30);
+	assert_eq!(i32::from_float(3.0), i32::MAX);
+	assert_eq!(i32::from_float(-3.0), i32::MIN);
+
+	let itl :InterleavedSamples<i32> = Samples::from_floats(vec![vec![1.0], vec![-1.0]]);
+	assert_eq!(itl.samples, [i32::MAX, i32::MIN]);
+}
@@ -0,0 +1,147 @@
+// Vorbis decoder written in Rust
+//
+// Copyright (c) 2026 est31 <MTest31@outlook.com>
+// and contributors. All rights reserved.
+// Licensed under MIT license, or Apache 2 license,
+// at your option. Please see the LICENSE file
+// attached to this source distribution for details.
+
+/*!
+Sample-by-sample decoding, for use with playback libraries
+
+The `LewtonDecoder` struct in this module is an iterator over
+the interleaved `i16` samples of a stream. If the `rodio` feature
+is enabled, it also implements the `rodio::Source` trait, so that
+it can be passed to rodio directly:
+
+```ignore
+let decoder = lewton::source::LewtonDecoder::new(file)?;
+stream_handle.play_raw(decoder.convert_samples())?;
+```
+*/
+
+use std::io::{Read, Seek};
+use std::time::Duration;
+use inside_ogg::OggStreamReader;
+use {VorbisError, samples_to_duration};
+
+/// Iterator over the interleaved samples of an ogg/vorbis stream
+///
+/// Decoding errors end the iteration, as there is no way to
+/// report them from an iterator. Chained files are decoded
+/// as a whole: the channel count and sample rate
+/// can change at the boundaries of the links.
+pub struct LewtonDecoder<T: Read + Seek> {
+	rdr :OggStreamReader<T>,
+	/// The samples of the last decoded packet
+	buffer :Vec<i16>,
+	/// Position of the next sample to return inside `buffer`
+	pos :usize,
+	total_duration :Option<Duration>,
+}
+
+impl<T: Read + Seek> LewtonDecoder<T> {
+	/// Constructs a new decoder from a given implementation of `Read + Seek`
+	pub fn new(rdr :T) -> Result<Self, VorbisError> {
+		let rdr = try!(OggStreamReader::new(rdr));
+		return LewtonDecoder::from_stream_reader(rdr);
+	}
+	/// Constructs a new decoder from a given `OggStreamReader`
+	///
+	/// In order to obtain the total duration, the stream is scanned
+	/// through. Decoding continues at the position of the reader.
+	pub fn from_stream_reader(mut rdr :OggStreamReader<T>) -> Result<Self, VorbisError> {
+		let total_samples = try!(rdr.total_samples());
+		let total_duration = samples_to_duration(total_samples,
+			rdr.ident_hdr.audio_sample_rate);
+		let mut decoder = LewtonDecoder {
+			rdr,
+			buffer : Vec::new(),
+			pos : 0,
+			total_duration,
+		};
+		decoder.fill_buffer();
+		return Ok(decoder);
+	}
+	/// Decodes packets until one with samples is found,
+	/// or the end of the stream has been reached
+	fn fill_buffer(&mut self) {
+		self.pos = 0;
+		self.buffer.clear();
+		while self.buffer.is_empty() {
+			self.buffer = match self.rdr.read_dec_packet_itl() {
+				Ok(Some(pck)) => pck,
+				Ok(None) | Err(_) => return,
+			};
+		}
+	}
+	/// Returns the underlying `OggStreamReader`
+	pub fn into_inner(self) -> OggStreamReader<T> {
+		self.rdr
+	}
+	/// Returns the number of channels of the samples that
+	/// are currently returned
+	pub fn channels(&self) -> u16 {
+		self.rdr.ident_hdr.audio_channels as u16
+	}
+	/// Returns the sample rate of the samples that
+	/// are currently returned
+	pub fn sample_rate(&self) -> u32 {
+		self.rdr.ident_hdr.audio_sample_rate
+	}
+	/// Returns the total duration of the stream
+	///
+	/// For chained files, this is the duration of the first link.
+	pub fn total_duration(&self) -> Option<Duration> {
+		self.total_duration
+	}
+	/// Returns the number of samples that are left until the
+	/// channel count or sample rate might change
+	///
+	/// Returns `Some(0)` if the end of the stream has been reached.
+	pub fn current_frame_len(&self) -> Option<usize> {
+		Some(self.buffer.len() - self.pos)
+	}
+}
+
+impl<T: Read + Seek> Iterator for LewtonDecoder<T> {
+	type Item = i16;
+	fn next(&mut self) -> Option<i16> {
+		let s = match self.buffer.get(self.pos) {
+			Some(s) => *s,
+			None => return None,
+		};
+		self.pos += 1;
+		// Decode the next packet right away, so that
+		// current_frame_len and friends always refer to
+		// the samples that are returned next.
+		if self.pos == self.buffer.len() {
+			self.fill_buffer();
+		}
+		return Some(s);
+	}
+}
+
+#[cfg(feature = "rodio")]
+impl<T: Read + Seek> ::rodio::Source for LewtonDecoder<T> {
+	fn current_frame_len(&self) -> Option<usize> {
+		LewtonDecoder::current_frame_len(self)
+	}
+	fn channels(&self) -> u16 {
+		LewtonDecoder::channels(self)
+	}
+	fn sample_rate(&self) -> u32 {
+		LewtonDecoder::sample_rate(self)
+	}
+	fn total_duration(&self) -> Option<Duration> {
+		LewtonDecoder::total_duration(self)
+	}
+}
+
+#[cfg(feature = "rodio")]
+#[test]
+fn test_rodio_source() {
+	use std::io::Cursor;
+	fn assert_source<S: ::rodio::Source>() where S::Item: ::rodio::Sample {}
+	assert_source::<LewtonDecoder<Cursor<Vec<u8>>>>();
+}
@@ -0,0 +1,147 @@
+// Vorbis decoder written in Rust
+//
+// Copyright (c) 2026 est31 <MTest31@outlook.com>
+// and contributors. All rights reserved.
+// Licensed under MIT license, or Apache 2 license,
+// at your option. Please see the LICENSE file
+// attached to this source distribution for details.
+
+/*!
+Conversion of decoded packets into `symphonia` audio buffers
+
+This module is only available if the `symphonia` feature is enabled.
+
+The channels of a decoded packet are in the order the vorbis spec
+defines, while `symphonia` orders the planes of its buffers by
+the position of the channels in its `Channels` bit mask.
+The functions in this module take care of the reordering.
+
+```
+# extern crate lewton;
+# extern crate symphonia_core;
+use lewton::header::read_header_ident;
+use lewton::symphonia::to_audio_buffer;
+use symphonia_core::audio::Signal;
+
+# let ident_packet = [0x01, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73,
+#     0x00, 0x00, 0x00, 0x00, 0x02, 0x44, 0xac, 0x00, 0x00,
+#     0x00, 0x00, 0x00, 0x00, 0x80, 0xb5, 0x01, 0x00,
+#     0x00, 0x00, 0x00, 0x00, 0xb8, 0x01];
+let ident = read_header_ident(&ident_packet).unwrap();
+let decoded = vec![vec![0.5; 128], vec![-0.5; 128]];
+let buf = to_audio_buffer(&ident, &decoded).unwrap();
+assert_eq!(buf.spec().rate, 44100);
+assert_eq!(buf.frames(), 128);
+assert_eq!(buf.chan(1)[0], -0.5);
+```
+*/
+
+use symphonia_core::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+use header::{IdentHeader, ChannelPosition};
+
+fn position_channel(pos :ChannelPosition) -> Channels {
+	match pos {
+		// symphonia represents mono as a single front left channel
+		ChannelPosition::Mono => Channels::FRONT_LEFT,
+		ChannelPosition::FrontLeft => Channels::FRONT_LEFT,
+		ChannelPosition::FrontCenter => Channels::FRONT_CENTRE,
+		ChannelPosition::FrontRight => Channels::FRONT_RIGHT,
+		ChannelPosition::SideLeft => Channels::SIDE_LEFT,
+		ChannelPosition::SideRight => Channels::SIDE_RIGHT,
+		ChannelPosition::RearLeft => Channels::REAR_LEFT,
+		ChannelPosition::RearCenter => Channels::REAR_CENTRE,
+		ChannelPosition::RearRight => Channels::REAR_RIGHT,
+		ChannelPosition::Lfe => Channels::LFE1,
+	}
+}
+
+/// Returns the `symphonia` channel of each channel of the stream,
+/// in the order of the decoded channels
+///
+/// For streams with more than eight channels, the layout is
+/// application defined, and the channels are mapped to the first
+/// positions of the `Channels` bit mask. Returns `None` if there
+/// are more channels than `symphonia` can represent.
+pub fn stream_channels(ident :&IdentHeader) -> Option<Vec<Channels>> {
+	match ident.channel_layout().positions() {
+		Some(positions) => Some(positions.iter()
+			.map(|pos| position_channel(*pos))
+			.collect()),
+		None => (0 .. ident.audio_channels as u32)
+			.map(|i| 1u32.checked_shl(i).and_then(Channels::from_bits))
+			.collect(),
+	}
+}
+
+/// Returns the `SignalSpec` of the stream
+///
+/// Returns `None` if there are more channels than
+/// `symphonia` can represent.
+pub fn signal_spec(ident :&IdentHeader) -> Option<SignalSpec> {
+	stream_channels(ident).map(|channels| {
+		let mask = channels.iter().fold(Channels::empty(), |mask, ch| mask | *ch);
+		SignalSpec::new(ident.audio_sample_rate, mask)
+	})
+}
+
+/// Converts a decoded packet into a `symphonia` `AudioBuffer`
+///
+/// The packet is expected to have one `Vec` per channel, like the
+/// ones returned by `read_audio_packet_generic::<Vec<Vec<f32>>>`.
+/// The channels are reordered into the plane order of `symphonia`.
+///
+/// Returns `None` if the number of channels of the packet doesn't
+/// match the ident header, or if there are more channels than
+/// `symphonia` can represent.
+///
+/// Panics if the channels have different lengths.
+pub fn to_audio_buffer(ident :&IdentHeader, decoded :&[Vec<f32>]) -> Option<AudioBuffer<f32>> {
+	let (channels, spec) = match (stream_channels(ident), signal_spec(ident)) {
+		(Some(c), Some(s)) if c.len() == decoded.len() => (c, s),
+		_ => return None,
+	};
+	let frames = decoded.first().map_or(0, |ch| ch.len());
+	assert!(decoded.iter().all(|ch| ch.len() == frames),
+		"All channels must have the same length");
+	let mask = spec.channels.bits();
+	let mut buf = AudioBuffer::new(frames as u64, spec);
+	buf.render_reserved(Some(frames));
+	for (ch, samples) in channels.iter().zip(decoded.iter()) {
+		// The planes are ordered by the position of
+		// their channel in the bit mask.
+		let plane = (mask & (ch.bits() - 1)).count_ones() as usize;
+		buf.chan_mut(plane).copy_from_slice(samples);
+	}
+	return Some(buf);
+}
+
+#[test]
+fn test_to_audio_buffer_reorders() {
+	// Vorbis orders three channels left, center, right,
+	// symphonia orders them left, right, center.
+	let ident = ::header::test_ident_with_channels(3);
+	let decoded = vec![vec![1.0; 4], vec![2.0; 4], vec![3.0; 4]];
+	let buf = to_audio_buffer(&ident, &decoded).unwrap();
+	assert_eq!(buf.spec().channels, Channels::FRONT_LEFT
+		| Channels::FRONT_RIGHT | Channels::FRONT_CENTRE);
+	assert_eq!(buf.chan(0), &[1.0; 4]);
+	assert_eq!(buf.chan(1), &[3.0; 4]);
+	assert_eq!(buf.chan(2), &[2.0; 4]);
+
+	// Wrong channel count
+	assert!(to_audio_buffer(&ident, &decoded[.. 2]).is_none());
+}
+
+#[test]
+fn test_stream_channels() {
+	assert_eq!(stream_channels(&::header::test_ident_with_channels(1)),
+		Some(vec![Channels::FRONT_LEFT]));
+	// Application defined layouts take the first positions
+	let channels = stream_channels(&::header::test_ident_with_channels(9)).unwrap();
+	assert_eq!(channels.len(), 9);
+	assert_eq!(channels[0], Channels::FRONT_LEFT);
+	assert_eq!(channels[8], Channels::from_bits(1 << 8).unwrap());
+	// More channels than symphonia has
+	assert!(stream_channels(&::header::test_ident_with_channels(255)).is_none());
+	assert!(signal_spec(&::header::test_ident_with_channels(255)).is_none());
+}